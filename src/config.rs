@@ -0,0 +1,481 @@
+//! Settings of the server, read from environment variables at startup.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use log::warn;
+
+/// Default for the maximum number of options a single poll may hold
+const DEFAULT_MAX_POLL_OPTIONS: usize = 20;
+/// Default for the maximum number of open polls per room
+const DEFAULT_MAX_POLLS_PER_ROOM: usize = 10;
+/// Default for the longest duration a poll may be closed automatically after, in seconds
+const DEFAULT_MAX_POLL_DURATION_SECS: u64 = 24 * 60 * 60;
+/// Default for the maximum length of user and room names
+const DEFAULT_MAX_NAME_LEN: usize = 64;
+/// Default for the maximum length of instant messages
+const DEFAULT_MAX_CHAT_LEN: usize = 2000;
+/// Default for the longest lifetime an instant message may ask for, in seconds
+const DEFAULT_MAX_MESSAGE_TTL_SECS: u64 = 24 * 60 * 60;
+/// Default for the number of instant messages kept per room
+const DEFAULT_HISTORY_SIZE: usize = 50;
+/// Default for the number of events kept in the transcript of each room
+const DEFAULT_TRANSCRIPT_MAX: usize = 1000;
+/// Default for the number of messages a session may send per second
+const DEFAULT_MSG_RATE: usize = 20;
+/// Default for the number of messages waiting to be sent to a client before it's disconnected
+const DEFAULT_MAX_PENDING_MESSAGES: usize = 256;
+/// Default for how long a user has to wait between raising and lowering, in milliseconds
+const DEFAULT_HAND_COOLDOWN_MS: u64 = 500;
+/// Default for how far elevated users move ahead in the speaking order, in seconds
+const DEFAULT_ELEVATE_WEIGHT_SECS: u64 = 60;
+/// Default for the maximum size of a message sent by a client, in bytes
+const DEFAULT_MAX_MSG_BYTES: usize = 64 * 1024;
+/// Default for how often heartbeat pings are sent, in seconds
+const DEFAULT_HEARTBEAT_SECS: u64 = 5;
+/// Default for how long before lack of client response causes a timeout, in seconds
+const DEFAULT_CLIENT_TIMEOUT_SECS: u64 = 10;
+/// Default for how long a disconnected session can be resumed, in seconds
+const DEFAULT_RESUME_GRACE_SECS: u64 = 30;
+/// Default for how long clients are warned before the server shuts down, in seconds
+const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 5;
+/// Default for how long the readiness probe waits for the web socket servers, in milliseconds
+const DEFAULT_READY_TIMEOUT_MS: u64 = 1000;
+/// Default for the number of web socket server actors rooms are spread across
+const DEFAULT_SHARDS: usize = 1;
+/// Default for the directory static files are served from
+const DEFAULT_STATIC_DIR: &str = "static/";
+
+/// Settings shared by the web socket server and its sessions
+#[derive(Clone)]
+pub struct Config {
+    /// Maximum number of options a single poll may hold
+    pub max_poll_options: usize,
+    /// Maximum number of open polls per room, closed polls don't count
+    pub max_polls_per_room: usize,
+    /// Longest duration in seconds a poll may be closed automatically after, polls asking for
+    /// longer ones are refused
+    pub max_poll_duration_secs: u64,
+    /// Directory closed polls are archived to, no archiving if unset
+    pub poll_archive_dir: Option<PathBuf>,
+    /// URL the results of closed polls are posted to as JSON, nothing is posted if unset
+    pub poll_webhook_url: Option<String>,
+    /// Maximum number of participants per room, unlimited if unset
+    pub max_room_size: Option<usize>,
+    /// Maximum number of rooms that exist at the same time on all servers, unlimited if unset
+    pub max_rooms: Option<usize>,
+    /// Maximum number of rooms created by one client IP that exist at the same time,
+    /// unlimited if unset
+    pub max_rooms_per_ip: Option<usize>,
+    /// Maximum length of user and room names in characters
+    pub max_name_len: usize,
+    /// Maximum length of instant messages in characters, longer ones are refused
+    pub max_chat_len: usize,
+    /// Longest lifetime in seconds an instant message may ask for with `ttl_secs`, messages
+    /// asking for longer ones are refused
+    pub max_message_ttl_secs: u64,
+    /// Number of instant messages per room replayed to joining users and open for edits,
+    /// 0 disables history
+    pub history_size: usize,
+    /// Number of events per room kept for the transcript export, oldest ones are dropped first,
+    /// 0 disables transcripts
+    pub transcript_max: usize,
+    /// Number of messages a session may send per second, further ones are dropped
+    pub msg_rate: usize,
+    /// Maximum size of a message sent by a client in bytes, including continuation frames
+    pub max_msg_bytes: usize,
+    /// Number of messages waiting to be sent to a client before it's disconnected as too slow
+    pub max_pending_messages: usize,
+    /// How long a user has to wait after raising or lowering before doing so again
+    pub hand_cooldown: Duration,
+    /// How much earlier than they actually did elevated users are treated as having raised
+    /// their hand in the speaking order
+    pub elevate_weight: Duration,
+    /// How often heartbeat pings are sent
+    pub heartbeat_interval: Duration,
+    /// How long before lack of client response causes a timeout
+    pub client_timeout: Duration,
+    /// How long a disconnected session can be resumed with its token
+    pub resume_grace: Duration,
+    /// How long a client may not send any message before it's disconnected, never if unset
+    pub idle_timeout: Option<Duration>,
+    /// Window in which poll results updates of rapid votes are merged into one, none if 0
+    pub results_coalesce_window: Duration,
+    /// How long clients are warned before the server shuts down
+    pub shutdown_grace: Duration,
+    /// How long `/readyz` waits for every web socket server to respond before failing
+    pub ready_timeout: Duration,
+    /// Certificate and key to serve HTTPS with, plain HTTP if unset
+    pub tls: Option<TlsConfig>,
+    /// Origins allowed to make cross-origin requests, no CORS handling if unset
+    pub allowed_origins: Option<AllowedOrigins>,
+    /// Directory static files, like the test page, are served from
+    pub static_dir: PathBuf,
+    /// Token operators authenticate admin requests with, admin requests are refused if unset
+    pub admin_token: Option<String>,
+    /// Whether clients connect through a reverse proxy whose `X-Forwarded-For` and
+    /// `X-Forwarded-Proto` headers are trusted to name their address and scheme
+    pub trust_proxy: bool,
+    /// Whether a join with a name that's taken in the room replaces the session using it,
+    /// instead of being refused
+    pub supersede_duplicates: bool,
+    /// Number of web socket server actors rooms are spread across, at least 1
+    pub shards: usize,
+}
+
+/// Origins allowed by CORS
+#[derive(Clone)]
+pub enum AllowedOrigins {
+    /// any origin, `*`
+    Any,
+    /// only the listed origins, e.g. `https://example.com`
+    List(Vec<String>),
+}
+
+/// Files needed to serve HTTPS
+#[derive(Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain
+    pub cert: PathBuf,
+    /// PEM file with the private key
+    pub key: PathBuf,
+}
+
+impl Config {
+    /// Read all settings from the environment
+    pub fn from_env() -> Result<Config, String> {
+        Config::from_vars(|key| env::var_os(key))
+    }
+
+    /// Read all settings from variables looked up by name
+    ///
+    /// Unset variables fall back to their defaults, invalid values are reported and
+    /// replaced by their defaults as well. Settings that can't fall back to a default, like
+    /// an incomplete TLS configuration, return an error.
+    ///
+    /// # Arguments
+    ///
+    /// * `var` - returns the value of a variable, `None` if it's unset
+    pub fn from_vars(var: impl Fn(&str) -> Option<OsString>) -> Result<Config, String> {
+        let (heartbeat_interval, client_timeout) = validate_heartbeat(
+            parse_env(&var, "VIMEET_HEARTBEAT_SECS", DEFAULT_HEARTBEAT_SECS),
+            parse_env(
+                &var,
+                "VIMEET_CLIENT_TIMEOUT_SECS",
+                DEFAULT_CLIENT_TIMEOUT_SECS,
+            ),
+        );
+
+        let tls = parse_tls(
+            var("VIMEET_TLS_CERT").map(PathBuf::from),
+            var("VIMEET_TLS_KEY").map(PathBuf::from),
+        )?;
+
+        Ok(Config {
+            max_poll_options: parse_env(&var, "VIMEET_MAX_POLL_OPTIONS", DEFAULT_MAX_POLL_OPTIONS),
+            max_polls_per_room: parse_env(
+                &var,
+                "VIMEET_MAX_POLLS_PER_ROOM",
+                DEFAULT_MAX_POLLS_PER_ROOM,
+            ),
+            max_poll_duration_secs: parse_env(
+                &var,
+                "VIMEET_MAX_POLL_DURATION_SECS",
+                DEFAULT_MAX_POLL_DURATION_SECS,
+            ),
+            poll_archive_dir: var("VIMEET_POLL_ARCHIVE_DIR").map(PathBuf::from),
+            poll_webhook_url: var_text(&var, "VIMEET_POLL_WEBHOOK_URL")
+                .filter(|url| !url.is_empty()),
+            max_room_size: parse_env_opt(&var, "VIMEET_MAX_ROOM_SIZE"),
+            max_rooms: parse_env_opt(&var, "VIMEET_MAX_ROOMS"),
+            max_rooms_per_ip: parse_env_opt(&var, "VIMEET_MAX_ROOMS_PER_IP"),
+            max_name_len: parse_env(&var, "VIMEET_MAX_NAME_LEN", DEFAULT_MAX_NAME_LEN),
+            max_chat_len: parse_env(&var, "VIMEET_MAX_CHAT_LEN", DEFAULT_MAX_CHAT_LEN),
+            max_message_ttl_secs: parse_env(
+                &var,
+                "VIMEET_MAX_MESSAGE_TTL_SECS",
+                DEFAULT_MAX_MESSAGE_TTL_SECS,
+            ),
+            history_size: parse_env(&var, "VIMEET_HISTORY_SIZE", DEFAULT_HISTORY_SIZE),
+            transcript_max: parse_env(&var, "VIMEET_TRANSCRIPT_MAX", DEFAULT_TRANSCRIPT_MAX),
+            msg_rate: parse_env(&var, "VIMEET_MSG_RATE", DEFAULT_MSG_RATE),
+            max_msg_bytes: parse_env(&var, "VIMEET_MAX_MSG_BYTES", DEFAULT_MAX_MSG_BYTES),
+            max_pending_messages: parse_env(
+                &var,
+                "VIMEET_MAX_PENDING_MESSAGES",
+                DEFAULT_MAX_PENDING_MESSAGES,
+            ),
+            hand_cooldown: Duration::from_millis(parse_env(
+                &var,
+                "VIMEET_HAND_COOLDOWN_MS",
+                DEFAULT_HAND_COOLDOWN_MS,
+            )),
+            elevate_weight: Duration::from_secs(parse_env(
+                &var,
+                "VIMEET_ELEVATE_WEIGHT",
+                DEFAULT_ELEVATE_WEIGHT_SECS,
+            )),
+            heartbeat_interval,
+            client_timeout,
+            resume_grace: Duration::from_secs(parse_env(
+                &var,
+                "VIMEET_RESUME_GRACE_SECS",
+                DEFAULT_RESUME_GRACE_SECS,
+            )),
+            idle_timeout: parse_env_opt(&var, "VIMEET_IDLE_TIMEOUT_SECS").map(Duration::from_secs),
+            results_coalesce_window: Duration::from_millis(parse_env(
+                &var,
+                "VIMEET_RESULTS_COALESCE_MS",
+                0,
+            )),
+            shutdown_grace: Duration::from_secs(parse_env(
+                &var,
+                "VIMEET_SHUTDOWN_GRACE_SECS",
+                DEFAULT_SHUTDOWN_GRACE_SECS,
+            )),
+            ready_timeout: Duration::from_millis(parse_env(
+                &var,
+                "VIMEET_READY_TIMEOUT_MS",
+                DEFAULT_READY_TIMEOUT_MS,
+            )),
+            tls,
+            allowed_origins: var_text(&var, "VIMEET_ALLOWED_ORIGINS")
+                .map(|origins| parse_origins(&origins)),
+            static_dir: var("VIMEET_STATIC_DIR")
+                .map_or_else(|| PathBuf::from(DEFAULT_STATIC_DIR), PathBuf::from),
+            admin_token: var_text(&var, "VIMEET_ADMIN_TOKEN").filter(|token| !token.is_empty()),
+            trust_proxy: parse_env(&var, "VIMEET_TRUST_PROXY", false),
+            supersede_duplicates: parse_env(&var, "VIMEET_SUPERSEDE_DUPLICATES", false),
+            shards: validate_shards(parse_env(&var, "VIMEET_SHARDS", DEFAULT_SHARDS)),
+        })
+    }
+}
+
+/// Parse a comma-separated list of origins, `*` allows any origin
+fn parse_origins(origins: &str) -> AllowedOrigins {
+    let origins: Vec<String> = origins
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .map(String::from)
+        .collect();
+
+    if origins.iter().any(|origin| origin == "*") {
+        AllowedOrigins::Any
+    } else {
+        AllowedOrigins::List(origins)
+    }
+}
+
+/// Combine certificate and key file into a TLS configuration
+///
+/// TLS is only used if both files are given, giving just one of them is an error.
+///
+/// # Arguments
+///
+/// * `cert` - path of the certificate chain
+/// * `key` - path of the private key
+fn parse_tls(cert: Option<PathBuf>, key: Option<PathBuf>) -> Result<Option<TlsConfig>, String> {
+    match (cert, key) {
+        (Some(cert), Some(key)) => Ok(Some(TlsConfig { cert, key })),
+        (None, None) => Ok(None),
+        (Some(_), None) => Err("VIMEET_TLS_CERT is set, but VIMEET_TLS_KEY is missing".to_string()),
+        (None, Some(_)) => Err("VIMEET_TLS_KEY is set, but VIMEET_TLS_CERT is missing".to_string()),
+    }
+}
+
+/// Turn heartbeat interval and client timeout into durations
+///
+/// The timeout has to be greater than the interval, otherwise clients would time out before
+/// they could answer a ping. Invalid pairs are reported and replaced by the defaults.
+///
+/// # Arguments
+///
+/// * `heartbeat_secs` - heartbeat interval in seconds
+/// * `client_timeout_secs` - client timeout in seconds
+fn validate_heartbeat(heartbeat_secs: u64, client_timeout_secs: u64) -> (Duration, Duration) {
+    if heartbeat_secs == 0 || client_timeout_secs <= heartbeat_secs {
+        warn!(
+            heartbeat_secs = heartbeat_secs,
+            client_timeout_secs = client_timeout_secs;
+            "Invalid heartbeat interval with client timeout, using defaults"
+        );
+        return (
+            Duration::from_secs(DEFAULT_HEARTBEAT_SECS),
+            Duration::from_secs(DEFAULT_CLIENT_TIMEOUT_SECS),
+        );
+    }
+    (
+        Duration::from_secs(heartbeat_secs),
+        Duration::from_secs(client_timeout_secs),
+    )
+}
+
+/// Check the number of web socket server actors
+///
+/// At least one server is needed to host any room, 0 is reported and replaced by the default.
+///
+/// # Arguments
+///
+/// * `shards` - number of servers
+fn validate_shards(shards: usize) -> usize {
+    if shards == 0 {
+        warn!(shards = shards; "Invalid number of shards, using default");
+        return DEFAULT_SHARDS;
+    }
+    shards
+}
+
+/// Look up an environment variable holding text
+///
+/// Returns `None` if the variable is unset or isn't valid unicode. Paths are looked up with
+/// `var` directly, as they don't have to be unicode.
+///
+/// # Arguments
+///
+/// * `var` - looks up the value of a variable
+/// * `key` - name of the environment variable
+fn var_text(var: &impl Fn(&str) -> Option<OsString>, key: &str) -> Option<String> {
+    match var(key)?.into_string() {
+        Ok(value) => Some(value),
+        Err(value) => {
+            warn!(key = key, value = value.to_string_lossy().as_ref(); "Value isn't unicode, ignoring");
+            None
+        }
+    }
+}
+
+/// Parse an environment variable or return a default value
+///
+/// # Arguments
+///
+/// * `var` - looks up the value of a variable
+/// * `key` - name of the environment variable
+/// * `default` - value used if the variable is unset or can't be parsed
+fn parse_env<T: FromStr>(var: &impl Fn(&str) -> Option<OsString>, key: &str, default: T) -> T {
+    match var_text(var, key) {
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            warn!(key = key, value = value.as_str(); "Invalid value, using default");
+            default
+        }),
+        None => default,
+    }
+}
+
+/// Parse an optional environment variable
+///
+/// Returns `None` if the variable is unset or can't be parsed.
+///
+/// # Arguments
+///
+/// * `var` - looks up the value of a variable
+/// * `key` - name of the environment variable
+fn parse_env_opt<T: FromStr>(var: &impl Fn(&str) -> Option<OsString>, key: &str) -> Option<T> {
+    let value = var_text(var, key)?;
+    match value.parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            warn!(key = key, value = value.as_str(); "Invalid value, ignoring");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// settings with some variables set
+    fn config_with(vars: &[(&str, &str)]) -> Result<Config, String> {
+        Config::from_vars(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.into())
+        })
+    }
+
+    #[test]
+    fn heartbeat_has_to_be_shorter_than_the_timeout() {
+        let defaults = (
+            Duration::from_secs(DEFAULT_HEARTBEAT_SECS),
+            Duration::from_secs(DEFAULT_CLIENT_TIMEOUT_SECS),
+        );
+        assert_eq!(
+            validate_heartbeat(2, 5),
+            (Duration::from_secs(2), Duration::from_secs(5))
+        );
+        assert_eq!(validate_heartbeat(5, 5), defaults);
+        assert_eq!(validate_heartbeat(10, 5), defaults);
+        assert_eq!(validate_heartbeat(0, 5), defaults);
+    }
+
+    #[test]
+    fn invalid_heartbeat_variables_fall_back_to_the_defaults() {
+        let config = config_with(&[
+            ("VIMEET_HEARTBEAT_SECS", "20"),
+            ("VIMEET_CLIENT_TIMEOUT_SECS", "10"),
+        ])
+        .unwrap();
+        assert_eq!(
+            config.heartbeat_interval,
+            Duration::from_secs(DEFAULT_HEARTBEAT_SECS)
+        );
+        assert_eq!(
+            config.client_timeout,
+            Duration::from_secs(DEFAULT_CLIENT_TIMEOUT_SECS)
+        );
+    }
+
+    #[test]
+    fn tls_needs_both_certificate_and_key() {
+        let tls = config_with(&[
+            ("VIMEET_TLS_CERT", "cert.pem"),
+            ("VIMEET_TLS_KEY", "key.pem"),
+        ])
+        .unwrap()
+        .tls
+        .unwrap();
+        assert_eq!(tls.cert, PathBuf::from("cert.pem"));
+        assert_eq!(tls.key, PathBuf::from("key.pem"));
+        assert!(config_with(&[]).unwrap().tls.is_none());
+
+        let cert_only = config_with(&[("VIMEET_TLS_CERT", "cert.pem")]).err();
+        assert_eq!(
+            cert_only.as_deref(),
+            Some("VIMEET_TLS_CERT is set, but VIMEET_TLS_KEY is missing")
+        );
+        let key_only = config_with(&[("VIMEET_TLS_KEY", "key.pem")]).err();
+        assert_eq!(
+            key_only.as_deref(),
+            Some("VIMEET_TLS_KEY is set, but VIMEET_TLS_CERT is missing")
+        );
+    }
+
+    #[test]
+    fn static_dir_variable_overrides_the_default() {
+        let default = config_with(&[]).unwrap();
+        assert_eq!(default.static_dir, PathBuf::from(DEFAULT_STATIC_DIR));
+        let config = config_with(&[("VIMEET_STATIC_DIR", "/srv/vimeet/static")]).unwrap();
+        assert_eq!(config.static_dir, PathBuf::from("/srv/vimeet/static"));
+    }
+
+    #[test]
+    fn paths_do_not_have_to_be_unicode() {
+        use std::os::unix::ffi::OsStringExt;
+
+        let path = OsString::from_vec(b"/srv/vim\xe9et".to_vec());
+        let config = Config::from_vars(|key| match key {
+            "VIMEET_TLS_CERT" | "VIMEET_TLS_KEY" | "VIMEET_STATIC_DIR" => Some(path.clone()),
+            "VIMEET_ADMIN_TOKEN" => Some(path.clone()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(config.static_dir, PathBuf::from(&path));
+        assert_eq!(config.tls.unwrap().cert, PathBuf::from(&path));
+        // text settings that aren't unicode are ignored
+        assert!(config.admin_token.is_none());
+    }
+}
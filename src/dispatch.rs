@@ -0,0 +1,868 @@
+//! Turns the text messages of clients into what their session has to do about them.
+//!
+//! Parsing is kept apart from the session actor, so it doesn't need a web socket context and
+//! a message's effect only depends on its text and the session that received it.
+
+use std::collections::HashMap;
+
+use serde_json::Value as Arbitrary;
+
+use crate::messages;
+use crate::messages::inbound::GetMessageType;
+use crate::server;
+
+/// The session a message was received by
+pub struct Sender<'a> {
+    /// id of the user
+    pub id: usize,
+    /// name of the user
+    pub name: &'a str,
+    /// joined room, or the last joined room if the session left it
+    pub room: &'a str,
+    /// whether the session is currently a member of `room`
+    pub joined: bool,
+    /// maximum length of room and user names given when joining another room
+    pub max_name_len: usize,
+    /// longest duration in seconds a poll may be closed automatically after
+    pub max_poll_duration_secs: u64,
+    /// longest lifetime in seconds an instant message may ask for
+    pub max_message_ttl_secs: u64,
+}
+
+/// What a session has to do about a message of its client
+pub enum Dispatch {
+    /// leave the current room, if any, and join another one
+    JoinRoom {
+        room: String,
+        name: String,
+        password: Option<String>,
+    },
+    /// move to another room as one step
+    SwitchRoom {
+        room: String,
+        password: Option<String>,
+    },
+    /// leave the current room but keep the connection
+    Leave,
+    /// tell the client the server's clock, answered by the session itself
+    ServerTime {
+        ack_id: Option<Arbitrary>,
+    },
+    Poll(server::Poll),
+    PollOption(server::PollOption),
+    PollOptionRemove(server::PollOptionRemove),
+    Vote(server::PollVoteHelper),
+    PollClose(server::PollCloseHelper),
+    PollDelete(server::PollDelete),
+    PollRename(server::PollRename),
+    PollOptionsQuery(server::PollOptionsQuery),
+    Edit(server::Edit),
+    React(server::React),
+    Unreact(server::Unreact),
+    Elevate(server::Elevate),
+    Recede(server::Recede),
+    Kick(server::Kick),
+    Mute(server::Mute),
+    Unmute(server::Unmute),
+    TransferHost(server::TransferHost),
+    Instant(server::Instant),
+    Raise(server::Raise),
+    Lower(server::Lower),
+    Direct(server::Direct),
+    Typing(server::Typing),
+    LowerAll(server::LowerAll),
+    CloseAllPolls(server::CloseAllPolls),
+    SetRoomOption(server::SetRoomOption),
+    SetWeight(server::SetWeight),
+    HandQueueQuery(server::HandQueueQuery),
+}
+
+/// Why a message of a client is refused
+pub enum Refusal {
+    /// the message isn't JSON, has an unknown type or lacks parameters of its type
+    BadMessage,
+    /// the session has no room, it may only join one or sync its clock
+    NotInRoom { ack_id: Option<Arbitrary> },
+    /// the room or user name to join with is unusable
+    InvalidName {
+        reason: &'static str,
+        ack_id: Option<Arbitrary>,
+    },
+    /// a number in the message is larger than the server allows
+    OutOfRange {
+        object: &'static str,
+        description: String,
+        ack_id: Option<Arbitrary>,
+    },
+}
+
+impl Refusal {
+    /// message telling the client why its message is refused
+    pub fn to_message(&self) -> messages::outbound::Message {
+        let (object, description, ack_id) = match self {
+            Refusal::BadMessage => (
+                "bad_message",
+                "Message is malformed or has an unknown type",
+                None,
+            ),
+            Refusal::NotInRoom { ack_id } => (
+                "not_in_room",
+                "Join a room before sending other messages",
+                ack_id.clone(),
+            ),
+            Refusal::InvalidName { reason, ack_id } => ("invalid_name", *reason, ack_id.clone()),
+            Refusal::OutOfRange {
+                object,
+                description,
+                ack_id,
+            } => (*object, description.as_str(), ack_id.clone()),
+        };
+        messages::outbound::Message::Error(messages::outbound::Error {
+            object: object.to_string(),
+            description: description.to_string(),
+            ack_id,
+        })
+    }
+}
+
+/// Parse a text message of a client
+///
+/// # Arguments
+///
+/// * `text` - the message as received
+/// * `sender` - the session that received the message
+pub fn dispatch(text: &str, sender: &Sender) -> Result<Dispatch, Refusal> {
+    let msg: Arbitrary = serde_json::from_str(text.trim()).map_err(|_| Refusal::BadMessage)?;
+    let msg_type = msg
+        .get("type")
+        .and_then(Arbitrary::as_str)
+        .and_then(|msg_type| msg_type.parse::<messages::inbound::Types>().ok());
+
+    // sessions without a room may only join one or sync their clock
+    if !sender.joined
+        && !matches!(
+            msg_type,
+            Some(messages::inbound::Types::JoinRoom) | Some(messages::inbound::Types::ServerTime)
+        )
+    {
+        return Err(Refusal::NotInRoom {
+            ack_id: msg.get("ack_id").cloned(),
+        });
+    }
+
+    // the type decides which message skeleton the message has to follow
+    let dispatched = match msg_type {
+        None => None,
+        Some(messages::inbound::Types::JoinRoom)
+        | Some(messages::inbound::Types::SwitchRoom)
+        | Some(messages::inbound::Types::Poll)
+        | Some(messages::inbound::Types::PollOption)
+        | Some(messages::inbound::Types::PollOptionRemove)
+        | Some(messages::inbound::Types::Vote)
+        | Some(messages::inbound::Types::PollClose)
+        | Some(messages::inbound::Types::PollDelete)
+        | Some(messages::inbound::Types::PollRename)
+        | Some(messages::inbound::Types::SetRoomOption)
+        | Some(messages::inbound::Types::SetWeight)
+        | Some(messages::inbound::Types::PollOptionsQuery)
+        | Some(messages::inbound::Types::Edit)
+        | Some(messages::inbound::Types::React)
+        | Some(messages::inbound::Types::Unreact) => serde_json::from_value(msg)
+            .ok()
+            .map(|msg| dispatch_hash_map_object(msg, sender))
+            .transpose()?
+            .flatten(),
+        Some(messages::inbound::Types::Elevate)
+        | Some(messages::inbound::Types::Recede)
+        | Some(messages::inbound::Types::Kick)
+        | Some(messages::inbound::Types::Mute)
+        | Some(messages::inbound::Types::Unmute)
+        | Some(messages::inbound::Types::TransferHost) => serde_json::from_value(msg)
+            .ok()
+            .and_then(|msg| dispatch_usize_object(msg, sender)),
+        Some(messages::inbound::Types::ServerTime) => Some(Dispatch::ServerTime {
+            ack_id: msg.get("ack_id").cloned(),
+        }),
+        Some(_) => serde_json::from_value(msg)
+            .ok()
+            .map(|msg| dispatch_arbitrary_object(msg, sender))
+            .transpose()?
+            .flatten(),
+    };
+
+    dispatched.ok_or(Refusal::BadMessage)
+}
+
+/// parse a message with named parameters, `None` if parameters of its type are missing
+fn dispatch_hash_map_object(
+    msg: messages::inbound::HashMapObject,
+    sender: &Sender,
+) -> Result<Option<Dispatch>, Refusal> {
+    let dispatch = match msg.get_type() {
+        Ok(messages::inbound::Types::JoinRoom) => {
+            if let (Some(room), Some(name)) = (msg.get_str("room"), msg.get_str("name")) {
+                let (room, name) = (crate::normalize_name(room), crate::normalize_name(name));
+                crate::validate_room_name(&room, sender.max_name_len)
+                    .and_then(|_| crate::validate_name(&name, sender.max_name_len))
+                    .map_err(|reason| Refusal::InvalidName {
+                        reason,
+                        ack_id: msg.ack_id.clone(),
+                    })?;
+
+                Some(Dispatch::JoinRoom {
+                    room,
+                    name,
+                    password: msg.get_str("password").map(str::to_string),
+                })
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::SwitchRoom) => {
+            if let Some(room) = msg.get_str("room") {
+                let room = crate::normalize_name(room);
+                crate::validate_room_name(&room, sender.max_name_len).map_err(|reason| {
+                    Refusal::InvalidName {
+                        reason,
+                        ack_id: msg.ack_id.clone(),
+                    }
+                })?;
+
+                Some(Dispatch::SwitchRoom {
+                    room,
+                    password: msg.get_str("password").map(str::to_string),
+                })
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::Poll) => {
+            let duration_secs = msg.get_u64("duration_secs");
+            // the duration is scheduled as a timer, which can't be arbitrarily far away
+            if duration_secs.is_some_and(|secs| secs > sender.max_poll_duration_secs) {
+                return Err(Refusal::OutOfRange {
+                    object: "invalid_duration",
+                    description: format!(
+                        "Polls can't run longer than {} seconds",
+                        sender.max_poll_duration_secs
+                    ),
+                    ack_id: msg.ack_id.clone(),
+                });
+            }
+
+            msg.get_str("poll_title").map(|poll_title| {
+                Dispatch::Poll(server::Poll {
+                    ack_id: msg.ack_id.clone(),
+                    title: poll_title.to_string(),
+                    owner_id: sender.id,
+                    owner_name: sender.name.to_string(),
+                    room_name: sender.room.to_string(),
+                    id: 0,
+                    options: Vec::new(),
+                    votes: HashMap::new(),
+                    weights: HashMap::new(),
+                    closed: false,
+                    multi: msg.get_bool("multi").unwrap_or(false),
+                    anonymous: msg.get_bool("anonymous").unwrap_or(false),
+                    duration_secs,
+                    auto_close_on_full: msg.get_bool("auto_close_on_full").unwrap_or(false),
+                })
+            })
+        }
+        Ok(messages::inbound::Types::PollOption) => {
+            if let (Some(poll_title), Some(poll_option_title)) =
+                (msg.get_str("poll_title"), msg.get_str("poll_option_title"))
+            {
+                Some(Dispatch::PollOption(server::PollOption {
+                    ack_id: msg.ack_id.clone(),
+                    poll_title: poll_title.to_string(),
+                    title: poll_option_title.to_string(),
+                    owner_id: sender.id,
+                    owner_name: sender.name.to_string(),
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::PollOptionRemove) => {
+            if let (Some(poll_title), Some(poll_option_title)) =
+                (msg.get_str("poll_title"), msg.get_str("poll_option_title"))
+            {
+                Some(Dispatch::PollOptionRemove(server::PollOptionRemove {
+                    ack_id: msg.ack_id.clone(),
+                    poll_title: poll_title.to_string(),
+                    title: poll_option_title.to_string(),
+                    sender_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::Vote) => {
+            if let (Some(poll_title), Some(poll_option_title)) =
+                (msg.get_str("poll_title"), msg.get_str("poll_option_title"))
+            {
+                Some(Dispatch::Vote(server::PollVoteHelper {
+                    ack_id: msg.ack_id.clone(),
+                    owner_id: sender.id,
+                    owner_name: sender.name.to_string(),
+                    room_name: sender.room.to_string(),
+                    poll_title: poll_title.to_string(),
+                    option_title: poll_option_title.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::PollClose) => msg.get_str("poll_title").map(|poll_title| {
+            Dispatch::PollClose(server::PollCloseHelper {
+                ack_id: msg.ack_id.clone(),
+                poll_title: poll_title.to_string(),
+                sender_id: sender.id,
+                sender_name: sender.name.to_string(),
+                room_name: sender.room.to_string(),
+            })
+        }),
+        Ok(messages::inbound::Types::PollDelete) => msg.get_str("poll_title").map(|poll_title| {
+            Dispatch::PollDelete(server::PollDelete {
+                ack_id: msg.ack_id.clone(),
+                poll_title: poll_title.to_string(),
+                sender_id: sender.id,
+                room_name: sender.room.to_string(),
+            })
+        }),
+        Ok(messages::inbound::Types::PollRename) => {
+            if let (Some(poll_title), Some(new_poll_title)) =
+                (msg.get_str("poll_title"), msg.get_str("new_poll_title"))
+            {
+                Some(Dispatch::PollRename(server::PollRename {
+                    ack_id: msg.ack_id.clone(),
+                    poll_title: poll_title.to_string(),
+                    new_title: new_poll_title.to_string(),
+                    sender_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::SetRoomOption) => {
+            if let (Some(option), Some(value)) = (msg.get_str("option"), msg.get_bool("value")) {
+                Some(Dispatch::SetRoomOption(server::SetRoomOption {
+                    ack_id: msg.ack_id.clone(),
+                    option: option.to_string(),
+                    value,
+                    sender_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::SetWeight) => {
+            if let (Some(user_id), Some(weight)) = (msg.get_u64("user_id"), msg.get_u64("weight")) {
+                Some(Dispatch::SetWeight(server::SetWeight {
+                    ack_id: msg.ack_id.clone(),
+                    user_id: user_id as usize,
+                    weight: weight as usize,
+                    sender_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::PollOptionsQuery) => {
+            msg.get_str("poll_title").map(|poll_title| {
+                Dispatch::PollOptionsQuery(server::PollOptionsQuery {
+                    ack_id: msg.ack_id.clone(),
+                    poll_title: poll_title.to_string(),
+                    sender_id: sender.id,
+                    room_name: sender.room.to_string(),
+                })
+            })
+        }
+        Ok(messages::inbound::Types::Edit) => {
+            if let (Some(message_id), Some(object)) =
+                (msg.get_u64("message_id"), msg.object.get("object"))
+            {
+                Some(Dispatch::Edit(server::Edit {
+                    ack_id: msg.ack_id.clone(),
+                    message_id: message_id as usize,
+                    object: object.clone(),
+                    owner_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::React) => {
+            if let (Some(target), Some(emoji)) = (msg.get_str("target"), msg.get_str("emoji")) {
+                Some(Dispatch::React(server::React {
+                    ack_id: msg.ack_id.clone(),
+                    target: target.to_string(),
+                    emoji: emoji.to_string(),
+                    owner_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(messages::inbound::Types::Unreact) => {
+            if let (Some(target), Some(emoji)) = (msg.get_str("target"), msg.get_str("emoji")) {
+                Some(Dispatch::Unreact(server::Unreact {
+                    ack_id: msg.ack_id.clone(),
+                    target: target.to_string(),
+                    emoji: emoji.to_string(),
+                    owner_id: sender.id,
+                    room_name: sender.room.to_string(),
+                }))
+            } else {
+                None
+            }
+        }
+        Ok(_) | Err(_) => None,
+    };
+
+    Ok(dispatch)
+}
+
+/// parse a message with a user id, `None` if its type doesn't take a user id
+fn dispatch_usize_object(msg: messages::inbound::UsizeObject, sender: &Sender) -> Option<Dispatch> {
+    match msg.get_type() {
+        Ok(messages::inbound::Types::Elevate) => Some(Dispatch::Elevate(server::Elevate {
+            ack_id: msg.ack_id,
+            object: msg.object,
+            owner_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Recede) => Some(Dispatch::Recede(server::Recede {
+            ack_id: msg.ack_id,
+            object: msg.object,
+            owner_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Kick) => Some(Dispatch::Kick(server::Kick {
+            ack_id: msg.ack_id,
+            user_id: msg.object,
+            sender_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Mute) => Some(Dispatch::Mute(server::Mute {
+            ack_id: msg.ack_id,
+            user_id: msg.object,
+            sender_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Unmute) => Some(Dispatch::Unmute(server::Unmute {
+            ack_id: msg.ack_id,
+            user_id: msg.object,
+            sender_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::TransferHost) => {
+            Some(Dispatch::TransferHost(server::TransferHost {
+                ack_id: msg.ack_id,
+                user_id: msg.object,
+                sender_id: sender.id,
+                room_name: sender.room.to_string(),
+            }))
+        }
+        Ok(_) | Err(_) => None,
+    }
+}
+
+/// parse a message with an arbitrary object, `None` if parameters of its type are missing
+fn dispatch_arbitrary_object(
+    msg: messages::inbound::ArbitraryObject,
+    sender: &Sender,
+) -> Result<Option<Dispatch>, Refusal> {
+    let dispatch = match msg.get_type() {
+        Ok(messages::inbound::Types::Instant) => {
+            // the lifetime is scheduled as a timer, which can't be arbitrarily far away
+            if msg
+                .ttl_secs
+                .is_some_and(|secs| secs > sender.max_message_ttl_secs)
+            {
+                return Err(Refusal::OutOfRange {
+                    object: "invalid_ttl",
+                    description: format!(
+                        "Messages can't expire later than after {} seconds",
+                        sender.max_message_ttl_secs
+                    ),
+                    ack_id: msg.ack_id,
+                });
+            }
+
+            Some(Dispatch::Instant(server::Instant {
+                ack_id: msg.ack_id,
+                object: msg.object,
+                owner_id: sender.id,
+                owner_name: sender.name.to_string(),
+                room_name: sender.room.to_string(),
+                ttl_secs: msg.ttl_secs,
+                reply_to: msg.reply_to,
+            }))
+        }
+        Ok(messages::inbound::Types::Raise) => Some(Dispatch::Raise(server::Raise {
+            ack_id: msg.ack_id,
+            object: msg.object,
+            reason: msg.reason,
+            owner_id: sender.id,
+            owner_name: sender.name.to_string(),
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Lower) => Some(Dispatch::Lower(server::Lower {
+            ack_id: msg.ack_id,
+            object: msg.object,
+            owner_id: sender.id,
+            owner_name: sender.name.to_string(),
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::Direct) => msg.target_id.map(|target_id| {
+            Dispatch::Direct(server::Direct {
+                ack_id: msg.ack_id,
+                object: msg.object,
+                target_id,
+                owner_id: sender.id,
+                owner_name: sender.name.to_string(),
+                room_name: sender.room.to_string(),
+            })
+        }),
+        Ok(messages::inbound::Types::Typing) => msg.object.as_bool().map(|active| {
+            Dispatch::Typing(server::Typing {
+                ack_id: msg.ack_id,
+                active,
+                owner_id: sender.id,
+                owner_name: sender.name.to_string(),
+                room_name: sender.room.to_string(),
+            })
+        }),
+        Ok(messages::inbound::Types::Leave) => Some(Dispatch::Leave),
+        Ok(messages::inbound::Types::LowerAll) => Some(Dispatch::LowerAll(server::LowerAll {
+            ack_id: msg.ack_id,
+            sender_id: sender.id,
+            room_name: sender.room.to_string(),
+        })),
+        Ok(messages::inbound::Types::CloseAllPolls) => {
+            Some(Dispatch::CloseAllPolls(server::CloseAllPolls {
+                ack_id: msg.ack_id,
+                sender_id: sender.id,
+                room_name: sender.room.to_string(),
+            }))
+        }
+        Ok(messages::inbound::Types::HandQueueQuery) => {
+            Some(Dispatch::HandQueueQuery(server::HandQueueQuery {
+                sender_id: sender.id,
+                room_name: sender.room.to_string(),
+            }))
+        }
+        Ok(_) | Err(_) => None,
+    };
+
+    Ok(dispatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender() -> Sender<'static> {
+        Sender {
+            id: 1,
+            name: "alice",
+            room: "room",
+            joined: true,
+            max_name_len: 32,
+            max_poll_duration_secs: 60,
+            max_message_ttl_secs: 60,
+        }
+    }
+
+    #[test]
+    fn poll_duration_up_to_the_maximum_is_accepted() {
+        let text = r#"{"type":"poll","object":{"poll_title":"lunch","duration_secs":60}}"#;
+        match dispatch(text, &sender()) {
+            Ok(Dispatch::Poll(poll)) => assert_eq!(poll.duration_secs, Some(60)),
+            _ => panic!("poll not dispatched"),
+        }
+    }
+
+    #[test]
+    fn poll_duration_above_the_maximum_is_refused() {
+        let text = r#"{"type":"poll","object":{"poll_title":"lunch","duration_secs":61}}"#;
+        match dispatch(text, &sender()) {
+            Err(Refusal::OutOfRange { object, .. }) => assert_eq!(object, "invalid_duration"),
+            _ => panic!("poll not refused"),
+        }
+    }
+
+    #[test]
+    fn poll_is_single_select_unless_asked() {
+        let single = r#"{"type":"poll","object":{"poll_title":"lunch"}}"#;
+        let multi = r#"{"type":"poll","object":{"poll_title":"lunch","multi":true}}"#;
+        match (dispatch(single, &sender()), dispatch(multi, &sender())) {
+            (Ok(Dispatch::Poll(single)), Ok(Dispatch::Poll(multi))) => {
+                assert!(!single.multi);
+                assert!(multi.multi);
+            }
+            _ => panic!("poll not dispatched"),
+        }
+    }
+
+    #[test]
+    fn messages_of_every_skeleton_are_dispatched() {
+        let vote = r#"{"type":"vote","object":{"poll_title":"lunch","poll_option_title":"pizza"}}"#;
+        let elevate = r#"{"type":"elevate","object":2}"#;
+        let instant = r#"{"type":"instant","object":"hi"}"#;
+        match dispatch(vote, &sender()) {
+            Ok(Dispatch::Vote(vote)) => {
+                assert_eq!(vote.poll_title, "lunch");
+                assert_eq!(vote.option_title, "pizza");
+            }
+            _ => panic!("vote not dispatched"),
+        }
+        match dispatch(elevate, &sender()) {
+            Ok(Dispatch::Elevate(elevate)) => assert_eq!(elevate.object, 2),
+            _ => panic!("elevate not dispatched"),
+        }
+        match dispatch(instant, &sender()) {
+            Ok(Dispatch::Instant(instant)) => assert_eq!(instant.object, "hi"),
+            _ => panic!("instant not dispatched"),
+        }
+    }
+
+    #[test]
+    fn messages_of_unknown_types_are_refused() {
+        // formerly delegated as a plain map of parameters
+        let text = r#"{"type":"delegate","object":{"poll_title":"lunch"}}"#;
+        assert!(matches!(
+            dispatch(text, &sender()),
+            Err(Refusal::BadMessage)
+        ));
+    }
+
+    #[test]
+    fn room_messages_are_dispatched_with_the_borrowed_sender() {
+        let join = r#"{"type":"joinroom","object":{"room":"other","name":"bob"}}"#;
+        let switch = r#"{"type":"switchroom","object":{"room":"other"}}"#;
+        let raise = r#"{"type":"raise","object":"question"}"#;
+        match dispatch(join, &sender()) {
+            Ok(Dispatch::JoinRoom { room, name, .. }) => {
+                assert_eq!(room, "other");
+                assert_eq!(name, "bob");
+            }
+            _ => panic!("joinroom not dispatched"),
+        }
+        match dispatch(switch, &sender()) {
+            Ok(Dispatch::SwitchRoom { room, password }) => {
+                assert_eq!(room, "other");
+                assert!(password.is_none());
+            }
+            _ => panic!("switchroom not dispatched"),
+        }
+        match dispatch(raise, &sender()) {
+            Ok(Dispatch::Raise(raise)) => {
+                assert_eq!(raise.owner_id, 1);
+                assert_eq!(raise.owner_name, "alice");
+                assert_eq!(raise.room_name, "room");
+            }
+            _ => panic!("raise not dispatched"),
+        }
+    }
+
+    #[test]
+    fn instant_lifetime_above_the_maximum_is_refused() {
+        let text = r#"{"type":"instant","object":"hi","ttl_secs":61}"#;
+        match dispatch(text, &sender()) {
+            Err(Refusal::OutOfRange { object, .. }) => assert_eq!(object, "invalid_ttl"),
+            _ => panic!("instant not refused"),
+        }
+        let text = r#"{"type":"instant","object":"hi","ttl_secs":60}"#;
+        match dispatch(text, &sender()) {
+            Ok(Dispatch::Instant(instant)) => assert_eq!(instant.ttl_secs, Some(60)),
+            _ => panic!("instant not dispatched"),
+        }
+    }
+
+    #[test]
+    fn messages_map_to_what_the_session_has_to_do() {
+        type Expectation = fn(&Result<Dispatch, Refusal>) -> bool;
+        let cases: &[(&str, Expectation)] = &[
+            (r#"{"type":"servertime"}"#, |d| {
+                matches!(d, Ok(Dispatch::ServerTime { ack_id: None }))
+            }),
+            (r#"{"type":"leave"}"#, |d| matches!(d, Ok(Dispatch::Leave))),
+            (r#"{"type":"poll","object":{"poll_title":"lunch"}}"#, |d| {
+                matches!(d, Ok(Dispatch::Poll(_)))
+            }),
+            (
+                r#"{"type":"polloption","object":{"poll_title":"lunch","poll_option_title":"pizza"}}"#,
+                |d| matches!(d, Ok(Dispatch::PollOption(_))),
+            ),
+            (
+                r#"{"type":"closepoll","object":{"poll_title":"lunch"}}"#,
+                |d| matches!(d, Ok(Dispatch::PollClose(_))),
+            ),
+            (
+                r#"{"type":"deletepoll","object":{"poll_title":"lunch"}}"#,
+                |d| matches!(d, Ok(Dispatch::PollDelete(_))),
+            ),
+            (r#"{"type":"kick","object":2}"#, |d| {
+                matches!(d, Ok(Dispatch::Kick(_)))
+            }),
+            (r#"{"type":"lower","object":"question"}"#, |d| {
+                matches!(d, Ok(Dispatch::Lower(_)))
+            }),
+            (r#"{"type":"direct","object":"psst","target_id":2}"#, |d| {
+                matches!(d, Ok(Dispatch::Direct(_)))
+            }),
+            (r#"{"type":"typing","object":true}"#, |d| {
+                matches!(d, Ok(Dispatch::Typing(_)))
+            }),
+            (
+                r#"{"type":"react","object":{"target":"lunch","emoji":"+1"}}"#,
+                |d| matches!(d, Ok(Dispatch::React(_))),
+            ),
+            (r#"{"type":"lowerall"}"#, |d| {
+                matches!(d, Ok(Dispatch::LowerAll(_)))
+            }),
+            (r#"{"type":"handqueuequery"}"#, |d| {
+                matches!(d, Ok(Dispatch::HandQueueQuery(_)))
+            }),
+            // malformed messages
+            ("", |d| matches!(d, Err(Refusal::BadMessage))),
+            ("{\"type\":", |d| matches!(d, Err(Refusal::BadMessage))),
+            ("[1, 2]", |d| matches!(d, Err(Refusal::BadMessage))),
+            (r#"{"object":"no type"}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            (r#"{"type":"dance"}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            // parameters of the wrong kind or missing
+            (r#"{"type":"kick","object":"bob"}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            (r#"{"type":"vote","object":{"poll_title":"lunch"}}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            (r#"{"type":"direct","object":"psst"}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            (r#"{"type":"typing","object":"yes"}"#, |d| {
+                matches!(d, Err(Refusal::BadMessage))
+            }),
+            (
+                r#"{"type":"joinroom","object":{"room":"a b","name":"bob"}}"#,
+                |d| matches!(d, Err(Refusal::InvalidName { .. })),
+            ),
+        ];
+        for (text, expected) in cases {
+            assert!(expected(&dispatch(text, &sender())), "{}", text);
+        }
+    }
+
+    #[test]
+    fn sessions_without_a_room_may_only_join_or_sync_their_clock() {
+        let sender = Sender {
+            joined: false,
+            ..sender()
+        };
+        let join = r#"{"type":"joinroom","object":{"room":"other","name":"bob"}}"#;
+        assert!(matches!(
+            dispatch(join, &sender),
+            Ok(Dispatch::JoinRoom { .. })
+        ));
+        assert!(matches!(
+            dispatch(r#"{"type":"servertime"}"#, &sender),
+            Ok(Dispatch::ServerTime { .. })
+        ));
+        assert!(matches!(
+            dispatch(r#"{"type":"raise","object":"q","ack_id":3}"#, &sender),
+            Err(Refusal::NotInRoom { ack_id: Some(_) })
+        ));
+    }
+
+    /// run with `cargo +nightly bench --features bench`
+    #[cfg(feature = "bench")]
+    mod benches {
+        extern crate test;
+
+        use super::*;
+        use std::alloc::{GlobalAlloc, Layout, System};
+        use std::cell::Cell;
+        use test::Bencher;
+
+        const INSTANT: &str = r#"{"type":"instant","object":"hi","ack_id":1}"#;
+        const VOTE: &str =
+            r#"{"type":"vote","object":{"poll_title":"lunch","poll_option_title":"pizza"}}"#;
+        const TYPING_NOT_A_BOOL: &str = r#"{"type":"typing","object":1}"#;
+
+        thread_local! {
+            static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+        }
+
+        /// the system allocator, counting the allocations of each thread
+        struct Counting;
+
+        unsafe impl GlobalAlloc for Counting {
+            unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+                let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+                System.alloc(layout)
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+                System.dealloc(ptr, layout)
+            }
+        }
+
+        #[global_allocator]
+        static ALLOCATOR: Counting = Counting;
+
+        fn allocations_of<T>(f: impl FnOnce() -> T) -> usize {
+            let before = ALLOCATIONS.with(Cell::get);
+            let result = f();
+            let allocations = ALLOCATIONS.with(Cell::get) - before;
+            drop(result);
+            allocations
+        }
+
+        #[test]
+        fn names_are_only_copied_into_server_messages() {
+            let sender = sender();
+            let parsing = allocations_of(|| {
+                let msg: Arbitrary = serde_json::from_str(TYPING_NOT_A_BOOL).unwrap();
+                serde_json::from_value::<messages::inbound::ArbitraryObject>(msg)
+            });
+            let refused = allocations_of(|| dispatch(TYPING_NOT_A_BOOL, &sender));
+            assert_eq!(refused, parsing);
+
+            let typing = r#"{"type":"typing","object":true}"#;
+            let dispatched = allocations_of(|| dispatch(typing, &sender));
+            assert_eq!(dispatched, parsing + 2);
+        }
+
+        #[bench]
+        fn dispatch_instant(b: &mut Bencher) {
+            let sender = sender();
+            b.iter(|| dispatch(test::black_box(INSTANT), &sender));
+        }
+
+        #[bench]
+        fn dispatch_vote(b: &mut Bencher) {
+            let sender = sender();
+            b.iter(|| dispatch(test::black_box(VOTE), &sender));
+        }
+
+        #[bench]
+        fn dispatch_incomplete_typing(b: &mut Bencher) {
+            let sender = sender();
+            b.iter(|| dispatch(test::black_box(TYPING_NOT_A_BOOL), &sender));
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Log output as one JSON object per line, so logs can be searched by room, user or event.
+
+use std::io::Write;
+
+use log::kv::{self, Key, Value, VisitSource};
+use serde_json::{json, Map, Value as Arbitrary};
+
+/// Collects the structured fields of a log record
+struct Fields(Map<String, Arbitrary>);
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = serde_json::to_value(&value).map_err(kv::Error::boxed)?;
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Install the JSON logger
+///
+/// The level is taken from `RUST_LOG` and defaults to `info`. Every line holds time, level,
+/// target and message, followed by the fields of the record, like `room` or `user_id`.
+pub fn init() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .format(|buf, record| {
+            let mut fields = Fields(Map::new());
+            // a field that can't be serialized shouldn't cost the whole line
+            let _ = record.key_values().visit(&mut fields);
+
+            let mut line = json!({
+                "time": buf.timestamp().to_string(),
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            });
+            if let Some(line) = line.as_object_mut() {
+                line.extend(fields.0);
+            }
+            writeln!(buf, "{}", line)
+        })
+        .init();
+}
+
+/// Logger for tests, keeping records to look at instead of printing them
+#[cfg(test)]
+pub mod capture {
+    use super::*;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::sync::{Mutex, Once};
+
+    /// Records of all tests, they run side by side in one process
+    static RECORDS: Mutex<Vec<Arbitrary>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl Log for Capture {
+        fn enabled(&self, _: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            let mut fields = Fields(Map::new());
+            let _ = record.key_values().visit(&mut fields);
+            fields
+                .0
+                .insert("level".to_string(), record.level().to_string().into());
+            fields
+                .0
+                .insert("message".to_string(), record.args().to_string().into());
+            let mut records = RECORDS.lock().unwrap_or_else(|e| e.into_inner());
+            records.push(Arbitrary::Object(fields.0));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// Install the logger, once for all tests
+    pub fn install() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&Capture).unwrap();
+            log::set_max_level(LevelFilter::Trace);
+        });
+    }
+
+    /// Records of a level logged so far, with their fields, `level` and `message`
+    pub fn records_of(level: Level) -> Vec<Arbitrary> {
+        let records = RECORDS.lock().unwrap_or_else(|e| e.into_inner());
+        records
+            .iter()
+            .filter(|record| record["level"] == level.to_string())
+            .cloned()
+            .collect()
+    }
+}
@@ -1,58 +1,536 @@
+#![cfg_attr(all(test, feature = "bench"), feature(test))]
+
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant};
 
 use actix::*;
+use actix_cors::{Cors, CorsFactory};
 use actix_files as fs;
-use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix_http::ws::{Codec, Item};
+use actix_rt::signal::unix::{signal, SignalKind};
+use actix_web::middleware::Condition;
+use actix_web::{dev::Server, web, App, Error, HttpRequest, HttpResponse, HttpServer};
 use actix_web_actors::ws;
 
-use serde_json::{Result as JsonResult, Value as Arbitrary};
+use serde::Deserialize;
 
 use dotenv::dotenv;
+use futures::future::{self, FutureExt};
+use log::{error, info, warn};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{NoClientAuth, ServerConfig};
 use std::env;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::net::IpAddr;
+use std::process;
+use unicode_normalization::UnicodeNormalization;
 
+mod config;
+mod dispatch;
+mod logging;
 mod messages;
-use messages::inbound::GetMessageType;
+mod metrics;
 mod server;
+mod shards;
+
+/// Window in which the messages of a client are counted for rate limiting
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(1);
+
+/// Query parameters accepted when connecting to a room
+#[derive(Deserialize)]
+struct JoinQuery {
+    /// Room to join, only read by the `/ws` route, the other one takes it from the path
+    room: Option<String>,
+    /// Name of the user, only read by the `/ws` route, the other one takes it from the path
+    name: Option<String>,
+    /// Password of the room, sets it if the room is created by this join
+    password: Option<String>,
+    /// Token of a previous session to resume
+    token: Option<String>,
+    /// `spectator` to only watch the room, `participant` if unset
+    role: Option<String>,
+    /// Hex color the user is shown in, e.g. `%23ff8800`, one is picked if unset
+    color: Option<String>,
+    /// URL of the user's avatar image
+    avatar: Option<String>,
+}
 
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum length of avatar URLs in bytes
+const MAX_AVATAR_LEN: usize = 2048;
 
-/// Entry point for our route
+/// Entry point for our route, room and user name are given as path segments
 async fn web_socket_route(
     req: HttpRequest,
     path: web::Path<(String, String)>,
+    query: web::Query<JoinQuery>,
+    stream: web::Payload,
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> Result<HttpResponse, Error> {
+    let (room, name) = path.into_inner();
+    start_session(req, room, name, &query, stream, &srv, &config)
+}
+
+/// Entry point for clients that give room and user name as query parameters, so names may
+/// contain characters like `/`
+async fn web_socket_query_route(
+    req: HttpRequest,
+    query: web::Query<JoinQuery>,
+    stream: web::Payload,
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> Result<HttpResponse, Error> {
+    match (query.room.clone(), query.name.clone()) {
+        (Some(room), Some(name)) => start_session(req, room, name, &query, stream, &srv, &config),
+        _ => Ok(HttpResponse::BadRequest().body("Room and name are required")),
+    }
+}
+
+/// Validate a connection request and start its session
+///
+/// # Arguments
+///
+/// * `room` - name of the room to join
+/// * `name` - name of the user
+/// * `query` - further parameters, room and name in it are ignored
+fn start_session(
+    req: HttpRequest,
+    room: String,
+    name: String,
+    query: &JoinQuery,
     stream: web::Payload,
-    srv: web::Data<Addr<server::WebSocketServer>>,
+    srv: &shards::Shards,
+    config: &config::Config,
 ) -> Result<HttpResponse, Error> {
-    ws::start(
-        WsWebSocketSession {
-            id: get_id(),
-            hb: Instant::now(),
-            room: path.0.clone(),
-            name: path.1.clone(),
-            addr: srv.get_ref().clone(),
-        },
-        &req,
-        stream,
+    let room = normalize_name(&room);
+    let name = normalize_name(&name);
+    if let Err(reason) = validate_room_name(&room, config.max_name_len)
+        .and_then(|_| validate_name(&name, config.max_name_len))
+    {
+        return Ok(HttpResponse::BadRequest().body(reason));
+    }
+
+    let spectator = match query.role.as_deref() {
+        None | Some("participant") => false,
+        Some("spectator") => true,
+        Some(_) => return Ok(HttpResponse::BadRequest().body("Unknown role")),
+    };
+
+    let color = match query.color.as_deref().map(normalize_color).transpose() {
+        Ok(color) => color,
+        Err(reason) => return Ok(HttpResponse::BadRequest().body(reason)),
+    };
+    if let Err(reason) = query.avatar.as_deref().map(validate_avatar).transpose() {
+        return Ok(HttpResponse::BadRequest().body(reason));
+    }
+
+    let ip = client_ip(&req, config.trust_proxy);
+    info!(
+        room = room.as_str(),
+        ip = ip.map(|ip| ip.to_string()),
+        scheme = client_scheme(&req, config.trust_proxy),
+        event = "connect";
+        "Client connected"
+    );
+
+    // frames larger than the limit are refused by the codec before they are buffered
+    let codec = Codec::new().max_size(config.max_msg_bytes);
+    let session = WsWebSocketSession {
+        id: get_id(),
+        hb: Instant::now(),
+        last_activity: Instant::now(),
+        idle_timeout: config.idle_timeout,
+        addr: srv.for_room(&room).clone(),
+        room,
+        name,
+        password: query.password.clone(),
+        token: query.token.clone(),
+        spectator,
+        color,
+        avatar: query.avatar.clone(),
+        heartbeat_interval: config.heartbeat_interval,
+        client_timeout: config.client_timeout,
+        max_name_len: config.max_name_len,
+        max_poll_duration_secs: config.max_poll_duration_secs,
+        max_message_ttl_secs: config.max_message_ttl_secs,
+        joined: false,
+        msg_rate: config.msg_rate,
+        msg_count: 0,
+        max_pending_messages: config.max_pending_messages,
+        max_msg_bytes: config.max_msg_bytes,
+        fragments: None,
+        ip,
+        shards: srv.clone(),
+    };
+    Ok(ws::handshake(&req)?.streaming(ws::WebsocketContext::with_codec(session, stream, codec)))
+}
+
+/// Address of the client that sent a request
+///
+/// Behind a trusted reverse proxy it's the last `X-Forwarded-For` entry, the one added by the
+/// proxy, as earlier entries come from the client. Otherwise, or if the header is missing or
+/// malformed, it's the address of the connection.
+///
+/// # Arguments
+///
+/// * `req` - the request
+/// * `trust_proxy` - whether `X-Forwarded-For` is set by a trusted proxy
+fn client_ip(req: &HttpRequest, trust_proxy: bool) -> Option<IpAddr> {
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-For")
+        .filter(|_| trust_proxy)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.rsplit(',').next())
+        .and_then(|ip| ip.trim().parse().ok());
+    forwarded.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+}
+
+/// Scheme the client connected with, `http` or `https`
+///
+/// Behind a trusted reverse proxy it's the last `X-Forwarded-Proto` entry, as the proxy may
+/// terminate TLS. Otherwise, or if the header is missing or names another scheme, it's the
+/// scheme of the connection.
+///
+/// # Arguments
+///
+/// * `req` - the request
+/// * `trust_proxy` - whether `X-Forwarded-Proto` is set by a trusted proxy
+fn client_scheme(req: &HttpRequest, trust_proxy: bool) -> &'static str {
+    let forwarded = req
+        .headers()
+        .get("X-Forwarded-Proto")
+        .filter(|_| trust_proxy)
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.rsplit(',').next())
+        .and_then(|scheme| match scheme.trim().to_ascii_lowercase().as_str() {
+            "http" => Some("http"),
+            "https" => Some("https"),
+            _ => None,
+        });
+    forwarded.unwrap_or(if req.app_config().secure() {
+        "https"
+    } else {
+        "http"
+    })
+}
+
+/// Whether a character takes no space when rendered, like zero-width spaces and joiners or
+/// marks changing the text direction, so names containing it look like other names
+fn is_invisible_char(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00ad}'
+            | '\u{180e}'
+            | '\u{200b}'..='\u{200f}'
+            | '\u{202a}'..='\u{202e}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{feff}'
     )
 }
 
+/// Bring a user or room name into the form it's known by everywhere, so the same looking
+/// names are equal and can't break logs or clients
+///
+/// Invisible characters are removed and the rest is NFC normalized, combining characters are
+/// composed with their base character where possible. Control characters are kept, so
+/// `validate_name` refuses names containing them.
+///
+/// # Arguments
+///
+/// * `name` - the name as given by the client
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !is_invisible_char(*c))
+        .nfc()
+        .collect()
+}
+
+/// Check that a user or room name is usable
+///
+/// # Arguments
+///
+/// * `name` - the name to check
+/// * `max_len` - maximum length in characters
+fn validate_name(name: &str, max_len: usize) -> Result<(), &'static str> {
+    if name.is_empty() {
+        Err("Names must not be empty")
+    } else if name.chars().count() > max_len {
+        Err("Name is too long")
+    } else if name.chars().any(char::is_control) {
+        Err("Names must not contain control characters")
+    } else {
+        Ok(())
+    }
+}
+
+/// Check that a color is a hex color like `#f80` or `#ff8800`, the `#` may be left out
+///
+/// Returns the color in the form `#ff8800`, so clients get the same format for every user.
+///
+/// # Arguments
+///
+/// * `color` - the color to check
+fn normalize_color(color: &str) -> Result<String, &'static str> {
+    let hex = color
+        .strip_prefix('#')
+        .unwrap_or(color)
+        .to_ascii_lowercase();
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err("Colors have to be hex colors like #ff8800");
+    }
+    match hex.len() {
+        // short form, every digit stands for two
+        3 => Ok(format!(
+            "#{}",
+            hex.chars().flat_map(|c| [c, c]).collect::<String>()
+        )),
+        6 => Ok(format!("#{}", hex)),
+        _ => Err("Colors have to be hex colors like #ff8800"),
+    }
+}
+
+/// Check that an avatar is a http(s) URL of limited length
+///
+/// # Arguments
+///
+/// * `avatar` - the URL to check
+fn validate_avatar(avatar: &str) -> Result<(), &'static str> {
+    if !avatar.starts_with("https://") && !avatar.starts_with("http://") {
+        Err("Avatars have to be http(s) URLs")
+    } else if avatar.len() > MAX_AVATAR_LEN {
+        Err("Avatar URL is too long")
+    } else if avatar.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Err("Avatar URLs must not contain whitespace")
+    } else {
+        Ok(())
+    }
+}
+
+/// Characters allowed in room names, so they are safe in URLs, logs and file names
+fn is_room_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+/// Check that a room name is usable and only made of the characters `is_room_name_char` allows
+///
+/// # Arguments
+///
+/// * `name` - the name to check
+/// * `max_len` - maximum length in characters
+fn validate_room_name(name: &str, max_len: usize) -> Result<(), &'static str> {
+    validate_name(name, max_len)?;
+    if !name.chars().all(is_room_name_char) {
+        return Err("Room names may only contain letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+/// Liveness probe, answers as long as the HTTP server runs
+async fn healthz_route(started: web::Data<Instant>) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "uptime_secs": started.elapsed().as_secs(),
+    }))
+}
+
+/// Readiness probe, answers once all web socket server actors respond
+///
+/// A server that doesn't answer a ping within the configured timeout, as it's stuck or has
+/// too many messages queued, fails the probe even though the process is alive.
+async fn readyz_route(
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> HttpResponse {
+    let pings = srv
+        .all()
+        .iter()
+        .map(|shard| shard.send(server::Ping).timeout(config.ready_timeout));
+    if future::join_all(pings).await.iter().any(Result::is_err) {
+        return HttpResponse::ServiceUnavailable()
+            .json(serde_json::json!({ "status": "unavailable" }));
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Current server metrics
+async fn metrics_route() -> HttpResponse {
+    HttpResponse::Ok().json(metrics::snapshot())
+}
+
+/// All polls of a room with their results, without voter identities
+async fn room_polls_route(
+    path: web::Path<String>,
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> Result<HttpResponse, Error> {
+    // the room is known by its normalized name, which also picks its server
+    let room_name = normalize_name(&path.into_inner());
+    if let Err(reason) = validate_room_name(&room_name, config.max_name_len) {
+        return Ok(HttpResponse::BadRequest().body(reason));
+    }
+    let polls = srv
+        .for_room(&room_name)
+        .send(server::RoomPolls { room_name })
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    Ok(HttpResponse::Ok().json(polls))
+}
+
+/// Whether a request carries the admin token as bearer token, never if none is configured
+///
+/// # Arguments
+///
+/// * `req` - the request
+/// * `config` - the settings holding the admin token
+fn is_admin(req: &HttpRequest, config: &config::Config) -> bool {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (&config.admin_token, token) {
+        (Some(admin_token), Some(token)) => {
+            server::constant_time_eq(admin_token.as_bytes(), token.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// Events of a room in the order they happened, for operators
+///
+/// Requires the admin token as bearer token, answers 404 if the room doesn't exist.
+async fn room_transcript_route(
+    req: HttpRequest,
+    path: web::Path<String>,
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> Result<HttpResponse, Error> {
+    if !is_admin(&req, &config) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let room_name = normalize_name(&path.into_inner());
+    if let Err(reason) = validate_room_name(&room_name, config.max_name_len) {
+        return Ok(HttpResponse::BadRequest().body(reason));
+    }
+    let transcript = srv
+        .for_room(&room_name)
+        .send(server::RoomTranscript { room_name })
+        .await
+        .map_err(|_| HttpResponse::InternalServerError().finish())?;
+    Ok(match transcript {
+        Some(transcript) => HttpResponse::Ok().json(transcript),
+        None => HttpResponse::NotFound().finish(),
+    })
+}
+
+/// Announcement posted to `/announce`
+#[derive(Deserialize)]
+struct Announcement {
+    /// Text sent to every connected user
+    message: String,
+}
+
+/// Send an operator's announcement to every session in every room
+///
+/// Requires the admin token as bearer token, it's refused with 401 if none is configured.
+async fn announce_route(
+    req: HttpRequest,
+    body: web::Json<Announcement>,
+    srv: web::Data<shards::Shards>,
+    config: web::Data<config::Config>,
+) -> HttpResponse {
+    if !is_admin(&req, &config) {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    if body.message.trim().is_empty() {
+        return HttpResponse::BadRequest().body("Announcements must not be empty");
+    }
+
+    info!(event = "announcement"; "Sending announcement to all rooms");
+    let msg = messages::outbound::Message::Announcement(messages::outbound::Announcement {
+        object: body.into_inner().message,
+    })
+    .to_string();
+    for shard in srv.all() {
+        shard.do_send(server::GlobalBroadcast(msg.clone()));
+    }
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// List active rooms and their number of participants
+async fn rooms_route(srv: web::Data<shards::Shards>) -> Result<HttpResponse, Error> {
+    // every room belongs to exactly one server, so the lists don't overlap
+    let mut rooms = HashMap::new();
+    for shard in srv.all() {
+        rooms.extend(
+            shard
+                .send(server::ListRooms)
+                .await
+                .map_err(|_| HttpResponse::InternalServerError().finish())?,
+        );
+    }
+    Ok(HttpResponse::Ok().json(rooms))
+}
+
 struct WsWebSocketSession {
     /// unique session id
     id: usize,
-    /// Client must send ping at least once per 10 seconds (CLIENT_TIMEOUT),
+    /// Client must send ping at least once per client_timeout,
     /// otherwise we drop connection.
     hb: Instant,
-    /// joined room
+    /// Time of the last message of the client, heartbeats aside
+    last_activity: Instant,
+    /// How long the client may not send any message before we drop the connection
+    idle_timeout: Option<Duration>,
+    /// How often heartbeat pings are sent
+    heartbeat_interval: Duration,
+    /// How long before lack of client response causes a timeout
+    client_timeout: Duration,
+    /// joined room, or the last joined room if the session left it
     room: String,
+    /// whether the session is currently a member of `room`
+    joined: bool,
+    /// Maximum length of room and user names given when joining another room
+    max_name_len: usize,
+    /// Longest duration in seconds a poll of the user may be closed automatically after
+    max_poll_duration_secs: u64,
+    /// Longest lifetime in seconds an instant message of the user may ask for
+    max_message_ttl_secs: u64,
     /// peer name
     name: String,
-    /// web socket server
+    /// room password given on connect
+    password: Option<String>,
+    /// resume token given on connect
+    token: Option<String>,
+    /// whether the user only watches, given on connect
+    spectator: bool,
+    /// color the user is shown in, given on connect
+    color: Option<String>,
+    /// URL of the user's avatar, given on connect
+    avatar: Option<String>,
+    /// Messages the client may send per RATE_LIMIT_WINDOW
+    msg_rate: usize,
+    /// Messages received in the current RATE_LIMIT_WINDOW
+    msg_count: usize,
+    /// Messages from the web socket server that may wait to be sent before the client is
+    /// disconnected as too slow
+    max_pending_messages: usize,
+    /// Maximum size of a message in bytes, including continuation frames
+    max_msg_bytes: usize,
+    /// Text received so far of a message split into continuation frames
+    fragments: Option<Vec<u8>>,
+    /// IP address of the client, if known
+    ip: Option<IpAddr>,
+    /// web socket server of the joined room
     addr: Addr<server::WebSocketServer>,
+    /// all web socket servers, to find the one of another room
+    shards: shards::Shards,
 }
 
 impl Actor for WsWebSocketSession {
@@ -62,34 +540,23 @@ impl Actor for WsWebSocketSession {
         // start heart beating
         self.hb(ctx);
 
-        // register self in web socket server. `AsyncContext::wait` register
-        // future within context, but context waits until this future resolves
-        // before processing any other events.
-        // HttpContext::state() is instance of WsWebSocketSessionState, state is shared
-        // across all routes within application
-        let addr = ctx.address();
-        self.addr
-            .send(server::Join {
-                addr: addr.recipient(),
-                room_name: self.room.clone(),
-                user_id: self.id,
-                user_name: self.name.clone(),
-            })
-            .into_actor(self)
-            .then(|res, _, ctx| {
-                match res {
-                    Ok(_) => (), // act.id = res,
-                    // something is wrong with web socket server
-                    _ => ctx.stop(),
-                }
-                fut::ready(())
-            })
-            .wait(ctx);
+        // start a new rate limit window every second
+        ctx.run_interval(RATE_LIMIT_WINDOW, |act, _| act.msg_count = 0);
+
+        // the mailbox isn't drained while the client doesn't read, the web socket server
+        // disconnects the session once it's full
+        ctx.set_mailbox_capacity(self.max_pending_messages);
+
+        // register self in web socket server
+        self.join(ctx, true);
     }
 
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
         // notify web socket server
-        self.addr.do_send(server::Disconnect { id: self.id });
+        self.addr.do_send(server::Disconnect {
+            id: self.id,
+            reason: server::LeaveReason::ClientClose,
+        });
         Running::Stop
     }
 }
@@ -103,10 +570,25 @@ impl Handler<server::Message> for WsWebSocketSession {
     }
 }
 
+/// Web socket server asks us to leave, send the last message and disconnect
+impl Handler<server::Close> for WsWebSocketSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: server::Close, ctx: &mut Self::Context) {
+        ctx.text(msg.0);
+        ctx.close(Some(ws::CloseCode::Policy.into()));
+        ctx.stop();
+    }
+}
+
 /// WebSocket message handler
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsWebSocketSession {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         let msg = match msg {
+            Err(ws::ProtocolError::Overflow) => {
+                self.refuse_too_large(ctx);
+                return;
+            }
             Err(_) => {
                 ctx.stop();
                 return;
@@ -125,182 +607,344 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsWebSocketSessio
             }
 
             ws::Message::Text(text) => {
-                let m = text.trim();
-                let msg: Result<messages::inbound::HashMapObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Poll) => match msg.object.get("poll_title") {
-                            Some(poll_title) => {
-                                self.addr.do_send(server::Poll {
-                                    title: poll_title.to_string(),
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                    options: Vec::new(),
-                                    votes: HashMap::new(),
-                                    closed: false,
-                                });
-                                return;
-                            }
-                            _ => (),
-                        },
-                        Ok(messages::inbound::Types::PollOption) => match (
-                            msg.object.get("poll_title"),
-                            msg.object.get("poll_option_title"),
-                        ) {
-                            (Some(poll_title), Some(poll_option_title)) => {
-                                self.addr.do_send(server::PollOption {
-                                    poll_title: poll_title.to_string(),
-                                    title: poll_option_title.to_string(),
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                });
-                                return;
-                            }
-                            (_, _) => (),
-                        },
-                        Ok(messages::inbound::Types::Vote) => match (
-                            msg.object.get("poll_title"),
-                            msg.object.get("poll_option_title"),
-                        ) {
-                            (Some(poll_title), Some(poll_option_title)) => {
-                                self.addr.do_send(server::PollVoteHelper {
-                                    owner_id: self.id,
-                                    owner_name: self.name.clone(),
-                                    room_name: self.room.to_owned(),
-                                    poll_title: poll_title.to_string(),
-                                    option_title: poll_option_title.to_string(),
-                                });
-                                return;
-                            }
-                            (_, _) => (),
-                        },
-                        Ok(messages::inbound::Types::PollClose) => {
-                            match msg.object.get("poll_title") {
-                                Some(poll_title) => {
-                                    self.addr.do_send(server::PollCloseHelper {
-                                        poll_title: poll_title.to_string(),
-                                        sender_id: self.id,
-                                        sender_name: self.name.clone(),
-                                        room_name: self.room.to_owned(),
-                                    });
-                                    return;
-                                }
-                                _ => (),
-                            }
-                        }
-                        Ok(_) | Err(_) => (),
-                    },
-                    Err(_) => (),
+                if text.len() > self.max_msg_bytes {
+                    self.refuse_too_large(ctx);
+                } else {
+                    self.handle_text(&text, ctx);
                 }
+            }
 
-                let msg: Result<messages::inbound::UsizeObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Elevate) => {
-                            self.addr.do_send(server::Elevate {
-                                object: msg.object,
-                                owner_id: self.id,
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
-                        }
-                        Ok(messages::inbound::Types::Recede) => {
-                            self.addr.do_send(server::Recede {
-                                object: msg.object,
-                                owner_id: self.id,
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
+            ws::Message::Binary(_) => {
+                warn!(
+                    room = self.room.as_str(),
+                    user_id = self.id,
+                    event = "unexpected_binary";
+                    "Unexpected binary"
+                );
+            }
+            ws::Message::Close(_) => {
+                ctx.stop();
+            }
+
+            ws::Message::Continuation(item) => {
+                let (bytes, last) = match item {
+                    Item::FirstText(bytes) => {
+                        self.fragments = Some(Vec::new());
+                        (bytes, false)
+                    }
+                    Item::FirstBinary(_) => {
+                        warn!(
+                            room = self.room.as_str(),
+                            user_id = self.id,
+                            event = "unexpected_binary";
+                            "Unexpected binary"
+                        );
+                        return;
+                    }
+                    Item::Continue(bytes) => (bytes, false),
+                    Item::Last(bytes) => (bytes, true),
+                };
+
+                // fragments of binary messages aren't collected and are ignored
+                if let Some(fragments) = self.fragments.as_mut() {
+                    fragments.extend_from_slice(&bytes);
+                    if fragments.len() > self.max_msg_bytes {
+                        self.refuse_too_large(ctx);
+                    } else if last {
+                        let fragments = self.fragments.take().unwrap_or_default();
+                        match String::from_utf8(fragments) {
+                            Ok(text) => self.handle_text(&text, ctx),
+                            Err(_) => ctx.stop(),
                         }
-                        Ok(_) | Err(_) => (),
-                    },
-                    Err(_) => (),
+                    }
                 }
+            }
 
-                let msg: Result<messages::inbound::ArbitraryObject, _> = serde_json::from_str(m);
-                match msg {
-                    Ok(msg) => match msg.get_type() {
-                        Ok(messages::inbound::Types::Instant) => {
-                            self.addr.do_send(server::Instant {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
-                        }
-                        Ok(messages::inbound::Types::Raise) => {
-                            self.addr.do_send(server::Raise {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
-                        }
-                        Ok(messages::inbound::Types::Lower) => {
-                            self.addr.do_send(server::Lower {
-                                object: msg.object,
-                                owner_id: self.id,
-                                owner_name: self.name.clone(),
-                                room_name: self.room.to_owned(),
-                            });
-                            return;
+            ws::Message::Nop => (),
+        }
+    }
+}
+
+impl WsWebSocketSession {
+    /// register the session in `room` of the web socket server
+    ///
+    /// `AsyncContext::wait` registers the future within context, but context waits until this
+    /// future resolves before processing any other events.
+    ///
+    /// # Arguments
+    ///
+    /// * `close_on_refusal` - whether to disconnect if the join is refused, otherwise the session
+    ///   stays connected without a room
+    fn join(&mut self, ctx: &mut ws::WebsocketContext<Self>, close_on_refusal: bool) {
+        let addr = ctx.address();
+        self.addr
+            .send(server::Join {
+                addr: addr.clone().recipient(),
+                close: addr.recipient(),
+                room_name: self.room.clone(),
+                user_id: self.id,
+                user_name: self.name.clone(),
+                password: self.password.take(),
+                token: self.token.take(),
+                ip: self.ip,
+                spectator: self.spectator,
+                color: self.color.clone(),
+                avatar: self.avatar.clone(),
+            })
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    // the id changes if a previous session is resumed
+                    Ok(Ok(id)) => {
+                        act.id = id;
+                        act.joined = true;
+                    }
+                    // join refused, tell the client why and disconnect
+                    Ok(Err(rejection)) => {
+                        ctx.text(rejection.message);
+                        if close_on_refusal {
+                            ctx.close(Some(rejection_close_reason(rejection.kind)));
+                            ctx.stop();
                         }
-                        Ok(_) | Err(_) => (),
-                    },
-                    Err(_) => (),
+                    }
+                    // something is wrong with web socket server
+                    _ => ctx.stop(),
                 }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
 
-                let testmsg: JsonResult<HashMap<String, Arbitrary>> = serde_json::from_str(m);
-                match testmsg {
-                    Err(_) => println!("Malformatted messge detected: {}", text),
-                    Ok(jsonmsg) => {
-                        let r#type = match jsonmsg["type"].as_str() {
-                            Some(res) => res,
-                            None => "NOT PARSEABLE",
-                        };
-
-                        match r#type {
-                            "raise" | "lower" | "instant" | "elevate" | "recede" | "poll"
-                            | "polloption" | "vote" | "closepoll" => {
-                                println!("[{}] Old delegating, DEPRECATED!", r#type)
-                            }
-                            _ => (),
+    /// move the session to another room, it stays in its current room if the new one refuses it
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - name of the new room
+    /// * `password` - password of the new room
+    fn switch_room(
+        &mut self,
+        room: String,
+        password: Option<String>,
+        ctx: &mut ws::WebsocketContext<Self>,
+    ) {
+        let shard = self.shards.for_room(&room).clone();
+
+        // the web socket server of both rooms switches in one step
+        if shard == self.addr {
+            self.addr
+                .send(server::SwitchRoom {
+                    id: self.id,
+                    user_name: self.name.clone(),
+                    room_name: room.clone(),
+                    password,
+                })
+                .into_actor(self)
+                .then(move |res, act, ctx| {
+                    match res {
+                        Ok(Ok(id)) => {
+                            act.id = id;
+                            act.room = room;
                         }
+                        // switch refused, the session stays in its room
+                        Ok(Err(rejection)) => ctx.text(rejection.message),
+                        // something is wrong with web socket server
+                        _ => ctx.stop(),
                     }
-                };
-            }
+                    fut::ready(())
+                })
+                .wait(ctx);
+            return;
+        }
 
-            ws::Message::Binary(_) => println!("Unexpected binary"),
-            ws::Message::Close(_) => {
-                ctx.stop();
+        // rooms of different web socket servers, join the new room before leaving the old one
+        let addr = ctx.address();
+        shard
+            .send(server::Join {
+                addr: addr.clone().recipient(),
+                close: addr.recipient(),
+                room_name: room.clone(),
+                user_id: self.id,
+                user_name: self.name.clone(),
+                password,
+                token: None,
+                ip: self.ip,
+                spectator: self.spectator,
+                color: self.color.clone(),
+                avatar: self.avatar.clone(),
+            })
+            .into_actor(self)
+            .then(move |res, act, ctx| {
+                match res {
+                    Ok(Ok(id)) => {
+                        act.addr.do_send(server::LeaveRoom { id: act.id });
+                        act.id = id;
+                        act.addr = shard;
+                        act.room = room;
+                    }
+                    // switch refused, the session stays in its room
+                    Ok(Err(rejection)) => ctx.text(rejection.message),
+                    // something is wrong with web socket server
+                    _ => ctx.stop(),
+                }
+                fut::ready(())
+            })
+            .wait(ctx);
+    }
+
+    /// leave the current room, the connection stays open to join another one
+    fn leave(&mut self) {
+        if self.joined {
+            self.addr.do_send(server::LeaveRoom { id: self.id });
+            self.joined = false;
+        }
+    }
+
+    /// handle a text message of the client, either received at once or reassembled from
+    /// continuation frames
+    fn handle_text(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self>) {
+        self.last_activity = Instant::now();
+        self.msg_count += 1;
+        if self.msg_count > self.msg_rate {
+            // tell the client once per window, drop silently afterwards
+            if self.msg_count == self.msg_rate + 1 {
+                ctx.text(
+                    messages::outbound::Message::RateLimited(messages::outbound::Error {
+                        object: "rate_limited".to_string(),
+                        description: "Too many messages, dropping messages for now".to_string(),
+                        ack_id: None,
+                    })
+                    .to_string(),
+                );
             }
+            return;
+        }
 
-            ws::Message::Continuation(_) => {
-                ctx.stop();
+        metrics::message_processed();
+
+        let sender = dispatch::Sender {
+            id: self.id,
+            name: &self.name,
+            room: &self.room,
+            joined: self.joined,
+            max_name_len: self.max_name_len,
+            max_poll_duration_secs: self.max_poll_duration_secs,
+            max_message_ttl_secs: self.max_message_ttl_secs,
+        };
+        match dispatch::dispatch(text, &sender) {
+            Ok(dispatched) => self.forward(dispatched, ctx),
+            Err(refusal) => {
+                if let dispatch::Refusal::BadMessage = refusal {
+                    warn!(
+                        room = self.room.as_str(),
+                        user_id = self.id,
+                        event = "bad_message",
+                        text = text;
+                        "Malformatted message detected"
+                    );
+                }
+                ctx.text(refusal.to_message().to_string());
             }
+        }
+    }
 
-            ws::Message::Nop => (),
+    /// act on a parsed message of the client, mostly by forwarding it to the web socket server
+    fn forward(&mut self, dispatched: dispatch::Dispatch, ctx: &mut ws::WebsocketContext<Self>) {
+        match dispatched {
+            dispatch::Dispatch::JoinRoom {
+                room,
+                name,
+                password,
+            } => {
+                // switching rooms, leave the current one first
+                self.leave();
+                self.addr = self.shards.for_room(&room).clone();
+                self.room = room;
+                self.name = name;
+                self.password = password;
+                self.join(ctx, false);
+            }
+            dispatch::Dispatch::SwitchRoom { room, password } => {
+                self.switch_room(room, password, ctx)
+            }
+            dispatch::Dispatch::Leave => self.leave(),
+            dispatch::Dispatch::ServerTime { ack_id } => ctx.text(
+                messages::outbound::Message::ServerTime(messages::outbound::ServerTime {
+                    object: messages::outbound::server_ts(),
+                    ack_id,
+                })
+                .to_string(),
+            ),
+            dispatch::Dispatch::Poll(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollOption(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollOptionRemove(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Vote(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollClose(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollDelete(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollRename(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::PollOptionsQuery(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Edit(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::React(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Unreact(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Elevate(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Recede(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Kick(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Mute(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Unmute(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::TransferHost(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Instant(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Raise(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Lower(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Direct(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::Typing(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::LowerAll(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::CloseAllPolls(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::SetRoomOption(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::SetWeight(msg) => self.addr.do_send(msg),
+            dispatch::Dispatch::HandQueueQuery(msg) => self.addr.do_send(msg),
         }
     }
-}
 
-impl WsWebSocketSession {
-    /// helper method that sends ping to client every second.
+    /// disconnect a client that sent a message larger than `max_msg_bytes`, without parsing it
+    fn refuse_too_large(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        warn!(
+            room = self.room.as_str(),
+            user_id = self.id,
+            event = "message_too_large";
+            "Message too large, disconnecting!"
+        );
+        self.fragments = None;
+        ctx.text(
+            messages::outbound::Message::Error(messages::outbound::Error {
+                object: "message_too_large".to_string(),
+                description: format!("Messages may be at most {} bytes", self.max_msg_bytes),
+                ack_id: None,
+            })
+            .to_string(),
+        );
+        ctx.close(Some(ws::CloseCode::Size.into()));
+        ctx.stop();
+    }
+
+    /// helper method that sends ping to client every heartbeat_interval.
     ///
     /// also this method checks heartbeats from client
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        ctx.run_interval(HEARTBEAT_INTERVAL, |act, ctx| {
+        ctx.run_interval(self.heartbeat_interval, |act, ctx| {
             // check client heartbeats
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+            if Instant::now().duration_since(act.hb) > act.client_timeout {
                 // heartbeat timed out
-                println!("Websocket Client heartbeat failed, disconnecting!");
+                warn!(
+                    room = act.room.as_str(),
+                    user_id = act.id,
+                    event = "timeout";
+                    "Websocket Client heartbeat failed, disconnecting!"
+                );
 
                 // notify web socket server
-                act.addr.do_send(server::Disconnect { id: act.id });
+                act.addr.do_send(server::Disconnect {
+                    id: act.id,
+                    reason: server::LeaveReason::Timeout,
+                });
 
                 // stop actor
                 ctx.stop();
@@ -309,6 +953,36 @@ impl WsWebSocketSession {
                 return;
             }
 
+            // check client activity, heartbeats keep the connection but not the session alive
+            if act
+                .idle_timeout
+                .is_some_and(|idle_timeout| act.last_activity.elapsed() > idle_timeout)
+            {
+                info!(
+                    room = act.room.as_str(),
+                    user_id = act.id,
+                    event = "idle";
+                    "Websocket Client is idle, disconnecting!"
+                );
+
+                act.addr.do_send(server::Disconnect {
+                    id: act.id,
+                    reason: server::LeaveReason::Idle,
+                });
+
+                ctx.text(
+                    messages::outbound::Message::Idle(messages::outbound::Error {
+                        object: "idle".to_string(),
+                        description: "No messages received for too long".to_string(),
+                        ack_id: None,
+                    })
+                    .to_string(),
+                );
+                ctx.close(Some(ws::CloseCode::Policy.into()));
+                ctx.stop();
+                return;
+            }
+
             ctx.ping(b"");
         });
     }
@@ -316,40 +990,903 @@ impl WsWebSocketSession {
 
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
-    env_logger::init();
     dotenv().ok();
+    logging::init();
+
+    let bind_address = parse_args(env::args().skip(1))
+        .and_then(|args| {
+            resolve_bind_address(
+                args,
+                env::var("PORT").or_else(|_| env::var("VIMEET_PORT")).ok(),
+                env::var("VIMEET_BIND_ADDRESS").ok(),
+            )
+        })
+        .unwrap_or_else(|e| {
+            error!(error = e.as_str(); "Invalid arguments");
+            eprintln!("Usage: vimeet-server [--port <port>] [--bind <address>]");
+            process::exit(1);
+        });
+    info!(address = bind_address.as_str(); "Binding server");
+
+    let started = Instant::now();
 
-    let port = env::var("PORT").unwrap_or(env::var("VIMEET_PORT").unwrap_or("8080".to_string()));
-    let mut bind_address = env::var("VIMEET_BIND_ADDRESS").unwrap_or("127.0.0.1".to_string());
+    let config = config::Config::from_env().unwrap_or_else(|e| {
+        error!(error = e.as_str(); "Invalid configuration");
+        process::exit(1);
+    });
+    let tls = config.tls.clone();
 
-    bind_address.push_str(":");
-    bind_address.push_str(port.as_str());
-    println!("Binding server to {}", bind_address);
+    match config.static_dir.canonicalize() {
+        Ok(path) => info!(path:% = path.display(); "Serving static files"),
+        Err(e) => {
+            warn!(
+                path:% = config.static_dir.display(),
+                error:% = e;
+                "Static files directory is not accessible"
+            )
+        }
+    }
 
-    // Start web socket server actor
-    let server = server::WebSocketServer::default().start();
+    // Start web socket server actors
+    let server = shards::Shards::start(&config);
+    let shutdown_grace = config.shutdown_grace;
+    let ws_server = server.clone();
 
     // Create Http server with websocket support
-    HttpServer::new(move || {
+    let http_server = HttpServer::new(move || {
         App::new()
-            .data(server.clone())
-            // redirect to websocket.html
-            .service(web::resource("/").route(web::get().to(|| {
-                HttpResponse::Found()
-                    .header("LOCATION", "/static/websocket.html")
-                    .finish()
-            })))
-            // websocket
-            .service(web::resource("/ws/{room}/{name}/").to(web_socket_route))
-            // static resources
-            .service(fs::Files::new("/static/", "static/"))
+            .wrap(cors(&config))
+            .configure(|cfg| configure_app(cfg, server.clone(), config.clone(), started))
     })
-    .bind(bind_address.as_str())?
-    .run()
-    .await
+    // signals are handled by shutdown() to warn clients first
+    .disable_signals();
+
+    let http_server = match tls {
+        Some(tls) => {
+            info!("TLS is active");
+            http_server.bind_rustls(bind_address.as_str(), load_tls(&tls)?)?
+        }
+        None => http_server.bind(bind_address.as_str())?,
+    }
+    .run();
+
+    actix_rt::spawn(shutdown(http_server.clone(), ws_server, shutdown_grace));
+
+    http_server.await
+}
+
+/// Requests from origins that aren't allowed are refused before reaching any route
+///
+/// Requests from any origin are let through if `allowed_origins` is unset.
+fn cors(config: &config::Config) -> Condition<CorsFactory> {
+    let cors = match &config.allowed_origins {
+        Some(config::AllowedOrigins::List(origins)) => origins
+            .iter()
+            .fold(Cors::new(), |cors, origin| cors.allowed_origin(origin)),
+        _ => Cors::new(),
+    };
+    Condition::new(config.allowed_origins.is_some(), cors.finish())
+}
+
+/// Register the state shared by the routes and the routes themselves
+///
+/// # Arguments
+///
+/// * `cfg` - the configuration of the app
+/// * `server` - the web socket server actors
+/// * `config` - the settings of the server
+/// * `started` - when the server was started
+fn configure_app(
+    cfg: &mut web::ServiceConfig,
+    server: shards::Shards,
+    config: config::Config,
+    started: Instant,
+) {
+    let static_dir = config.static_dir.clone();
+    cfg.data(server)
+        .data(config)
+        .data(started)
+        // redirect to websocket.html
+        .service(web::resource("/").route(web::get().to(|| {
+            HttpResponse::Found()
+                .header("LOCATION", "/static/websocket.html")
+                .finish()
+        })))
+        // health checks
+        .service(web::resource("/healthz").route(web::get().to(healthz_route)))
+        .service(web::resource("/readyz").route(web::get().to(readyz_route)))
+        // server metrics
+        .service(web::resource("/metrics").route(web::get().to(metrics_route)))
+        // active rooms
+        .service(web::resource("/rooms").route(web::get().to(rooms_route)))
+        .service(web::resource("/rooms/{room}/polls").route(web::get().to(room_polls_route)))
+        .service(
+            web::resource("/rooms/{room}/transcript").route(web::get().to(room_transcript_route)),
+        )
+        // operator announcements
+        .service(web::resource("/announce").route(web::post().to(announce_route)))
+        // websocket
+        .service(web::resource("/ws/{room}/{name}/").to(web_socket_route))
+        .service(web::resource("/ws").to(web_socket_query_route))
+        // static resources
+        .service(fs::Files::new("/static/", static_dir));
+}
+
+/// Bind settings given on the command line
+#[derive(Default)]
+struct Args {
+    port: Option<String>,
+    bind: Option<String>,
+}
+
+/// Parse the command line arguments, `--port <port>` and `--bind <address>`
+///
+/// Both flags may also be given as `--flag=value`.
+///
+/// # Arguments
+///
+/// * `args` - the arguments without the program name
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    while let Some(arg) = args.next() {
+        let (flag, value) = match arg.find('=') {
+            Some(index) => (arg[..index].to_string(), Some(arg[index + 1..].to_string())),
+            None => (arg, None),
+        };
+        let target = match flag.as_str() {
+            "--port" => &mut parsed.port,
+            "--bind" => &mut parsed.bind,
+            _ => return Err(format!("Unknown argument '{}'", flag)),
+        };
+        match value.or_else(|| args.next()) {
+            Some(value) => *target = Some(value),
+            None => return Err(format!("Missing value for {}", flag)),
+        }
+    }
+    Ok(parsed)
+}
+
+/// Combine address and port to bind to
+///
+/// Command line arguments take precedence over environment variables, which take precedence
+/// over the defaults `127.0.0.1` and `8080`.
+///
+/// # Arguments
+///
+/// * `args` - bind settings given on the command line
+/// * `env_port` - port from `PORT` or `VIMEET_PORT`
+/// * `env_bind` - address from `VIMEET_BIND_ADDRESS`
+fn resolve_bind_address(
+    args: Args,
+    env_port: Option<String>,
+    env_bind: Option<String>,
+) -> Result<String, String> {
+    let port = args.port.or(env_port).unwrap_or_else(|| "8080".to_string());
+    let port: u16 = port
+        .parse()
+        .map_err(|_| format!("Invalid port '{}', expected a number from 0 to 65535", port))?;
+    let bind = args
+        .bind
+        .or(env_bind)
+        .unwrap_or_else(|| "127.0.0.1".to_string());
+    Ok(format!("{}:{}", bind, port))
+}
+
+/// Stop the server gracefully on SIGINT or SIGTERM
+///
+/// Clients are sent a `servershutdown` message and given `grace` to show a notice before
+/// the server stops.
+async fn shutdown(http_server: Server, ws_server: shards::Shards, grace: Duration) {
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            error!(error:% = e; "Can't listen for SIGTERM");
+            return;
+        }
+    };
+    future::select(
+        actix_rt::signal::ctrl_c().boxed_local(),
+        terminate.recv().boxed_local(),
+    )
+    .await;
+
+    info!(grace_secs = grace.as_secs(); "Shutting down");
+    let msg = messages::outbound::Message::ServerShutdown(messages::outbound::ServerShutdown {
+        grace_secs: grace.as_secs(),
+    })
+    .to_string();
+    for shard in ws_server.all() {
+        let _ = shard.send(server::ShutdownNotice(msg.clone())).await;
+    }
+
+    actix_rt::time::delay_for(grace).await;
+    http_server.stop(true).await;
+}
+
+/// Load certificate chain and private key for HTTPS
+///
+/// The key may be either PKCS#8 or RSA encoded.
+fn load_tls(tls: &config::TlsConfig) -> io::Result<ServerConfig> {
+    let invalid = |what: &str, path: &std::path::Path| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("No valid {} found in {}", what, path.display()),
+        )
+    };
+
+    let cert_chain = certs(&mut BufReader::new(File::open(&tls.cert)?))
+        .ok()
+        .filter(|certs| !certs.is_empty())
+        .ok_or_else(|| invalid("certificate", &tls.cert))?;
+
+    let mut keys =
+        pkcs8_private_keys(&mut BufReader::new(File::open(&tls.key)?)).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut BufReader::new(File::open(&tls.key)?)).unwrap_or_default();
+    }
+    if keys.is_empty() {
+        return Err(invalid("private key", &tls.key));
+    }
+
+    let mut server_config = ServerConfig::new(NoClientAuth::new());
+    server_config
+        .set_single_cert(cert_chain, keys.remove(0))
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(server_config)
+}
+
+/// Close code and reason a session is disconnected with when its join is refused
+///
+/// Codes from 4000 on are private to vimeet, the reason names the refused message's type.
+fn rejection_close_reason(kind: server::RejectionKind) -> ws::CloseReason {
+    let (code, description) = match kind {
+        // the room may have space again later
+        server::RejectionKind::RoomFull => (ws::CloseCode::Again, "roomfull"),
+        server::RejectionKind::RoomLimit => (ws::CloseCode::Other(4001), "roomlimit"),
+        // rooms may be freed up later
+        server::RejectionKind::Capacity => (ws::CloseCode::Again, "capacity"),
+        server::RejectionKind::AuthFailed => (ws::CloseCode::Other(4002), "authfailed"),
+        server::RejectionKind::NameTaken => (ws::CloseCode::Other(4003), "nametaken"),
+        server::RejectionKind::NotInRoom | server::RejectionKind::AlreadyInRoom => {
+            (ws::CloseCode::Policy, "refused")
+        }
+    };
+    ws::CloseReason {
+        code,
+        description: Some(description.to_string()),
+    }
 }
 
 fn get_id() -> usize {
     static COUNTER: AtomicUsize = AtomicUsize::new(1);
     COUNTER.fetch_add(1, Ordering::Relaxed)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::{self, TestServer};
+    use futures::{Sink, SinkExt, Stream, StreamExt};
+    use serde_json::{json, Value as Arbitrary};
+
+    /// settings with every variable unset
+    fn config() -> config::Config {
+        config_with(&[])
+    }
+
+    /// settings with some variables set
+    fn config_with(vars: &[(&str, &str)]) -> config::Config {
+        config::Config::from_vars(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.into())
+        })
+        .unwrap()
+    }
+
+    /// start web socket servers and an HTTP server in front of them
+    fn start(config: config::Config) -> TestServer {
+        let server = shards::Shards::start(&config);
+        let started = Instant::now();
+        test::start(move || {
+            let (server, config) = (server.clone(), config.clone());
+            App::new()
+                .wrap(cors(&config))
+                .configure(move |cfg| configure_app(cfg, server, config, started))
+        })
+    }
+
+    /// read the messages of a client until one of a type arrives, dropping the others
+    async fn receive<S>(client: &mut S, kind: &str) -> Arbitrary
+    where
+        S: Stream<Item = Result<ws::Frame, ws::ProtocolError>> + Unpin,
+    {
+        loop {
+            let frame = actix_rt::time::timeout(Duration::from_secs(5), client.next())
+                .await
+                .unwrap_or_else(|_| panic!("no {} message received", kind))
+                .expect("connection closed")
+                .unwrap();
+            if let ws::Frame::Text(text) = frame {
+                let message: Arbitrary = serde_json::from_slice(&text).unwrap();
+                if message["type"] == kind {
+                    return message;
+                }
+            }
+        }
+    }
+
+    /// messages a client receives within `wait`
+    async fn receive_for<S>(client: &mut S, wait: Duration) -> Vec<Arbitrary>
+    where
+        S: Stream<Item = Result<ws::Frame, ws::ProtocolError>> + Unpin,
+    {
+        let mut received = Vec::new();
+        let until = Instant::now() + wait;
+        while let Ok(Some(frame)) = actix_rt::time::timeout(
+            until.saturating_duration_since(Instant::now()),
+            client.next(),
+        )
+        .await
+        {
+            if let ws::Frame::Text(text) = frame.unwrap() {
+                received.push(serde_json::from_slice(&text).unwrap());
+            }
+        }
+        received
+    }
+
+    async fn send<S>(client: &mut S, message: Arbitrary)
+    where
+        S: Sink<ws::Message, Error = ws::ProtocolError> + Unpin,
+    {
+        client
+            .send(ws::Message::Text(message.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// body of a response parsed as JSON
+    async fn body_of(srv: &mut TestServer, path: &str) -> Arbitrary {
+        let response = srv.get(path).send().await.unwrap();
+        serde_json::from_slice(&srv.load_body(response).await.unwrap()).unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn rooms_lists_rooms_with_their_members() {
+        let mut srv = start(config());
+        let mut clients = Vec::new();
+        for path in &["/ws/first/alice/", "/ws/first/bob/", "/ws/second/carol/"] {
+            let mut client = srv.ws_at(path).await.unwrap();
+            receive(&mut client, "hello").await;
+            clients.push(client);
+        }
+
+        let rooms = body_of(&mut srv, "/rooms").await;
+
+        assert_eq!(rooms, json!({ "first": 2, "second": 1 }));
+    }
+
+    #[test]
+    fn names_have_to_be_neither_empty_nor_too_long() {
+        assert!(validate_name("alice", 5).is_ok());
+        assert_eq!(validate_name("", 5), Err("Names must not be empty"));
+        assert_eq!(validate_name("alice!", 5), Err("Name is too long"));
+        // lengths are counted in characters, not bytes
+        assert!(validate_name("ääää", 4).is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn unusable_names_are_refused_before_connecting() {
+        let srv = start(config());
+        let long_name = "a".repeat(100);
+        let paths = [
+            format!("/ws/room/{}/", long_name),
+            format!("/ws/{}/alice/", long_name),
+            "/ws?room=room&name=".to_string(),
+            "/ws?room=&name=alice".to_string(),
+        ];
+        for path in &paths {
+            let response = srv.get(path).send().await.unwrap();
+            assert_eq!(response.status(), 400, "{}", path);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn bursts_of_messages_are_dropped() {
+        let mut srv = start(config_with(&[("VIMEET_MSG_RATE", "3")]));
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+        receive_for(&mut client, Duration::from_millis(100)).await;
+
+        for _ in 0..6 {
+            send(&mut client, json!({ "type": "servertime" })).await;
+        }
+        let received = receive_for(&mut client, Duration::from_millis(300)).await;
+        let kinds: Vec<_> = received
+            .iter()
+            .map(|message| message["type"].clone())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["servertime", "servertime", "servertime", "ratelimited"]
+        );
+
+        // a new window lets messages pass again
+        actix_rt::time::delay_for(Duration::from_secs(1)).await;
+        send(&mut client, json!({ "type": "servertime" })).await;
+        receive(&mut client, "servertime").await;
+    }
+
+    #[actix_rt::test]
+    async fn fragmented_message_is_handled_like_a_whole_one() {
+        let mut srv = start(config());
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+
+        send(&mut client, json!({ "type": "servertime", "ack_id": 1 })).await;
+        let whole = receive(&mut client, "servertime").await;
+
+        // the client codec writes first fragments with the opcode of the other kind
+        let fragments = [
+            Item::FirstBinary(r#"{"type":"ser"#.into()),
+            Item::Continue(r#"vertime","ack"#.into()),
+            Item::Last(r#"_id":2}"#.into()),
+        ];
+        for fragment in fragments {
+            client
+                .send(ws::Message::Continuation(fragment))
+                .await
+                .unwrap();
+        }
+        let fragmented = receive(&mut client, "servertime").await;
+
+        assert_eq!(whole["ack_id"], 1);
+        assert_eq!(fragmented["ack_id"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn garbage_gets_exactly_one_error_back() {
+        let mut srv = start(config());
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+        receive_for(&mut client, Duration::from_millis(100)).await;
+
+        client
+            .send(ws::Message::Text("{not json".to_string()))
+            .await
+            .unwrap();
+        let received = receive_for(&mut client, Duration::from_millis(300)).await;
+
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["type"], "error");
+        assert_eq!(received[0]["object"], "bad_message");
+    }
+
+    #[actix_rt::test]
+    async fn requests_from_other_origins_are_refused() {
+        let srv = start(config_with(&[(
+            "VIMEET_ALLOWED_ORIGINS",
+            "https://meet.example.com",
+        )]));
+
+        let allowed = srv
+            .get("/rooms")
+            .header("Origin", "https://meet.example.com")
+            .send()
+            .await
+            .unwrap();
+        assert!(allowed.status().is_success());
+        assert_eq!(
+            allowed
+                .headers()
+                .get("access-control-allow-origin")
+                .unwrap(),
+            "https://meet.example.com"
+        );
+
+        let refused = srv
+            .get("/rooms")
+            .header("Origin", "https://evil.example.com")
+            .send()
+            .await
+            .unwrap();
+        assert!(refused.status().is_client_error());
+    }
+
+    #[actix_rt::test]
+    async fn healthz_reports_status_and_uptime() {
+        let mut srv = start(config());
+        let response = srv.get("/healthz").send().await.unwrap();
+        assert_eq!(response.status(), 200);
+
+        let health = body_of(&mut srv, "/healthz").await;
+        assert_eq!(health["status"], "ok");
+        assert!(health["uptime_secs"].is_u64());
+    }
+
+    #[actix_rt::test]
+    async fn session_leaves_a_room_and_joins_another_over_one_socket() {
+        let mut srv = start(config());
+        let mut alice = srv.ws_at("/ws/first/alice/").await.unwrap();
+        receive(&mut alice, "hello").await;
+        let mut bob = srv.ws_at("/ws/second/bob/").await.unwrap();
+        receive(&mut bob, "hello").await;
+
+        send(&mut alice, json!({ "type": "leave" })).await;
+        let rooms = loop {
+            let rooms = body_of(&mut srv, "/rooms").await;
+            if rooms.get("first").is_none() {
+                break rooms;
+            }
+        };
+        assert_eq!(rooms, json!({ "second": 1 }));
+        receive_for(&mut alice, Duration::from_millis(100)).await;
+
+        send(
+            &mut alice,
+            json!({ "type": "joinroom", "object": { "room": "second", "name": "alice" } }),
+        )
+        .await;
+        let joined = receive(&mut bob, "userjoined").await;
+        assert_eq!(joined["object"]["user_name"], "alice");
+        let roster = receive(&mut alice, "roster").await;
+        assert_eq!(roster["object"].as_array().unwrap().len(), 2);
+        assert_eq!(body_of(&mut srv, "/rooms").await, json!({ "second": 2 }));
+    }
+
+    #[test]
+    fn command_line_takes_precedence_over_environment_and_defaults() {
+        let args = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        let env = |value: &str| Some(value.to_string());
+
+        assert_eq!(
+            resolve_bind_address(args(&[]), None, None),
+            Ok("127.0.0.1:8080".to_string())
+        );
+        assert_eq!(
+            resolve_bind_address(args(&[]), env("9000"), env("0.0.0.0")),
+            Ok("0.0.0.0:9000".to_string())
+        );
+        assert_eq!(
+            resolve_bind_address(
+                args(&["--port", "7000", "--bind=10.0.0.1"]),
+                env("9000"),
+                env("0.0.0.0")
+            ),
+            Ok("10.0.0.1:7000".to_string())
+        );
+        assert!(resolve_bind_address(args(&["--port=http"]), None, None).is_err());
+        assert!(parse_args(vec!["--port".to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn room_names_are_limited_to_safe_characters() {
+        for name in &["lobby", "team-42", "daily_standup", "A1"] {
+            assert!(validate_room_name(name, 16).is_ok(), "{}", name);
+        }
+        for name in &[
+            "",
+            "two words",
+            "a/b",
+            "tab\t",
+            "café",
+            "a-very-long-room-name",
+        ] {
+            assert!(validate_room_name(name, 16).is_err(), "{}", name);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn unsafe_room_names_are_refused_before_connecting() {
+        let srv = start(config());
+        for path in &["/ws/a%20b/alice/", "/ws?room=a.b&name=alice"] {
+            let response = srv.get(path).send().await.unwrap();
+            assert_eq!(response.status(), 400, "{}", path);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn silent_client_times_out_with_the_reason() {
+        let mut srv = start(config_with(&[
+            ("VIMEET_HEARTBEAT_SECS", "1"),
+            ("VIMEET_CLIENT_TIMEOUT_SECS", "2"),
+        ]));
+        let mut bob = srv.ws_at("/ws/room/bob/").await.unwrap();
+        receive(&mut bob, "hello").await;
+        let mut alice = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut alice, "hello").await;
+
+        // bob never answers a ping, alice pings to stay connected
+        let mut attempts = 0;
+        let left = loop {
+            attempts += 1;
+            assert!(attempts <= 20, "no userleft message received");
+            alice
+                .send(ws::Message::Ping(Default::default()))
+                .await
+                .unwrap();
+            let received = receive_for(&mut alice, Duration::from_millis(500)).await;
+            if let Some(left) = received
+                .into_iter()
+                .find(|message| message["type"] == "userleft")
+            {
+                break left;
+            }
+        };
+
+        assert_eq!(left["object"]["user_name"], "bob");
+        assert_eq!(left["reason"], "timeout");
+    }
+
+    #[actix_rt::test]
+    async fn client_sending_only_pings_is_disconnected_as_idle() {
+        let mut srv = start(config_with(&[
+            ("VIMEET_HEARTBEAT_SECS", "1"),
+            ("VIMEET_CLIENT_TIMEOUT_SECS", "10"),
+            ("VIMEET_IDLE_TIMEOUT_SECS", "2"),
+        ]));
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+
+        let mut attempts = 0;
+        let idle = loop {
+            attempts += 1;
+            assert!(attempts <= 20, "no idle message received");
+            client
+                .send(ws::Message::Ping(Default::default()))
+                .await
+                .unwrap();
+            let received = receive_for(&mut client, Duration::from_millis(500)).await;
+            if let Some(idle) = received
+                .into_iter()
+                .find(|message| message["type"] == "idle")
+            {
+                break idle;
+            }
+        };
+
+        assert_eq!(idle["object"], "idle");
+        // the connection is closed right after
+        let closed = actix_rt::time::timeout(Duration::from_secs(1), async {
+            while client.next().await.is_some() {}
+        });
+        assert!(closed.await.is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn heartbeat_timeout_logs_a_warning_with_the_session() {
+        logging::capture::install();
+        let mut srv = start(config_with(&[
+            ("VIMEET_HEARTBEAT_SECS", "1"),
+            ("VIMEET_CLIENT_TIMEOUT_SECS", "2"),
+        ]));
+        let mut client = srv.ws_at("/ws/logged/alice/").await.unwrap();
+        let hello = receive(&mut client, "hello").await;
+        let roster = receive(&mut client, "roster").await;
+        assert_eq!(hello["is_host"], true);
+        let id = roster["host_id"].clone();
+
+        let mut attempts = 0;
+        let warning = loop {
+            attempts += 1;
+            assert!(attempts <= 20, "no timeout logged");
+            actix_rt::time::delay_for(Duration::from_millis(500)).await;
+            let warnings = logging::capture::records_of(log::Level::Warn);
+            if let Some(warning) = warnings
+                .into_iter()
+                .find(|record| record["event"] == "timeout" && record["room"] == "logged")
+            {
+                break warning;
+            }
+        };
+
+        assert_eq!(warning["user_id"], id);
+    }
+
+    #[actix_rt::test]
+    async fn announcement_reaches_every_room() {
+        let mut srv = start(config_with(&[("VIMEET_ADMIN_TOKEN", "secret")]));
+        let mut alice = srv.ws_at("/ws/first/alice/").await.unwrap();
+        receive(&mut alice, "hello").await;
+        let mut bob = srv.ws_at("/ws/second/bob/").await.unwrap();
+        receive(&mut bob, "hello").await;
+        let announcement = json!({ "message": "maintenance at 5pm" });
+
+        let refused = srv
+            .post("/announce")
+            .header("Authorization", "Bearer guess")
+            .send_json(&announcement)
+            .await
+            .unwrap();
+        assert_eq!(refused.status(), 401);
+
+        let accepted = srv
+            .post("/announce")
+            .header("Authorization", "Bearer secret")
+            .send_json(&announcement)
+            .await
+            .unwrap();
+        assert_eq!(accepted.status(), 200);
+        for client in &mut [&mut alice, &mut bob] {
+            let received = receive(client, "announcement").await;
+            assert_eq!(received["object"], "maintenance at 5pm");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn poll_created_over_the_socket_is_served_at_the_room() {
+        let mut srv = start(config());
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+
+        send(
+            &mut client,
+            json!({ "type": "poll", "object": { "poll_title": "lunch" } }),
+        )
+        .await;
+        send(
+            &mut client,
+            json!({
+                "type": "polloption",
+                "object": { "poll_title": "lunch", "poll_option_title": "pizza" },
+            }),
+        )
+        .await;
+        receive(&mut client, "polloption").await;
+
+        let polls = body_of(&mut srv, "/rooms/room/polls").await;
+        assert_eq!(polls.as_array().unwrap().len(), 1);
+        assert_eq!(polls[0]["title"], "lunch");
+        assert_eq!(polls[0]["options"], json!(["pizza"]));
+        assert_eq!(body_of(&mut srv, "/rooms/nowhere/polls").await, json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn oversized_message_is_refused_unparsed() {
+        let mut srv = start(config_with(&[("VIMEET_MAX_MSG_BYTES", "64")]));
+        let mut client = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut client, "hello").await;
+        receive_for(&mut client, Duration::from_millis(100)).await;
+
+        // a valid message, it would be answered if it were parsed
+        let padding = "x".repeat(64);
+        send(
+            &mut client,
+            json!({ "type": "servertime", "ack_id": padding }),
+        )
+        .await;
+        let received = receive_for(&mut client, Duration::from_millis(300)).await;
+
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["type"], "error");
+        assert_eq!(received[0]["object"], "message_too_large");
+    }
+
+    #[actix_rt::test]
+    async fn query_route_joins_the_named_room_with_the_name() {
+        let mut srv = start(config());
+        let mut client = srv.ws_at("/ws?room=lobby&name=alice").await.unwrap();
+        receive(&mut client, "hello").await;
+
+        let roster = receive(&mut client, "roster").await;
+        assert_eq!(roster["object"][0]["user_name"], "alice");
+        assert_eq!(body_of(&mut srv, "/rooms").await, json!({ "lobby": 1 }));
+    }
+
+    #[actix_rt::test]
+    async fn full_room_closes_the_socket_with_a_code_and_reason() {
+        let mut srv = start(config_with(&[("VIMEET_MAX_ROOM_SIZE", "1")]));
+        let mut alice = srv.ws_at("/ws/room/alice/").await.unwrap();
+        receive(&mut alice, "hello").await;
+
+        let mut bob = srv.ws_at("/ws/room/bob/").await.unwrap();
+        let reason = loop {
+            let frame = actix_rt::time::timeout(Duration::from_secs(5), bob.next())
+                .await
+                .expect("socket not closed")
+                .expect("connection closed without a reason")
+                .unwrap();
+            if let ws::Frame::Close(reason) = frame {
+                break reason.unwrap();
+            }
+        };
+
+        // 1013, try again later
+        assert_eq!(reason.code, ws::CloseCode::Again);
+        assert_eq!(reason.description.as_deref(), Some("roomfull"));
+    }
+
+    #[test]
+    fn forwarded_address_is_only_used_behind_a_trusted_proxy() {
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        let req = test::TestRequest::default()
+            .peer_addr((peer, 4321).into())
+            .header("X-Forwarded-For", "198.51.100.7, 203.0.113.9")
+            .to_http_request();
+
+        assert_eq!(client_ip(&req, true), "203.0.113.9".parse().ok());
+        assert_eq!(client_ip(&req, false), Some(peer));
+
+        let malformed = test::TestRequest::default()
+            .peer_addr((peer, 4321).into())
+            .header("X-Forwarded-For", "unknown")
+            .to_http_request();
+        assert_eq!(client_ip(&malformed, true), Some(peer));
+    }
+
+    #[test]
+    fn forwarded_scheme_is_only_used_behind_a_trusted_proxy() {
+        let req = test::TestRequest::default()
+            .header("X-Forwarded-Proto", "http, HTTPS")
+            .to_http_request();
+        assert_eq!(client_scheme(&req, true), "https");
+        assert_eq!(client_scheme(&req, false), "http");
+
+        let unknown = test::TestRequest::default()
+            .header("X-Forwarded-Proto", "gopher")
+            .to_http_request();
+        assert_eq!(client_scheme(&unknown, true), "http");
+    }
+
+    #[actix_rt::test]
+    async fn connection_is_logged_with_the_forwarded_address_and_scheme() {
+        logging::capture::install();
+        let srv = start(config_with(&[("VIMEET_TRUST_PROXY", "true")]));
+        let (_, mut client) = actix_web::client::Client::new()
+            .ws(srv.url("/ws/proxied/alice/"))
+            .header("X-Forwarded-For", "203.0.113.9")
+            .header("X-Forwarded-Proto", "https")
+            .connect()
+            .await
+            .unwrap();
+        receive(&mut client, "hello").await;
+
+        let connections: Vec<_> = logging::capture::records_of(log::Level::Info)
+            .into_iter()
+            .filter(|record| record["event"] == "connect" && record["room"] == "proxied")
+            .collect();
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0]["ip"], "203.0.113.9");
+        assert_eq!(connections[0]["scheme"], "https");
+    }
+
+    #[test]
+    fn colors_are_normalized_hex_colors() {
+        assert_eq!(normalize_color("#FF8800"), Ok("#ff8800".to_string()));
+        assert_eq!(normalize_color("f80"), Ok("#ff8800".to_string()));
+        assert!(normalize_color("orange").is_err());
+        assert!(normalize_color("#ff88").is_err());
+    }
+
+    #[test]
+    fn names_are_composed_and_stripped_of_invisible_characters() {
+        // "é" as e followed by a combining acute accent, and precomposed
+        let combining = "Rene\u{0301}";
+        let precomposed = "Ren\u{00e9}";
+        assert_eq!(normalize_name(combining), precomposed);
+        assert_eq!(normalize_name(combining), normalize_name(precomposed));
+
+        assert_eq!(normalize_name("al\u{200b}ice\u{feff}"), "alice");
+        assert_eq!(normalize_name("\u{202e}alice\u{2066}"), "alice");
+        // control characters are left to validate_name
+        assert_eq!(normalize_name("ali\nce"), "ali\nce");
+    }
+
+    #[actix_rt::test]
+    async fn normalized_name_is_shown_to_the_room() {
+        let mut srv = start(config());
+        let mut client = srv.ws_at("/ws/room/Rene%CC%81%E2%80%8B/").await.unwrap();
+        receive(&mut client, "hello").await;
+
+        let roster = receive(&mut client, "roster").await;
+        assert_eq!(roster["object"][0]["user_name"], "Ren\u{00e9}");
+    }
+
+    #[actix_rt::test]
+    async fn readyz_answers_while_the_servers_respond() {
+        let srv = start(config_with(&[("VIMEET_SHARDS", "2")]));
+        let response = srv.get("/readyz").send().await.unwrap();
+        assert_eq!(response.status(), 200);
+    }
+}
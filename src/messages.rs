@@ -45,6 +45,28 @@ pub mod inbound {
         PollOption,
         Vote,
         PollClose,
+        PollOptionRemove,
+        PollDelete,
+        PollRename,
+        CloseAllPolls,
+        SetRoomOption,
+        SetWeight,
+        LowerAll,
+        Kick,
+        TransferHost,
+        Mute,
+        Unmute,
+        Direct,
+        Typing,
+        React,
+        Unreact,
+        Edit,
+        Leave,
+        JoinRoom,
+        SwitchRoom,
+        HandQueueQuery,
+        ServerTime,
+        PollOptionsQuery,
     }
 
     impl FromStr for Types {
@@ -64,6 +86,28 @@ pub mod inbound {
                 "polloption" => Ok(Types::PollOption),
                 "vote" => Ok(Types::Vote),
                 "closepoll" => Ok(Types::PollClose),
+                "removepolloption" => Ok(Types::PollOptionRemove),
+                "deletepoll" => Ok(Types::PollDelete),
+                "renamepoll" => Ok(Types::PollRename),
+                "closeallpolls" => Ok(Types::CloseAllPolls),
+                "setroomoption" => Ok(Types::SetRoomOption),
+                "setweight" => Ok(Types::SetWeight),
+                "lowerall" => Ok(Types::LowerAll),
+                "kick" => Ok(Types::Kick),
+                "transferhost" => Ok(Types::TransferHost),
+                "mute" => Ok(Types::Mute),
+                "unmute" => Ok(Types::Unmute),
+                "direct" => Ok(Types::Direct),
+                "typing" => Ok(Types::Typing),
+                "react" => Ok(Types::React),
+                "unreact" => Ok(Types::Unreact),
+                "edit" => Ok(Types::Edit),
+                "leave" => Ok(Types::Leave),
+                "joinroom" => Ok(Types::JoinRoom),
+                "switchroom" => Ok(Types::SwitchRoom),
+                "handqueuequery" => Ok(Types::HandQueueQuery),
+                "servertime" => Ok(Types::ServerTime),
+                "polloptionsquery" => Ok(Types::PollOptionsQuery),
                 _ => Err(InvalidMessageType {}),
             }
         }
@@ -72,11 +116,27 @@ pub mod inbound {
     /// Inbound message skeleton: Arbitrary object
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
-    /// * `object` - Any value a JSON parameter can hold
+    /// * `object` - Any value a JSON parameter can hold, `null` if omitted
+    /// * `reason` - Optional reason, e.g. why an object is raised
+    /// * `target_id` - Optional user ID of the recipient, e.g. of a direct message
+    /// * `ttl_secs` - Optional lifetime in seconds, e.g. of an instant message
+    /// * `reply_to` - Optional ID of the instant message an instant message replies to
+    /// * `ack_id` - Optional client-generated ID, echoed in the responses to this message
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct ArbitraryObject {
         pub r#type: String,
+        #[serde(default)]
         pub object: Arbitrary,
+        #[serde(default)]
+        pub reason: Option<String>,
+        #[serde(default)]
+        pub target_id: Option<usize>,
+        #[serde(default)]
+        pub ttl_secs: Option<u64>,
+        #[serde(default)]
+        pub reply_to: Option<usize>,
+        #[serde(default)]
+        pub ack_id: Option<Arbitrary>,
     }
 
     impl GetMessageType for ArbitraryObject {
@@ -101,10 +161,13 @@ pub mod inbound {
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
     /// * `object` - A `usize` value
+    /// * `ack_id` - Optional client-generated ID, echoed in the responses to this message
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct UsizeObject {
         pub r#type: String,
         pub object: usize,
+        #[serde(default)]
+        pub ack_id: Option<Arbitrary>,
     }
 
     impl GetMessageType for UsizeObject {
@@ -128,12 +191,37 @@ pub mod inbound {
     /// Inbound message skeleton: Vec objects
     ///
     /// * `type` - Message type, see [Types](#struct.Types)
-    /// * `pollobject` - A `String` value
-    /// * `polloptionobject` - A `String` value
+    /// * `object` - Named parameters, e.g. `poll_title` and `poll_option_title`
+    /// * `ack_id` - Optional client-generated ID, echoed in the responses to this message
     #[derive(Serialize, Deserialize, Clone, Debug)]
     pub struct HashMapObject {
         pub r#type: String,
-        pub object: HashMap<String, String>,
+        pub object: HashMap<String, Arbitrary>,
+        #[serde(default)]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    impl HashMapObject {
+        /// Get a string parameter of the object
+        ///
+        /// * `key` - Name of the parameter
+        pub fn get_str(&self, key: &str) -> Option<&str> {
+            self.object.get(key).and_then(Arbitrary::as_str)
+        }
+
+        /// Get an unsigned integer parameter of the object
+        ///
+        /// * `key` - Name of the parameter
+        pub fn get_u64(&self, key: &str) -> Option<u64> {
+            self.object.get(key).and_then(Arbitrary::as_u64)
+        }
+
+        /// Get a boolean parameter of the object
+        ///
+        /// * `key` - Name of the parameter
+        pub fn get_bool(&self, key: &str) -> Option<bool> {
+            self.object.get(key).and_then(Arbitrary::as_bool)
+        }
     }
 
     impl GetMessageType for HashMapObject {
@@ -159,42 +247,24 @@ pub mod outbound {
     use serde::Serialize;
     use serde_json::Value as Arbitrary;
     use std::collections::HashMap;
-    use std::{error, fmt};
+    use std::fmt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
 
     use crate::server;
-    /// Error if message has unknown message type
-    ///
-    /// For all known types, see
-    /// [Types](#struct.Types)
-    #[derive(Debug)]
-    pub struct InvalidMessageType;
 
-    impl fmt::Display for InvalidMessageType {
-        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-            write!(f, "Invalid message type")
-        }
-    }
-
-    // see https://doc.rust-lang.org/stable/rust-by-example/error/multiple_error_types/define_error_type.html
-    impl error::Error for InvalidMessageType {
-        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-            None
-        }
-    }
+    /// Latest timestamp a message was stamped with, in milliseconds since the unix epoch
+    static LAST_SERVER_TS: AtomicU64 = AtomicU64::new(0);
 
-    /// Get type of any message struct
+    /// All outgoing messages
     ///
-    /// Ensuring that every struct representing a message skeleton implements the same basic
-    /// functions to return the message type
-    pub trait GetMessageType {
-        fn get_type(&self) -> Result<Types, InvalidMessageType>;
-    }
-
-    /// All known types of incoming messages
-    #[derive(Debug, Serialize)]
-    #[serde(rename_all = "lowercase")]
-    pub enum Types {
-        User,
+    /// Serialized as the wrapped message skeleton with an additional `type` field holding the
+    /// lowercase variant name and a `server_ts` field holding the time the message was
+    /// serialized, in milliseconds since the unix epoch.
+    #[derive(Serialize)]
+    #[serde(tag = "type", rename_all = "lowercase")]
+    pub enum Message {
+        User(User),
         // {
         //     "type": "joined",
         //     "object" : {
@@ -202,18 +272,28 @@ pub mod outbound {
         //         "id": user_id,
         //         "elevated": elevated
         //     }
-        All,
+        All(All),
         // {
         //     "type": "all",
         //     "raised": room.raised,
         //     "joined": room.connected,
         // }
-        SelfStatus, // r#Self is restricted https://internals.rust-lang.org/t/raw-identifiers-dont-work-for-all-identifiers/9094/3
+        ServerShutdown(ServerShutdown),
+        // {
+        //     "type": "servershutdown",
+        //     "grace_secs": seconds_until_shutdown,
+        // }
+        ResumeToken(ResumeToken),
+        // {
+        //     "type": "resumetoken",
+        //     "object": token,
+        // }
+        SelfStatus(PermissionChange), // r#Self is restricted https://internals.rust-lang.org/t/raw-identifiers-dont-work-for-all-identifiers/9094/3
         // {
         //     "type": "self",
         //     "id": user_id,
         // },
-        Raised,
+        Raised(OwnedObject),
         // {
         //     "type": "raised",
         //     "owner_id": msg.owner_id,
@@ -221,7 +301,7 @@ pub mod outbound {
         //     "object": &msg.object,
         //     "elevated": elevated,
         // }
-        Lower,
+        Lower(OwnedObject),
         // {
         //     "type": "lower",
         //     "owner_id": msg.owner_id,
@@ -229,48 +309,50 @@ pub mod outbound {
         //     "object": msg.object,
         //     "elevated": elevated,
         // });
-        Instant,
+        Instant(Instant),
         // {
         //     "type": "instant",
+        //     "id": message_id,
         //     "owner_id": msg.owner_id,
         //     "owner_name": msg.owner_name,
         //     "object": msg.object,
         //     "elevated": elevated,
+        //     "reply_to": parent_message_id | null,
         // }
-        Elevated,
+        Elevated(PermissionChange),
         // {
         //     "type": "elevated",
         //     "object": msg.object,
         // }
-        Receded,
+        Receded(PermissionChange),
         // {
         //     "type": "receded",
         //     "object": msg.object,
         // }
-        Error,
+        Error(Error),
         // {
         //     "type": "error",
         //     "object": "error description",
         // }
-        VoteDelete,
+        VoteDelete(VoteDelete),
         // {
         //      "type": "deletevote",
         //      "pollobject": poll.title,
         //      "polloptionobject": poll_option_title,
         //      "userid": user_id, // or 0 in case of not elevated users
         // }
-        Poll,
+        Poll(Poll),
         // {
         //     "type": "poll",
         //     "object": "amazing poll title",
         // }
-        PollOption,
+        PollOption(PollOption),
         // {
-        //     "type": "poll",
+        //     "type": "polloption",
         //     "pollobject": "amazing poll title",
         //     "polloptionobject": "amazing poll-option title",
         // }
-        Vote,
+        Vote(Vote),
         // {
         //      "type": "vote",
         //      "pollobject": poll_title,
@@ -278,17 +360,269 @@ pub mod outbound {
         //      "username": vote.owner_name, // or 0 in case of not elevated user
         //      "userid": vote.owner_id, // or "" in case of not elevated user
         // }
-        PollClose,
+        PollClose(PollClose),
         // {
         //      "type": "closepoll",
         //      "pollobject": poll.title,
         // }
+        PollsClosed(PollsClosed),
+        // {
+        //      "type": "pollsclosed",
+        //      "object": [poll.title, ...],
+        // }
+        PollResults(PollResults),
+        // {
+        //      "type": "pollresults",
+        //      "pollobject": poll.title,
+        //      "object": { poll_option_title: { "count": vote_count, "percent": vote_share } },
+        // }
+        PollOptions(PollOptions),
+        // {
+        //      "type": "polloptions",
+        //      "pollobject": poll.title,
+        //      "object": [poll_option_title, ...],
+        // }
+        PollDeleted(PollClose),
+        // {
+        //      "type": "polldeleted",
+        //      "object": poll.title,
+        // }
+        PollRenamed(PollRenamed),
+        // {
+        //      "type": "pollrenamed",
+        //      "pollobject": old_title,
+        //      "object": new_title,
+        // }
+        VoteAck(VoteAck),
+        // {
+        //      "type": "voteack",
+        //      "pollobject": poll_title,
+        //      "polloptionobject": poll_option_title,
+        // }
+        VoteNack(VoteNack),
+        // {
+        //      "type": "votenack",
+        //      "pollobject": poll_title,
+        //      "polloptionobject": poll_option_title,
+        //      "object": "error_code",
+        //      "description": "error description",
+        // }
+        HandQueue(HandQueue),
+        // {
+        //      "type": "handqueue",
+        //      "object": [{ "object": ..., "owner_id": ..., "owner_name": ..., "raised_at": ..., "reason": ... }, ...],
+        // }
+        SpeakingOrder(SpeakingOrder),
+        // {
+        //      "type": "speakingorder",
+        //      "object": [{ "user_id": ..., "user_name": ..., "elevated": ..., "raised_at": ... }, ...],
+        // }
+        Roster(Roster),
+        // {
+        //      "type": "roster",
+        //      "object": [{ "user_id": user_id, "user_name": user_name, "spectator": spectator, "color": "#ff8800", "avatar": url | null }, ...],
+        //      "host_id": host_id,
+        // }
+        Count(Count),
+        // {
+        //      "type": "count",
+        //      "object": number_of_members,
+        // }
+        UserJoined(RosterChange),
+        // {
+        //      "type": "userjoined",
+        //      "object": { "user_id": user_id, "user_name": user_name, "spectator": spectator, "color": "#ff8800", "avatar": url | null },
+        // }
+        RoomFull(Error),
+        // {
+        //      "type": "roomfull",
+        //      "object": room_name,
+        //      "description": "error description",
+        // }
+        RoomLimit(Error),
+        // {
+        //      "type": "roomlimit",
+        //      "object": room_name,
+        //      "description": "error description",
+        // }
+        Capacity(Error),
+        // {
+        //      "type": "capacity",
+        //      "object": room_name,
+        //      "description": "error description",
+        // }
+        AuthFailed(Error),
+        // {
+        //      "type": "authfailed",
+        //      "object": room_name,
+        //      "description": "error description",
+        // }
+        UserLeft(UserLeft),
+        // {
+        //      "type": "userleft",
+        //      "object": { "user_id": user_id, "user_name": user_name, "spectator": spectator, "color": "#ff8800", "avatar": url | null },
+        //      "reason": "clientclose" | "timeout" | "kicked" | "servershutdown" | "slowconsumer" | "idle" | "left" | "superseded",
+        // }
+        Kicked(Error),
+        // {
+        //      "type": "kicked",
+        //      "object": room_name,
+        //      "description": "description",
+        // }
+        Superseded(Error),
+        // {
+        //      "type": "superseded",
+        //      "object": room_name,
+        //      "description": "description",
+        // }
+        Muted(Error),
+        // {
+        //      "type": "muted",
+        //      "object": room_name,
+        //      "description": "description",
+        // }
+        WeightChanged(WeightChanged),
+        // {
+        //      "type": "weightchanged",
+        //      "user_id": user_id,
+        //      "object": weight,
+        // }
+        Unmuted(Error),
+        // {
+        //      "type": "unmuted",
+        //      "object": room_name,
+        //      "description": "description",
+        // }
+        HostChanged(PermissionChange),
+        // {
+        //      "type": "hostchanged",
+        //      "object": host_id,
+        // }
+        NameTaken(Error),
+        // {
+        //      "type": "nametaken",
+        //      "object": user_name,
+        //      "description": "error description",
+        // }
+        Typing(OwnedObject),
+        // {
+        //     "type": "typing",
+        //     "owner_id": msg.owner_id,
+        //     "owner_name": msg.owner_name,
+        //     "object": active,
+        //     "elevated": elevated,
+        // }
+        InstantExpired(InstantExpired),
+        // {
+        //     "type": "instantexpired",
+        //     "id": message_id,
+        // }
+        Edited(Instant),
+        // {
+        //     "type": "edited",
+        //     "id": message_id,
+        //     "owner_id": msg.owner_id,
+        //     "owner_name": msg.owner_name,
+        //     "object": new_object,
+        //     "elevated": elevated,
+        //     "reply_to": parent_message_id | null,
+        // }
+        Reactions(Reactions),
+        // {
+        //      "type": "reactions",
+        //      "target": poll_title or message_id,
+        //      "object": { emoji: count, ... },
+        // }
+        RateLimited(Error),
+        // {
+        //      "type": "ratelimited",
+        //      "object": "rate_limited",
+        //      "description": "error description",
+        // }
+        SlowConsumer(Error),
+        // {
+        //      "type": "slowconsumer",
+        //      "object": "slow_consumer",
+        //      "description": "error description",
+        // }
+        Idle(Error),
+        // {
+        //      "type": "idle",
+        //      "object": "idle",
+        //      "description": "error description",
+        // }
+        History(History),
+        // {
+        //      "type": "history",
+        //      "object": [{ "object": ..., "owner_id": ..., "owner_name": ..., "elevated": ..., "sent_at": ..., "reply_to": ... }, ...],
+        // }
+        Direct(Direct),
+        // {
+        //      "type": "direct",
+        //      "owner_id": sender_id,
+        //      "owner_name": sender_name,
+        //      "target_id": target_id,
+        //      "object": msg.object,
+        // }
+        RoomOptions(RoomOptions),
+        // {
+        //      "type": "roomoptions",
+        //      "object": { "host_only_polls": bool },
+        // }
+        PollSnapshot(PollSnapshot),
+        // {
+        //      "type": "pollsnapshot",
+        //      "object": [{ "title": ..., "options": [...], "results": {...}, "multi": ..., "anonymous": ..., "closed": false }, ...],
+        // }
+        ServerTime(ServerTime),
+        // {
+        //      "type": "servertime",
+        //      "object": server_time_ms,
+        // }
+        OwnVotes(OwnVotes),
+        // {
+        //      "type": "ownvotes",
+        //      "object": { poll_title: [option_title, ...], ... },
+        // }
+        Announcement(Announcement),
+        // {
+        //      "type": "announcement",
+        //      "object": "message of the operator",
+        // }
+        Hello(Hello),
+        // {
+        //      "type": "hello",
+        //      "version": "0.1.0",
+        //      "capabilities": ["polls", "reactions", "direct", ...],
+        //      "is_host": is_host,
+        // }
+    }
+
+    impl fmt::Display for Message {
+        /// Serialize the message to JSON as it is sent to clients, stamped with `server_ts`
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let mut json = serde_json::to_value(self).map_err(|_| fmt::Error)?;
+            if let Some(json) = json.as_object_mut() {
+                json.insert("server_ts".to_string(), server_ts().into());
+            }
+            write!(f, "{}", json)
+        }
+    }
+
+    /// current time in milliseconds since the unix epoch, never less than a previous result
+    ///
+    /// The system clock may be set back, messages still have to stay in order for clients.
+    pub fn server_ts() -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        LAST_SERVER_TS.fetch_max(now, Ordering::Relaxed).max(now)
     }
 
     /// Message skeleton containing the current state of a room
     #[derive(Serialize)]
     pub struct All {
-        pub r#type: Types,
         pub raised: Vec<server::Raised>,
         pub joined: HashMap<usize, server::User>,
     }
@@ -300,59 +634,133 @@ pub mod outbound {
         pub elevated: bool,
     }
 
+    /// Member of a room as listed in the roster
+    /// # Parameters
+    /// * `color` - Hex color the user is shown in, like `#ff8800`
+    /// * `avatar` - URL of the user's avatar image, `null` if none was given
+    #[derive(Serialize)]
+    pub struct RosterEntry {
+        pub user_id: usize,
+        pub user_name: String,
+        pub spectator: bool,
+        pub color: String,
+        pub avatar: Option<String>,
+    }
+
+    /// Message skeleton listing every member of a room, ordered by user ID, and its host
+    #[derive(Serialize)]
+    pub struct Roster {
+        pub object: Vec<RosterEntry>,
+        pub host_id: usize,
+    }
+
+    /// Message skeleton of the number of members of a room, spectators included
+    #[derive(Serialize)]
+    pub struct Count {
+        pub object: usize,
+    }
+
+    /// Message skeleton announcing a member joining the room
+    #[derive(Serialize)]
+    pub struct RosterChange {
+        pub object: RosterEntry,
+    }
+
+    /// Message skeleton announcing a member leaving the room
+    /// # Parameters
+    /// * `object` - The member that left
+    /// * `reason` - Why the member left
+    #[derive(Serialize)]
+    pub struct UserLeft {
+        pub object: RosterEntry,
+        pub reason: server::LeaveReason,
+    }
+
     /// Message skeleton containing the current state of a user
     #[derive(Serialize)]
     pub struct User {
-        pub r#type: Types,
         pub object: UserFormat,
     }
 
     /// Message skeleton representing an object an its metadata
     /// # Parameters
-    /// * `type` - Message type. Expected: Raised, Lowered, Instant
     /// * `owner_id` - Owner's user ID
     /// * `owner_name` - Owner's name
     /// * `object` - The represented object
     #[derive(Serialize)]
     pub struct OwnedObject {
-        pub r#type: Types,
         pub owner_id: usize,
         pub owner_name: String,
         pub object: Arbitrary,
         pub elevated: bool,
     }
 
+    /// Message skeleton of a direct message between two users
+    /// # Parameters
+    /// * `owner_id` - Sender's user ID
+    /// * `owner_name` - Sender's name
+    /// * `target_id` - Recipient's user ID
+    /// * `object` - The message
+    #[derive(Serialize)]
+    pub struct Direct {
+        pub owner_id: usize,
+        pub owner_name: String,
+        pub target_id: usize,
+        pub object: Arbitrary,
+    }
+
+    /// Message skeleton representing an instant message
+    /// # Parameters
+    /// * `id` - Message ID, unique within the room
+    /// * `owner_id` - Owner's user ID
+    /// * `owner_name` - Owner's name
+    /// * `object` - The message
+    #[derive(Serialize)]
+    pub struct Instant {
+        pub id: usize,
+        pub owner_id: usize,
+        pub owner_name: String,
+        pub object: Arbitrary,
+        pub elevated: bool,
+        pub reply_to: Option<usize>,
+    }
+
+    /// Message skeleton announcing that an instant message reached its TTL
+    /// # Parameters
+    /// * `id` - ID of the expired instant message
+    #[derive(Serialize)]
+    pub struct InstantExpired {
+        pub id: usize,
+    }
+
     /// Message skeleton to change a user's permissions
     /// # Parameters
-    /// * `type` - Message type. Exprected: Elevated, Receded
     /// * `object` - Target user's ID
     #[derive(Serialize)]
     pub struct PermissionChange {
-        pub r#type: Types,
         pub object: usize,
     }
 
     /// Message skeleton to send an error
     /// # Parameters
-    /// * `type` - Message type. Exprected: Error
     /// * `object` - Error Code
     /// * `description` - Error Description
+    /// * `ack_id` - ID given by the client with the message causing the error, if any
     #[derive(Serialize)]
     pub struct Error {
-        pub r#type: Types,
         pub object: String,
         pub description: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
     }
 
     /// Message skeleton to delete a user's vote
     /// # Parameters
-    /// * `type` - Message type. Exprected: VoteDelete
     /// * `pollobject` - Title of the poll
     /// * `polloptionobject` - Title of the poll-option
     /// * `userid` - ID of the user (or 0 is the receiver is not elevated)
     #[derive(Serialize)]
     pub struct VoteDelete {
-        pub r#type: Types,
         pub pollobject: String,
         pub polloptionobject: String,
         pub userid: usize,
@@ -360,49 +768,351 @@ pub mod outbound {
 
     // Message skeleton to send a poll
     /// # Parameters
-    /// * `type` - Message type. Exprected: Poll
     /// * `object` - Title of the poll
     #[derive(Serialize)]
     pub struct Poll {
-        pub r#type: Types,
         pub object: String,
     }
 
-    // Message skeleton to send a poll-option
+    // Message skeleton to send a poll-option, broadcast to the whole room once it was added;
+    // options with a title already taken in the poll are refused and never broadcast
     /// # Parameters
-    /// * `type` - Message type. Exprected: PollOption
     /// * `pollobject` - Title of the poll
     /// * `polloptionobject` - Title of the poll-option
     #[derive(Serialize)]
     pub struct PollOption {
-        pub r#type: Types,
         pub pollobject: String,
         pub polloptionobject: String,
     }
 
     // Message skeleton to send a vote
     /// # Parameters
-    /// * `type` - Message type. Exprected: Vote
     /// * `pollobject` - Title of the poll
     /// * `polloptionobject` - Title of the poll-option
     /// * `username` - Name of the voting-user (or "" if the receiver is not elevated)
     /// * `userid` - ID of the voting-user (or 0 if the receiver is not elevated)
     #[derive(Serialize)]
     pub struct Vote {
-        pub r#type: Types,
         pub pollobject: String,
         pub polloptionobject: String,
         pub username: String,
         pub userid: usize,
     }
 
-    // Message skeleton to close a poll
+    // Message skeleton to close or delete a poll
     /// # Parameters
-    /// * `type` - Message type. Exprected: PollClose
     /// * `object` - Title of the poll
     #[derive(Serialize)]
     pub struct PollClose {
-        pub r#type: Types,
         pub object: String,
     }
+
+    /// Message skeleton announcing polls closed at once
+    /// # Parameters
+    /// * `object` - Titles of the polls
+    #[derive(Serialize)]
+    pub struct PollsClosed {
+        pub object: Vec<String>,
+    }
+
+    /// Message skeleton announcing a poll's new title
+    /// # Parameters
+    /// * `pollobject` - Previous title of the poll
+    /// * `object` - New title of the poll
+    #[derive(Serialize)]
+    pub struct PollRenamed {
+        pub pollobject: String,
+        pub object: String,
+    }
+
+    /// Result of a single poll-option
+    /// # Parameters
+    /// * `count` - Number of votes for the poll-option
+    /// * `percent` - Share of all votes in the poll, rounded to one decimal
+    #[derive(Serialize, Clone, Debug, PartialEq)]
+    pub struct PollOptionResult {
+        pub count: usize,
+        pub percent: f64,
+    }
+
+    // Message skeleton to send the current results of a poll
+    /// # Parameters
+    /// * `pollobject` - Title of the poll
+    /// * `object` - Result of each poll-option, keyed by the poll-option's title
+    #[derive(Serialize)]
+    pub struct PollResults {
+        pub pollobject: String,
+        pub object: HashMap<String, PollOptionResult>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    // Message skeleton to send all options of a poll, broadcast after an option was removed and
+    // sent to a single client asking with `polloptionsquery`
+    /// # Parameters
+    /// * `pollobject` - Title of the poll
+    /// * `object` - Titles of the poll-options in the order they were added
+    /// * `ack_id` - ID given by the client with the message causing this one, if any
+    #[derive(Serialize)]
+    pub struct PollOptions {
+        pub pollobject: String,
+        pub object: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    // Message skeleton to acknowledge a recorded vote
+    /// # Parameters
+    /// * `pollobject` - Title of the poll
+    /// * `polloptionobject` - Title of the poll-option
+    #[derive(Serialize)]
+    pub struct VoteAck {
+        pub pollobject: String,
+        pub polloptionobject: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    // Message skeleton to reject a vote
+    /// # Parameters
+    /// * `pollobject` - Title of the poll
+    /// * `polloptionobject` - Title of the poll-option
+    /// * `object` - Error Code
+    /// * `description` - Error Description
+    /// * `ack_id` - ID given by the client with the message causing the error, if any
+    #[derive(Serialize)]
+    pub struct VoteNack {
+        pub pollobject: String,
+        pub polloptionobject: String,
+        pub object: String,
+        pub description: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    // Message skeleton to send the queue of raised objects, broadcast on every change and sent
+    // to a single client asking with `handqueuequery`
+    /// # Parameters
+    /// * `object` - Raised objects in the order they were raised
+    #[derive(Serialize)]
+    pub struct HandQueue {
+        pub object: Vec<server::Raised>,
+    }
+
+    // Message skeleton to send the order in which users with raised objects get to speak,
+    // broadcast whenever the queue or the elevation of a user in it changes
+    /// # Parameters
+    /// * `object` - Users in speaking order
+    #[derive(Serialize)]
+    pub struct SpeakingOrder {
+        pub object: Vec<SpeakingOrderEntry>,
+    }
+
+    /// A user waiting to speak
+    /// # Parameters
+    /// * `user_id` - ID of the user
+    /// * `user_name` - Name of the user
+    /// * `elevated` - Whether the user is elevated, which moves them ahead
+    /// * `raised_at` - Time the user's earliest raised object was raised, in milliseconds since
+    ///   the unix epoch
+    #[derive(Serialize)]
+    pub struct SpeakingOrderEntry {
+        pub user_id: usize,
+        pub user_name: String,
+        pub elevated: bool,
+        pub raised_at: u64,
+    }
+
+    /// Message skeleton replaying the latest instant messages of a room
+    /// # Parameters
+    /// * `object` - Instant messages, oldest first
+    #[derive(Serialize)]
+    pub struct History {
+        pub object: Vec<server::HistoryEntry>,
+    }
+
+    /// Current state of a poll, without the identities of voters
+    /// # Parameters
+    /// * `title` - Title of the poll
+    /// * `options` - Titles of the poll-options in the order they were added
+    /// * `results` - Result of each poll-option, keyed by the poll-option's title
+    /// * `multi` - Whether users may vote for several options
+    /// * `anonymous` - Whether identities of voters are hidden
+    /// * `closed` - Whether the poll is closed
+    #[derive(Serialize)]
+    pub struct PollSnapshotEntry {
+        pub title: String,
+        pub options: Vec<String>,
+        pub results: HashMap<String, PollOptionResult>,
+        pub multi: bool,
+        pub anonymous: bool,
+        pub closed: bool,
+    }
+
+    /// Message skeleton telling a user and the host the voting weight the host gave the user
+    /// # Parameters
+    /// * `user_id` - User whose weight changed
+    /// * `object` - New weight, counted for each vote the user casts from now on
+    #[derive(Serialize)]
+    pub struct WeightChanged {
+        pub user_id: usize,
+        pub object: usize,
+    }
+
+    /// Message skeleton containing the options the host set for a room
+    /// # Parameters
+    /// * `object` - All options with their current values
+    #[derive(Serialize)]
+    pub struct RoomOptions {
+        pub object: server::RoomOptions,
+    }
+
+    /// Message skeleton handing a joining user all open polls of a room
+    /// # Parameters
+    /// * `object` - Open polls in the order they were created
+    #[derive(Serialize)]
+    pub struct PollSnapshot {
+        pub object: Vec<PollSnapshotEntry>,
+    }
+
+    /// Message skeleton containing the reaction counts of a poll or message
+    /// # Parameters
+    /// * `target` - Poll title or message id the reactions belong to
+    /// * `object` - Number of users per emoji
+    #[derive(Serialize)]
+    pub struct Reactions {
+        pub target: String,
+        pub object: HashMap<String, usize>,
+    }
+
+    /// Message skeleton handing the client the token to resume its session
+    /// # Parameters
+    /// * `object` - Token to pass as `token` query parameter when reconnecting
+    #[derive(Serialize)]
+    pub struct ResumeToken {
+        pub object: String,
+    }
+
+    /// Message skeleton warning clients that the server is about to shut down
+    /// # Parameters
+    /// * `grace_secs` - Seconds until the server stops
+    #[derive(Serialize)]
+    pub struct ServerShutdown {
+        pub grace_secs: u64,
+    }
+
+    /// Message skeleton telling a resumed session which votes of it still count
+    /// # Parameters
+    /// * `object` - Titles of the voted poll-options by poll title
+    #[derive(Serialize)]
+    pub struct OwnVotes {
+        pub object: HashMap<String, Vec<String>>,
+    }
+
+    /// Message skeleton of an operator's announcement to all connected users
+    /// # Parameters
+    /// * `object` - Text of the announcement
+    #[derive(Serialize)]
+    pub struct Announcement {
+        pub object: String,
+    }
+
+    /// Message skeleton introducing the server to a client that joined a room
+    /// # Parameters
+    /// * `version` - Version of the server
+    /// * `capabilities` - Features the server supports with its current configuration
+    /// * `is_host` - Whether the client is the host of the room it joined
+    #[derive(Serialize)]
+    pub struct Hello {
+        pub version: &'static str,
+        pub capabilities: Vec<&'static str>,
+        pub is_host: bool,
+    }
+
+    /// Message skeleton answering a `servertime` request, to estimate the clock offset
+    /// # Parameters
+    /// * `object` - Current server time in milliseconds since the unix epoch
+    /// * `ack_id` - ID given by the client with the request, if any
+    #[derive(Serialize)]
+    pub struct ServerTime {
+        pub object: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub ack_id: Option<Arbitrary>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        /// Serialize a message as it is sent, without the `server_ts` stamp
+        fn wire(message: Message) -> Arbitrary {
+            let mut json: Arbitrary = serde_json::from_str(&message.to_string()).unwrap();
+            assert!(json["server_ts"].is_u64());
+            json.as_object_mut().unwrap().remove("server_ts");
+            json
+        }
+
+        #[test]
+        fn roster_has_the_shape_clients_expect() {
+            let message = Message::Roster(Roster {
+                object: vec![RosterEntry {
+                    user_id: 1,
+                    user_name: "alice".to_string(),
+                    spectator: false,
+                    color: "#ff8800".to_string(),
+                    avatar: None,
+                }],
+                host_id: 1,
+            });
+            assert_eq!(
+                wire(message),
+                json!({
+                    "type": "roster",
+                    "object": [{
+                        "user_id": 1,
+                        "user_name": "alice",
+                        "spectator": false,
+                        "color": "#ff8800",
+                        "avatar": null,
+                    }],
+                    "host_id": 1,
+                })
+            );
+        }
+
+        #[test]
+        fn error_carries_the_ack_id_only_when_given() {
+            let error = |ack_id| {
+                Message::Error(Error {
+                    object: "unknown_poll".to_string(),
+                    description: "No such poll".to_string(),
+                    ack_id,
+                })
+            };
+            assert_eq!(
+                wire(error(None)),
+                json!({
+                    "type": "error",
+                    "object": "unknown_poll",
+                    "description": "No such poll",
+                })
+            );
+            assert_eq!(wire(error(Some(json!(7))))["ack_id"], json!(7));
+        }
+
+        #[test]
+        fn messages_in_sequence_have_non_decreasing_timestamps() {
+            let stamp = || {
+                let message = Message::PollClose(PollClose {
+                    object: "lunch".to_string(),
+                });
+                let json: Arbitrary = serde_json::from_str(&message.to_string()).unwrap();
+                json["server_ts"].as_u64().unwrap()
+            };
+            let stamps: Vec<u64> = (0..100).map(|_| stamp()).collect();
+            assert!(stamps.windows(2).all(|pair| pair[0] <= pair[1]));
+            assert!(server_ts() >= stamps[99]);
+        }
+    }
 }
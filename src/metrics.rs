@@ -0,0 +1,77 @@
+//! Counters describing the load of the server, served at `/metrics`.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Number of sessions currently joined to a room and of rooms with at least one participant,
+/// per web socket server
+static GAUGES: Mutex<Vec<(usize, usize)>> = Mutex::new(Vec::new());
+/// Number of polls created since startup
+static POLLS: AtomicUsize = AtomicUsize::new(0);
+/// Number of inbound messages processed since startup
+static MESSAGES: AtomicUsize = AtomicUsize::new(0);
+
+/// Current values of all counters
+#[derive(Serialize)]
+pub struct Snapshot {
+    pub connections: usize,
+    pub rooms: usize,
+    pub polls: usize,
+    pub messages: usize,
+}
+
+/// Set the number of connected sessions and active rooms of a web socket server
+///
+/// Both are gauges owned by the web socket server, which sets them from its state after every
+/// change instead of counting up and down, so they can't drift or go negative. The snapshot
+/// sums them up across all servers.
+///
+/// # Arguments
+///
+/// * `shard` - index of the web socket server
+/// * `connections` - number of sessions of the server
+/// * `rooms` - number of active rooms of the server
+pub fn set_connections(shard: usize, connections: usize, rooms: usize) {
+    let mut gauges = GAUGES.lock().unwrap_or_else(|e| e.into_inner());
+    if gauges.len() <= shard {
+        gauges.resize(shard + 1, (0, 0));
+    }
+    gauges[shard] = (connections, rooms);
+}
+
+/// Number of sessions connected to a web socket server
+///
+/// Tests run side by side in one process, the sum in the snapshot is shared by all of them.
+#[cfg(test)]
+pub fn connections_of(shard: usize) -> usize {
+    let gauges = GAUGES.lock().unwrap_or_else(|e| e.into_inner());
+    gauges.get(shard).map_or(0, |gauge| gauge.0)
+}
+
+/// Count a created poll
+pub fn poll_created() {
+    POLLS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Count a processed inbound message
+pub fn message_processed() {
+    MESSAGES.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Read all counters
+pub fn snapshot() -> Snapshot {
+    let (connections, rooms) = GAUGES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .fold((0, 0), |(connections, rooms), gauge| {
+            (connections + gauge.0, rooms + gauge.1)
+        });
+    Snapshot {
+        connections,
+        rooms,
+        polls: POLLS.load(Ordering::Relaxed),
+        messages: MESSAGES.load(Ordering::Relaxed),
+    }
+}
@@ -3,22 +3,85 @@
 //! room through `WebSocketServer`.
 
 use actix::prelude::*;
+use actix_rt::time::delay_for;
+use actix_web::client::Client;
+use log::{debug, error, info, warn};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use serde::Serialize;
 use serde_json::{json, Value as Arbitrary};
-use std::collections::HashMap;
-
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::Config;
 use crate::messages;
+use crate::metrics;
 
 /// web socket server sends this messages to session
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Message(pub String);
 
+/// web socket server sends this message to make a session send a last message and
+/// disconnect
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Close(pub String);
+
+/// Warn every connected session, regardless of its room, that the server is shutting down
+///
+/// Sessions disconnecting from now on leave their rooms with `LeaveReason::ServerShutdown`.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ShutdownNotice(pub String);
+
+/// Send a serialized message to every session of every room
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct GlobalBroadcast(pub String);
+
+/// Why a user left a room, sent to the remaining members
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LeaveReason {
+    /// the client closed the connection
+    ClientClose,
+    /// the client stopped answering heartbeats
+    Timeout,
+    /// the host removed the user
+    Kicked,
+    /// the server is shutting down
+    ServerShutdown,
+    /// the client didn't read its messages fast enough
+    SlowConsumer,
+    /// the client didn't send any message for too long
+    Idle,
+    /// the user left for another room, keeping the connection
+    Left,
+    /// a newer session with the same name took over
+    Superseded,
+}
+
 /// Session is disconnected
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Disconnect {
     pub id: usize,
+    pub reason: LeaveReason,
+}
+
+/// Session leaves its room but keeps the connection open to join another one
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct LeaveRoom {
+    pub id: usize,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -27,6 +90,7 @@ pub struct Elevate {
     pub object: usize,
     pub owner_id: usize,
     pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -35,6 +99,48 @@ pub struct Recede {
     pub object: usize,
     pub owner_id: usize,
     pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Remove a user from a room, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Kick {
+    pub user_id: usize,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Stop a user from chatting, raising and taking part in polls without removing them, only the
+/// host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Mute {
+    pub user_id: usize,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Let a muted user take part again, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct Unmute {
+    pub user_id: usize,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Hand the host role of a room to another user, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct TransferHost {
+    pub user_id: usize,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 /// Send message to specific room
@@ -49,32 +155,89 @@ pub struct ClientMessage {
     pub room: String,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Default)]
 pub struct Room {
     raised: Vec<Raised>,
     polls: Vec<Poll>,
     connected: HashMap<usize, User>,
+    host_id: usize,                  // user id of the room's host, 0 if there is none
+    password_hash: Option<Vec<u8>>,  // set by the first joiner, None for open rooms
+    history: VecDeque<HistoryEntry>, // latest instant messages, oldest first
+    typing: HashMap<usize, (bool, u64)>, // last broadcast typing state and its time per user
+    hand_toggled: HashMap<usize, u64>, // time of the last raise or lower per user
+    reactions: HashMap<String, HashMap<String, HashSet<usize>>>, // target -> emoji -> user ids
+    last_message_id: usize,          // id of the latest instant message, ids start at 1
+    creator_ip: Option<IpAddr>,      // client IP the room counts against, if known
+    options: RoomOptions,
+    transcript: VecDeque<TranscriptEntry>, // latest events for the transcript, oldest first
+}
+
+/// Something that happened in a room, as listed in its transcript
+#[derive(Clone, Serialize)]
+pub struct TranscriptEntry {
+    at: u64, // milliseconds since the unix epoch
+    event: &'static str,
+    user_id: Option<usize>, // None for events of the server and votes in anonymous polls
+    user_name: Option<String>,
+    object: Arbitrary,
+}
+
+/// Settings of a room the host may change, reset once the room is reclaimed
+#[derive(Clone, Default, Serialize)]
+pub struct RoomOptions {
+    /// only the host may create polls
+    host_only_polls: bool,
 }
 
 #[derive(Clone, Serialize)]
 pub struct User {
     name: String,
     elevated: bool,
+    spectator: bool,        // only watches, may not vote, raise, elevate or chat
+    muted: bool,            // silenced by the host, the same actions are dropped until unmuted
+    color: String,          // hex color front-ends show the user in
+    avatar: Option<String>, // URL of an avatar image
+    #[serde(skip)]
+    weight: usize, // how much each vote of the user counts, only told to the user and host
 }
 
-impl Default for Room {
-    fn default() -> Room {
-        Room {
-            raised: Vec::new(),
-            polls: Vec::new(),
-            connected: HashMap::new(),
+/// Colors handed out to users who don't pick one, by user id
+const DEFAULT_COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#42d4f4", "#f032e6", "#9a6324",
+];
+
+impl User {
+    /// the user as listed in the roster
+    fn roster_entry(&self, user_id: usize) -> messages::outbound::RosterEntry {
+        messages::outbound::RosterEntry {
+            user_id,
+            user_name: self.name.clone(),
+            spectator: self.spectator,
+            color: self.color.clone(),
+            avatar: self.avatar.clone(),
         }
     }
 }
 
 impl Room {
     fn remove_user(&mut self, user_id: &usize) {
+        self.lower_user(user_id);
+        self.typing.remove(user_id);
+        self.hand_toggled.remove(user_id);
+    }
+
+    /// whether the last raise or lower of a user is at least `cooldown` milliseconds ago
+    fn hand_cooled_down(&self, user_id: &usize, cooldown: u64, now: u64) -> bool {
+        self.hand_toggled
+            .get(user_id)
+            .is_none_or(|toggled| now.saturating_sub(*toggled) >= cooldown)
+    }
+
+    /// lower all objects raised by a user, returns whether anything was lowered
+    fn lower_user(&mut self, user_id: &usize) -> bool {
+        let raised_count = self.raised.len();
         self.raised.retain(|elem| &elem.owner_id != user_id);
+        self.raised.len() != raised_count
     }
 
     fn is_elevated(&self, user_id: &usize) -> Result<bool, &'static str> {
@@ -84,12 +247,74 @@ impl Room {
         }
     }
 
-    fn set_elevated(&mut self, user_id: &usize, elevated: bool) {
-        match self.connected.get_mut(user_id) {
-            None => {
-                return;
+    fn is_host(&self, user_id: &usize) -> bool {
+        self.host_id == *user_id
+    }
+
+    /// id of the user with a name, ignoring case and surrounding whitespace
+    fn user_named(&self, user_name: &str) -> Option<usize> {
+        let normalized_name = user_name.trim().to_lowercase();
+        self.connected
+            .iter()
+            .find(|(_, user)| user.name.trim().to_lowercase() == normalized_name)
+            .map(|(id, _)| *id)
+    }
+
+    fn is_spectator(&self, user_id: &usize) -> bool {
+        self.connected
+            .get(user_id)
+            .is_some_and(|user| user.spectator)
+    }
+
+    fn is_muted(&self, user_id: &usize) -> bool {
+        self.connected.get(user_id).is_some_and(|user| user.muted)
+    }
+
+    /// how much votes of a user count, 1 unless the host changed it
+    fn weight(&self, user_id: &usize) -> usize {
+        self.connected.get(user_id).map_or(1, |user| user.weight)
+    }
+
+    /// ids of the users who may vote, spectators and muted users can't
+    fn voters(&self) -> Vec<usize> {
+        self.connected
+            .iter()
+            .filter(|(_, user)| !user.spectator && !user.muted)
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// users with raised objects, ordered by their earliest raise
+    ///
+    /// Elevated users count as having raised `elevate_weight` milliseconds earlier, so they
+    /// can move ahead of users who raised before them.
+    fn speaking_order(&self, elevate_weight: u64) -> Vec<messages::outbound::SpeakingOrderEntry> {
+        let mut order: Vec<messages::outbound::SpeakingOrderEntry> = Vec::new();
+        // the queue is ordered by raise time, so the first raise of a user is their earliest
+        for raised in &self.raised {
+            if order.iter().any(|entry| entry.user_id == raised.owner_id) {
+                continue;
+            }
+            order.push(messages::outbound::SpeakingOrderEntry {
+                user_id: raised.owner_id,
+                user_name: raised.owner_name.clone(),
+                elevated: self.is_elevated(&raised.owner_id).unwrap_or(false),
+                raised_at: raised.raised_at,
+            });
+        }
+        order.sort_by_key(|entry| {
+            if entry.elevated {
+                entry.raised_at.saturating_sub(elevate_weight)
+            } else {
+                entry.raised_at
             }
-            Some(connected) => connected.elevated = elevated,
+        });
+        order
+    }
+
+    fn set_elevated(&mut self, user_id: &usize, elevated: bool) {
+        if let Some(connected) = self.connected.get_mut(user_id) {
+            connected.elevated = elevated;
         }
     }
 }
@@ -101,6 +326,68 @@ pub struct PollCloseHelper {
     pub sender_name: String,
     pub room_name: String,
     pub poll_title: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Send the options of a poll to a single session
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollOptionsQuery {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollDelete {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Change an option of a room, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct SetRoomOption {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub option: String,
+    pub value: bool,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Change how much the votes of a user count, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct SetWeight {
+    pub user_id: usize,
+    pub weight: usize,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Close all open polls of a room, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct CloseAllPolls {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Change the title of a poll nobody has voted in yet, only its owner may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollRename {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub new_title: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -111,6 +398,7 @@ pub struct PollVoteHelper {
     pub room_name: String,
     pub poll_title: String,
     pub option_title: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -121,6 +409,18 @@ pub struct PollOption {
     pub owner_name: String,
     pub room_name: String,
     pub poll_title: String,
+    #[serde(skip)]
+    pub ack_id: Option<Arbitrary>,
+}
+
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct PollOptionRemove {
+    pub title: String,
+    pub sender_id: usize,
+    pub room_name: String,
+    pub poll_title: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -130,18 +430,91 @@ pub struct Poll {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    #[serde(skip)]
+    pub id: usize, // set by the server, stays the same if the poll is renamed
     pub options: Vec<PollOption>,
-    pub votes: HashMap<usize, String>, // HashMap<user_id, option_title>
+    pub votes: HashMap<usize, Vec<String>>, // HashMap<user_id, option_titles>
+    #[serde(skip)]
+    pub weights: HashMap<usize, usize>, // weight of each voter when they voted
     pub closed: bool,
+    pub multi: bool,                // users may vote for several options
+    pub anonymous: bool,            // identities of voters are never send to clients
+    pub duration_secs: Option<u64>, // poll is closed automatically after this duration
+    pub auto_close_on_full: bool,   // poll is closed automatically once everyone has voted
+    #[serde(skip)]
+    pub ack_id: Option<Arbitrary>,
+}
+
+impl Poll {
+    /// current state of the poll as sent to clients, voter identities are left out
+    fn snapshot(&self) -> messages::outbound::PollSnapshotEntry {
+        messages::outbound::PollSnapshotEntry {
+            title: self.title.clone(),
+            options: self
+                .options
+                .iter()
+                .map(|option| option.title.clone())
+                .collect(),
+            results: self.results(),
+            multi: self.multi,
+            anonymous: self.anonymous,
+            closed: self.closed,
+        }
+    }
+
+    /// count the votes of every poll-option and their share of all votes
+    ///
+    /// Options without any votes are included with a count of 0, votes for titles that aren't
+    /// options of the poll are left out, so they can't add options to the tally. Every vote
+    /// counts with the weight its voter had when voting.
+    fn results(&self) -> HashMap<String, messages::outbound::PollOptionResult> {
+        let mut counts: HashMap<String, usize> = self
+            .options
+            .iter()
+            .map(|option| (option.title.clone(), 0))
+            .collect();
+        for (user_id, option_titles) in &self.votes {
+            let weight = self.weights.get(user_id).copied().unwrap_or(1);
+            for option_title in option_titles {
+                if let Some(count) = counts.get_mut(option_title) {
+                    *count += weight;
+                }
+            }
+        }
+
+        let total: usize = counts.values().sum();
+        counts
+            .into_iter()
+            .map(|(option_title, count)| {
+                let result = messages::outbound::PollOptionResult {
+                    count,
+                    percent: percentage(count, total),
+                };
+                (option_title, result)
+            })
+            .collect()
+    }
+}
+
+/// share of `count` in `total` in percent, rounded to one decimal
+///
+/// Returns 0.0 if `total` is 0.
+fn percentage(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    (count as f64 * 1000.0 / total as f64).round() / 10.0
 }
 
 #[derive(Message, Serialize, Clone)]
 #[rtype(result = "()")]
 pub struct Raise {
     pub object: Arbitrary,
+    pub reason: Option<String>,
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
 }
 
 #[derive(Message, Serialize, Clone)]
@@ -150,6 +523,20 @@ pub struct Raised {
     pub object: Arbitrary,
     owner_id: usize,
     owner_name: String,
+    raised_at: u64, // milliseconds since the unix epoch
+    reason: Option<String>,
+}
+
+/// An instant message kept in a room's history
+#[derive(Serialize, Clone)]
+pub struct HistoryEntry {
+    id: usize,
+    object: Arbitrary,
+    owner_id: usize,
+    owner_name: String,
+    elevated: bool,
+    sent_at: u64,            // milliseconds since the unix epoch
+    reply_to: Option<usize>, // id of the message this one replies to
 }
 
 impl std::cmp::PartialEq for Raised {
@@ -165,6 +552,24 @@ pub struct Lower {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Lower all raised objects in a room, only the host may do so
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct LowerAll {
+    pub sender_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Send the ordered queue of raised objects to a single session
+#[derive(Message, Serialize, Clone)]
+#[rtype(result = "()")]
+pub struct HandQueueQuery {
+    pub sender_id: usize,
+    pub room_name: String,
 }
 
 #[derive(Message, Serialize, Clone, Debug)]
@@ -174,34 +579,284 @@ pub struct Instant {
     pub owner_id: usize,
     pub owner_name: String,
     pub room_name: String,
+    pub ttl_secs: Option<u64>,   // message expires after this duration
+    pub reply_to: Option<usize>, // id of the message this one replies to
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Change the content of an instant message, only its author may do so
+#[derive(Message, Serialize, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct Edit {
+    pub message_id: usize,
+    pub object: Arbitrary,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Toggle a user's emoji reaction to a poll or message
+#[derive(Message, Serialize, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct React {
+    pub target: String,
+    pub emoji: String,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Take back a user's emoji reaction to a poll or message, if there is one
+#[derive(Message, Serialize, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct Unreact {
+    pub target: String,
+    pub emoji: String,
+    pub owner_id: usize,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// A user started or stopped typing
+#[derive(Message, Serialize, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct Typing {
+    pub active: bool,
+    pub owner_id: usize,
+    pub owner_name: String,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Send a message to a single user of a room
+#[derive(Message, Serialize, Clone, Debug)]
+#[rtype(result = "()")]
+pub struct Direct {
+    pub object: Arbitrary,
+    pub target_id: usize,
+    pub owner_id: usize,
+    pub owner_name: String,
+    pub room_name: String,
+    pub ack_id: Option<Arbitrary>,
+}
+
+/// Why a session isn't let into a room
+#[derive(Clone, Copy, Debug)]
+pub enum RejectionKind {
+    /// the room has reached its maximum size
+    RoomFull,
+    /// the client has created too many rooms
+    RoomLimit,
+    /// the server hosts as many rooms as it may
+    Capacity,
+    /// the password is wrong or missing
+    AuthFailed,
+    /// another user of the room has the name
+    NameTaken,
+    /// the session isn't in a room it could switch from
+    NotInRoom,
+    /// the session is already in the room
+    AlreadyInRoom,
+}
+
+/// A refused join, returned instead of the user id of the session
+pub struct Rejection {
+    pub kind: RejectionKind,
+    /// serialized message telling the session why it was refused
+    pub message: String,
+}
+
+impl Rejection {
+    fn new(kind: RejectionKind, message: messages::outbound::Message) -> Rejection {
+        Rejection {
+            kind,
+            message: message.to_string(),
+        }
+    }
 }
 
 /// Join room, if room does not exists create new one.
+///
+/// Returns the user id of the session, which differs from `user_id` if a previous session
+/// is resumed. If the join is refused, the rejection is returned instead.
 #[derive(Message)]
-#[rtype(result = "()")]
+#[rtype(result = "Result<usize, Rejection>")]
 pub struct Join {
     pub addr: Recipient<Message>,
+    pub close: Recipient<Close>,
     /// Client id
     pub user_id: usize,
     pub user_name: String,
     /// Room name
     pub room_name: String,
+    /// Password of the room, required if the room was created with one
+    pub password: Option<String>,
+    /// Token of a previous session to resume
+    pub token: Option<String>,
+    /// IP address of the client, if known, rooms it creates count against its limit
+    pub ip: Option<IpAddr>,
+    /// Whether the user only watches, spectators never become host or elevated
+    pub spectator: bool,
+    /// Hex color the user is shown in, one is picked by user id if unset
+    pub color: Option<String>,
+    /// URL of the user's avatar image
+    pub avatar: Option<String>,
+}
+
+/// Answered right away, shows that the server still processes its messages
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Ping;
+
+/// List active rooms with their number of participants
+#[derive(Message)]
+#[rtype(result = "HashMap<String, usize>")]
+pub struct ListRooms;
+
+/// Events of a room in the order they happened, `None` if the room doesn't exist
+#[derive(Message)]
+#[rtype(result = "Option<Vec<TranscriptEntry>>")]
+pub struct RoomTranscript {
+    pub room_name: String,
+}
+
+/// List all polls of a room, open and closed, empty if the room doesn't exist
+#[derive(Message)]
+#[rtype(result = "Vec<messages::outbound::PollSnapshotEntry>")]
+pub struct RoomPolls {
+    pub room_name: String,
+}
+
+/// Move a session from its room to another one, as one step
+///
+/// The session keeps its name, but loses elevation and raised objects of the old room.
+/// Returns the id of the session in the new room. If the new room refuses the session, it stays
+/// in its old room and the rejection is returned instead.
+#[derive(Message)]
+#[rtype(result = "Result<usize, Rejection>")]
+pub struct SwitchRoom {
+    /// Client id
+    pub id: usize,
+    pub user_name: String,
+    /// Name of the new room
+    pub room_name: String,
+    /// Password of the new room, required if the room was created with one
+    pub password: Option<String>,
+}
+
+/// Addresses of a connected session
+struct Session {
+    /// id of the user
+    id: usize,
+    /// receives the messages sent to the client
+    addr: Recipient<Message>,
+    /// makes the session disconnect
+    close: Recipient<Close>,
+    /// the web socket server itself, to drop a session that can't keep up
+    server: Recipient<Disconnect>,
+    /// lets the client resume the session after a reconnect
+    token: String,
+    /// IP address of the client, if known
+    ip: Option<IpAddr>,
+    /// whether the user only watches, kept when switching rooms
+    spectator: bool,
+    /// color and avatar given on connect, kept when switching rooms
+    color: Option<String>,
+    avatar: Option<String>,
+}
+
+impl Session {
+    /// queue a message for the client
+    ///
+    /// The mailbox of a session only fills up if its client doesn't read fast enough, such a
+    /// client is disconnected instead of buffering ever more messages for it. The session
+    /// leaves its room right away, it may resume it after reconnecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - a string slice that holds the message to be send
+    fn deliver(&self, message: &str) {
+        if let Err(SendError::Full(_)) = self.addr.try_send(Message(message.to_owned())) {
+            warn!(
+                user_id = self.id,
+                event = "slow_consumer";
+                "Session can't keep up with its messages, disconnecting!"
+            );
+            let slow = messages::outbound::Message::SlowConsumer(messages::outbound::Error {
+                object: "slow_consumer".to_string(),
+                description: "Too many messages are waiting to be sent to you".to_string(),
+                ack_id: None,
+            })
+            .to_string();
+            let _ = self.close.do_send(Close(slow));
+            let _ = self.server.do_send(Disconnect {
+                id: self.id,
+                reason: LeaveReason::SlowConsumer,
+            });
+        }
+    }
+}
+
+/// State of a disconnected session, kept for a while to be resumed
+struct Suspended {
+    user_id: usize,
+    room_name: String,
+    elevated: bool,
+    muted: bool,
+    weight: usize,
+    raised: Vec<Raised>,
+    // poll title -> option titles and the weight they were cast with, of open polls
+    votes: HashMap<String, (Vec<String>, usize)>,
+    disconnected_at: u64, // milliseconds since the unix epoch
 }
 
 /// `WebSocketServer` manages web socket rooms and responsible for coordinating web socket
 /// session. implementation is super primitive
 pub struct WebSocketServer {
-    sessions: HashMap<usize, Recipient<Message>>,
+    sessions: HashMap<usize, Session>,
     rooms: HashMap<String, Room>,
+    suspended: HashMap<String, Suspended>, // resume token -> session state
+    config: Config,
+    shard: usize,        // index among all servers, rooms are spread across them by name
+    shutting_down: bool, // set once sessions have been warned about the shutdown
+    rooms_per_ip: RoomsPerIp,
+    room_count: RoomCount,
+    pending_results: HashSet<(String, String)>, // (room, poll) with results waiting to be sent
+    last_poll_id: usize,                        // id of the latest poll of any room, ids start at 1
 }
 
-impl Default for WebSocketServer {
-    fn default() -> WebSocketServer {
-        let rooms = HashMap::new(); // mut?!
+/// Number of existing rooms created by each client IP, shared by all servers
+pub type RoomsPerIp = Arc<Mutex<HashMap<IpAddr, usize>>>;
+
+/// Number of existing rooms, shared by all servers
+pub type RoomCount = Arc<AtomicUsize>;
 
+impl WebSocketServer {
+    /// create a web socket server without any rooms
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - the settings the server enforces
+    /// * `shard` - index of the server among all servers the rooms are spread across
+    /// * `rooms_per_ip` - room counts per client IP, shared with the other servers
+    /// * `room_count` - number of rooms, shared with the other servers
+    pub fn new(
+        config: Config,
+        shard: usize,
+        rooms_per_ip: RoomsPerIp,
+        room_count: RoomCount,
+    ) -> WebSocketServer {
         WebSocketServer {
             sessions: HashMap::new(),
-            rooms,
+            rooms: HashMap::new(),
+            suspended: HashMap::new(),
+            config,
+            shard,
+            shutting_down: false,
+            rooms_per_ip,
+            room_count,
+            pending_results: HashSet::new(),
+            last_poll_id: 0,
         }
     }
 }
@@ -219,15 +874,15 @@ impl WebSocketServer {
     fn send_message_skip_user(&self, room: &str, message: &str, skip_id: usize) {
         if let Some(room) = self.rooms.get(room) {
             let sessions = &room.connected;
-            for (id, _) in sessions {
+            for id in sessions.keys() {
                 if *id != skip_id {
-                    if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                    if let Some(session) = self.sessions.get(id) {
+                        session.deliver(message);
                     }
                 }
             }
         } else {
-            println!("No room '{}' found", room);
+            debug!(room = room; "No room found");
         }
     }
 
@@ -252,17 +907,13 @@ impl WebSocketServer {
     /// * `user_id` - the user id of the user that should receive the message
     fn send_message_user(&self, room: &str, message: &str, user_id: usize) {
         if let Some(room) = self.rooms.get(room) {
-            let sessions = &room.connected;
-            for (id, _) in sessions {
-                if id == &user_id {
-                    if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
-                    }
-                    break;
+            if room.connected.contains_key(&user_id) {
+                if let Some(session) = self.sessions.get(&user_id) {
+                    session.deliver(message);
                 }
             }
         } else {
-            println!("No room '{}' found", room);
+            debug!(room = room; "No room found");
         }
     }
 
@@ -279,13 +930,13 @@ impl WebSocketServer {
             let sessions = &room.connected;
             for (id, user) in sessions {
                 if user.elevated {
-                    if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                    if let Some(session) = self.sessions.get(id) {
+                        session.deliver(message);
                     }
                 }
             }
         } else {
-            println!("No room '{}' found", room);
+            debug!(room = room; "No room found");
         }
     }
 
@@ -302,13 +953,13 @@ impl WebSocketServer {
             let sessions = &room.connected;
             for (id, user) in sessions {
                 if !user.elevated {
-                    if let Some(addr) = self.sessions.get(id) {
-                        let _ = addr.do_send(Message(message.to_owned()));
+                    if let Some(session) = self.sessions.get(id) {
+                        session.deliver(message);
                     }
                 }
             }
         } else {
-            println!("No room '{}' found", room);
+            debug!(room = room; "No room found");
         }
     }
 
@@ -328,50 +979,354 @@ impl WebSocketServer {
         error_code: &str,
         error_description: &str,
         user_id: usize,
+        ack_id: &Option<Arbitrary>,
     ) {
-        let error_message = json!(messages::outbound::Error {
-            r#type: messages::outbound::Types::Error,
+        let error_message = messages::outbound::Message::Error(messages::outbound::Error {
             object: error_code.to_string(),
             description: error_description.to_string(),
+            ack_id: ack_id.clone(),
         })
         .to_string();
         self.send_message_user(room, &error_message, user_id);
     }
-}
 
-/// Make actor from `WebSocketServer`
-impl Actor for WebSocketServer {
-    /// We are going to use simple Context, we just need ability to communicate
-    /// with other actors.
-    type Context = Context<Self>;
-}
+    /// refuse an action of a spectator or muted user
+    ///
+    /// Spectators get a `forbidden` error, muted users are reminded with a `muted` message.
+    /// Returns whether the action is refused, it must be dropped if so.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room
+    /// * `user_id` - the user id of the acting user
+    /// * `ack_id` - the ack id of the refused message, echoed in the answer
+    fn refuse_action(&self, room: &str, user_id: usize, ack_id: &Option<Arbitrary>) -> bool {
+        let (spectator, muted) = match self.rooms.get(room) {
+            Some(room) => (room.is_spectator(&user_id), room.is_muted(&user_id)),
+            None => return false,
+        };
+        if spectator {
+            self.send_error_user(
+                room,
+                "forbidden",
+                "Spectators can only watch the room",
+                user_id,
+                ack_id,
+            );
+        } else if muted {
+            self.send_muted(room, user_id, true, ack_id);
+        }
+        spectator || muted
+    }
+
+    /// tell a user that the host muted or unmuted them
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - name of the room
+    /// * `user_id` - user id of the (un)muted user
+    /// * `muted` - whether the user is muted now
+    /// * `ack_id` - the ack id to echo, if the message answers an action of the user
+    fn send_muted(&self, room: &str, user_id: usize, muted: bool, ack_id: &Option<Arbitrary>) {
+        let change = messages::outbound::Error {
+            object: room.to_string(),
+            description: if muted {
+                "The host muted you, you can only watch the room".to_string()
+            } else {
+                "The host unmuted you".to_string()
+            },
+            ack_id: ack_id.clone(),
+        };
+        let msg = if muted {
+            messages::outbound::Message::Muted(change)
+        } else {
+            messages::outbound::Message::Unmuted(change)
+        };
+        self.send_message_user(room, &msg.to_string(), user_id);
+    }
+
+    /// publish the number of sessions and active rooms to the metrics
+    fn update_metrics(&self) {
+        let rooms = self
+            .rooms
+            .values()
+            .filter(|room| !room.connected.is_empty())
+            .count();
+        metrics::set_connections(self.shard, self.sessions.len(), rooms);
+    }
+
+    /// tell all users in a room who its host is now
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - name of the room
+    /// * `host_id` - user id of the new host
+    fn send_host_changed(&mut self, room: &str, host_id: usize) {
+        let msg = messages::outbound::Message::HostChanged(messages::outbound::PermissionChange {
+            object: host_id,
+        })
+        .to_string();
+        self.send_message_all(room, &msg);
+    }
+
+    /// tell all users in a room how many members it has now
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - name of the room
+    fn send_count(&mut self, room: &str) {
+        let count = self.rooms.get(room).map_or(0, |room| room.connected.len());
+        let msg = messages::outbound::Message::Count(messages::outbound::Count { object: count })
+            .to_string();
+        self.send_message_all(room, &msg);
+    }
+
+    /// send a vote to all users in a room
+    ///
+    /// Only elevated users receive the name and id of the voting user, and only if the poll
+    /// is not anonymous.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `poll_title` - a string slice with the title of the poll
+    /// * `option_title` - a string slice with the title of the poll-option voted for
+    /// * `user_name` - a string slice with the name of the voting user
+    /// * `user_id` - the user id of the voting user
+    /// * `anonymous` - whether the identity of the voting user must not be send at all
+    fn send_vote(
+        &self,
+        room: &str,
+        poll_title: &str,
+        option_title: &str,
+        user_name: &str,
+        user_id: usize,
+        anonymous: bool,
+    ) {
+        let not_elevated_txt = messages::outbound::Message::Vote(messages::outbound::Vote {
+            pollobject: poll_title.to_string(),
+            polloptionobject: option_title.to_string(),
+            username: "".to_string(),
+            userid: 0,
+        })
+        .to_string();
+        let elevated_txt = if anonymous {
+            not_elevated_txt.clone()
+        } else {
+            messages::outbound::Message::Vote(messages::outbound::Vote {
+                pollobject: poll_title.to_string(),
+                polloptionobject: option_title.to_string(),
+                username: user_name.to_string(),
+                userid: user_id,
+            })
+            .to_string()
+        };
+
+        self.send_message_all_elevated(room, &elevated_txt);
+        self.send_message_all_not_elevated(room, &not_elevated_txt);
+    }
+
+    /// send the ordered queue of raised objects to all users in a room
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    fn send_hand_queue(&mut self, room: &str) {
+        if let Some(txt) = self.hand_queue_message(room) {
+            self.send_message_all(room, &txt);
+        }
+        self.send_speaking_order(room);
+    }
+
+    /// send the order in which users with raised objects get to speak to all users in a room
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    fn send_speaking_order(&mut self, room: &str) {
+        let elevate_weight = self.config.elevate_weight.as_millis() as u64;
+        let order = match self.rooms.get(room) {
+            Some(room) => room.speaking_order(elevate_weight),
+            None => return,
+        };
+
+        let txt = messages::outbound::Message::SpeakingOrder(messages::outbound::SpeakingOrder {
+            object: order,
+        })
+        .to_string();
+        self.send_message_all(room, &txt);
+    }
+
+    /// the `handqueue` message of a room, `None` if the room doesn't exist
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room
+    fn hand_queue_message(&self, room: &str) -> Option<String> {
+        let raised = self.rooms.get(room)?.raised.clone();
+        Some(
+            messages::outbound::Message::HandQueue(messages::outbound::HandQueue {
+                object: raised,
+            })
+            .to_string(),
+        )
+    }
+
+    /// send the deletion of a vote to all users in a room
+    ///
+    /// Only elevated users receive the id of the user whose vote was deleted, and only if the
+    /// poll is not anonymous.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room where the message has to be send
+    /// * `poll_title` - a string slice with the title of the poll
+    /// * `option_title` - a string slice with the title of the poll-option the vote was for
+    /// * `user_id` - the user id of the user whose vote was deleted
+    /// * `anonymous` - whether the identity of the user must not be send at all
+    fn send_vote_delete(
+        &self,
+        room: &str,
+        poll_title: &str,
+        option_title: &str,
+        user_id: usize,
+        anonymous: bool,
+    ) {
+        let not_elevated_txt =
+            messages::outbound::Message::VoteDelete(messages::outbound::VoteDelete {
+                pollobject: poll_title.to_string(),
+                polloptionobject: option_title.to_string(),
+                userid: 0,
+            })
+            .to_string();
+        let elevated_txt = if anonymous {
+            not_elevated_txt.clone()
+        } else {
+            messages::outbound::Message::VoteDelete(messages::outbound::VoteDelete {
+                pollobject: poll_title.to_string(),
+                polloptionobject: option_title.to_string(),
+                userid: user_id,
+            })
+            .to_string()
+        };
+
+        self.send_message_all_elevated(room, &elevated_txt);
+        self.send_message_all_not_elevated(room, &not_elevated_txt);
+    }
+}
+
+/// Make actor from `WebSocketServer`
+impl Actor for WebSocketServer {
+    /// We are going to use simple Context, we just need ability to communicate
+    /// with other actors.
+    type Context = Context<Self>;
+}
 
 /// Handler for Disconnect message.
 impl Handler<Disconnect> for WebSocketServer {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        let mut rooms_leaving: Vec<String> = Vec::new();
+        // forget sessions that can't be resumed anymore
+        let now = now_millis();
+        let grace = self.config.resume_grace.as_millis() as u64;
+        self.suspended
+            .retain(|_, suspended| now.saturating_sub(suspended.disconnected_at) <= grace);
+
+        let reason = if self.shutting_down {
+            LeaveReason::ServerShutdown
+        } else {
+            msg.reason
+        };
+        self.remove_session(msg.id, true, reason);
+    }
+}
+
+impl Handler<LeaveRoom> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: LeaveRoom, _: &mut Context<Self>) {
+        self.remove_session(msg.id, false, LeaveReason::Left);
+    }
+}
+
+impl WebSocketServer {
+    /// remove a session from the server and its room, informing the remaining members
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - id of the session
+    /// * `suspend` - whether the session may be resumed with its token within the grace period
+    /// * `reason` - why the session leaves, told to the remaining members
+    fn remove_session(&mut self, id: usize, suspend: bool, reason: LeaveReason) {
+        let mut rooms_leaving: Vec<(String, messages::outbound::RosterEntry)> = Vec::new();
 
         // remove address
-        if self.sessions.remove(&msg.id).is_some() {
+        if let Some(session) = self.sessions.remove(&id) {
             // remove session from rooms
             for (room_name, room) in &mut self.rooms {
-                if room.connected.remove_entry(&msg.id).is_some() {
-                    rooms_leaving.push(room_name.to_owned());
-                    room.remove_user(&msg.id);
+                if let Some((_, user)) = room.connected.remove_entry(&id) {
+                    // keep the state around in case the client reconnects
+                    if suspend {
+                        self.suspended.insert(
+                            session.token,
+                            Suspended {
+                                user_id: id,
+                                room_name: room_name.to_owned(),
+                                elevated: user.elevated,
+                                muted: user.muted,
+                                weight: user.weight,
+                                raised: room
+                                    .raised
+                                    .iter()
+                                    .filter(|raised| raised.owner_id == id)
+                                    .cloned()
+                                    .collect(),
+                                votes: room
+                                    .polls
+                                    .iter()
+                                    .filter(|poll| !poll.closed)
+                                    .filter_map(|poll| {
+                                        let option_titles = poll.votes.get(&id)?.clone();
+                                        let weight = poll.weights.get(&id).copied().unwrap_or(1);
+                                        Some((poll.title.clone(), (option_titles, weight)))
+                                    })
+                                    .collect(),
+                                disconnected_at: now_millis(),
+                            },
+                        );
+                    }
+
+                    rooms_leaving.push((room_name.to_owned(), user.roster_entry(id)));
+                    room.remove_user(&id);
                     break;
                 }
             }
 
-            for room_name in rooms_leaving {
-                let room = self
-                    .rooms
-                    .entry(room_name.clone())
-                    .or_insert(Room::default());
+            for (room_name, roster_entry) in rooms_leaving {
+                self.record(
+                    &room_name,
+                    "leave",
+                    Some((id, &roster_entry.user_name)),
+                    json!({ "reason": reason }),
+                );
+                let room = self.rooms.entry(room_name.clone()).or_default();
+
+                // promote the longest connected member if the host left, ids are handed
+                // out in ascending order so this is the lowest remaining one
+                let host_changed = room.is_host(&id);
+                if host_changed {
+                    room.host_id = room
+                        .connected
+                        .iter()
+                        .filter(|(_, user)| !user.spectator)
+                        .map(|(id, _)| *id)
+                        .min()
+                        .unwrap_or(0);
+                }
+                let host_id = room.host_id;
 
-                let txt = json!(messages::outbound::All {
-                    r#type: messages::outbound::Types::All,
+                let txt = messages::outbound::Message::All(messages::outbound::All {
                     raised: room.raised.clone(),
                     joined: room.connected.clone(),
                 })
@@ -379,60 +1334,85 @@ impl Handler<Disconnect> for WebSocketServer {
 
                 self.send_message_all(&room_name, txt.as_str());
 
-                let room = self
-                    .rooms
-                    .entry(room_name.clone())
-                    .or_insert(Room::default());
-
-                // get username
-                let user_id = msg.id;
-
-                let mut messages_to_send_to_elevated: Vec<String> = Vec::new();
-                let mut messages_to_send_to_not_elevated: Vec<String> = Vec::new();
-
-                for i in 0..room.polls.clone().len() {
-                    let poll = room.polls[i].clone();
-                    if !poll.closed {
-                        for (id, poll_option_title) in poll.votes {
-                            if id == msg.id {
-                                // delete vote
-                                room.polls[i].votes.remove(&id);
-
-                                // send poll option message to clients
-                                let elevated_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: poll_option_title.clone(),
-                                    userid: user_id,
-                                })
-                                .to_string();
-                                let not_elevated_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: poll_option_title.clone(),
-                                    userid: 0,
-                                })
-                                .to_string();
+                let txt = messages::outbound::Message::UserLeft(messages::outbound::UserLeft {
+                    object: roster_entry,
+                    reason,
+                })
+                .to_string();
 
-                                messages_to_send_to_elevated.push(elevated_txt);
-                                messages_to_send_to_not_elevated.push(not_elevated_txt);
-                            }
+                self.send_message_all(&room_name, txt.as_str());
+                self.send_count(&room_name);
+
+                if host_changed && host_id != 0 {
+                    self.send_host_changed(&room_name, host_id);
+                }
+
+                let room = self.rooms.entry(room_name.clone()).or_default();
+
+                // delete votes of the user in open polls
+                let mut deleted_votes: Vec<(String, String, bool)> = Vec::new();
+                for poll in room.polls.iter_mut().filter(|poll| !poll.closed) {
+                    poll.weights.remove(&id);
+                    if let Some(option_titles) = poll.votes.remove(&id) {
+                        for option_title in option_titles {
+                            deleted_votes.push((poll.title.clone(), option_title, poll.anonymous));
                         }
                     }
                 }
 
-                for message_to_send_to_elevated in messages_to_send_to_elevated {
-                    self.send_message_all_elevated(&room_name, &message_to_send_to_elevated);
+                // send vote deletions to clients
+                for (poll_title, option_title, anonymous) in deleted_votes {
+                    self.send_vote_delete(&room_name, &poll_title, &option_title, id, anonymous);
                 }
 
-                for message_to_send_to_not_elevated in messages_to_send_to_not_elevated {
-                    self.send_message_all_not_elevated(
-                        &room_name,
-                        &message_to_send_to_not_elevated,
+                // reclaim the room with its polls and hand queue once nobody is left
+                if self
+                    .rooms
+                    .get(&room_name)
+                    .is_some_and(|room| room.connected.is_empty())
+                {
+                    let room = self.rooms.remove(&room_name);
+                    self.room_count.fetch_sub(1, Ordering::SeqCst);
+                    if let Some(ip) = room.and_then(|room| room.creator_ip) {
+                        let mut rooms_per_ip = self.rooms_per_ip.lock().unwrap();
+                        if let Some(count) = rooms_per_ip.get_mut(&ip) {
+                            *count -= 1;
+                            if *count == 0 {
+                                rooms_per_ip.remove(&ip);
+                            }
+                        }
+                    }
+                    info!(
+                        room = room_name.as_str(),
+                        event = "room_removed";
+                        "Room is empty, removing it"
                     );
                 }
             }
         }
+
+        self.update_metrics();
+    }
+}
+
+impl Handler<GlobalBroadcast> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: GlobalBroadcast, _: &mut Context<Self>) {
+        for session in self.sessions.values() {
+            session.deliver(&msg.0);
+        }
+    }
+}
+
+impl Handler<ShutdownNotice> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ShutdownNotice, _: &mut Context<Self>) {
+        self.shutting_down = true;
+        for session in self.sessions.values() {
+            session.deliver(&msg.0);
+        }
     }
 }
 
@@ -447,101 +1427,483 @@ impl Handler<ClientMessage> for WebSocketServer {
 
 /// Join room, send disconnect message to old room
 /// send join message to new room
+impl WebSocketServer {
+    /// check whether a user may join a room, returns why not if it may not
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    /// * `user_name` - a string slice with the name of the joining user
+    /// * `password` - the password given by the joining user
+    /// * `ip` - IP address of the joining client, if known
+    fn admit(
+        &self,
+        room_name: &str,
+        user_name: &str,
+        password: Option<&str>,
+        ip: Option<IpAddr>,
+    ) -> Result<(), Rejection> {
+        // only creating a room counts against the limits, joining an existing one doesn't
+        let creating = self
+            .rooms
+            .get(room_name)
+            .is_none_or(|room| room.connected.is_empty());
+
+        if let (Some(max_rooms_per_ip), Some(ip)) = (self.config.max_rooms_per_ip, ip) {
+            let created = self
+                .rooms_per_ip
+                .lock()
+                .unwrap()
+                .get(&ip)
+                .copied()
+                .unwrap_or(0);
+            if creating && created >= max_rooms_per_ip {
+                return Err(Rejection::new(
+                    RejectionKind::RoomLimit,
+                    messages::outbound::Message::RoomLimit(messages::outbound::Error {
+                        object: room_name.to_string(),
+                        description: format!(
+                            "Too many rooms created from your address ({} rooms)",
+                            max_rooms_per_ip
+                        ),
+                        ack_id: None,
+                    }),
+                ));
+            }
+        }
+
+        // a session taking over another one doesn't make the room any fuller
+        let superseding = self.config.supersede_duplicates
+            && self
+                .rooms
+                .get(room_name)
+                .is_some_and(|room| room.user_named(user_name).is_some());
+
+        if let Some(max_room_size) = self.config.max_room_size.filter(|_| !superseding) {
+            let size = self
+                .rooms
+                .get(room_name)
+                .map_or(0, |room| room.connected.len());
+            if size >= max_room_size {
+                return Err(Rejection::new(
+                    RejectionKind::RoomFull,
+                    messages::outbound::Message::RoomFull(messages::outbound::Error {
+                        object: room_name.to_string(),
+                        description: format!("Room is full ({} participants)", max_room_size),
+                        ack_id: None,
+                    }),
+                ));
+            }
+        }
+
+        if let Some(room) = self.rooms.get(room_name) {
+            // the password is set by the first user, who is always admitted
+            if let Some(password_hash) = room
+                .password_hash
+                .as_ref()
+                .filter(|_| !room.connected.is_empty())
+            {
+                let authenticated = password.is_some_and(|password| {
+                    constant_time_eq(password_hash, &hash_password(room_name, password))
+                });
+                if !authenticated {
+                    return Err(Rejection::new(
+                        RejectionKind::AuthFailed,
+                        messages::outbound::Message::AuthFailed(messages::outbound::Error {
+                            object: room_name.to_string(),
+                            description: "Wrong or missing password for this room".to_string(),
+                            ack_id: None,
+                        }),
+                    ));
+                }
+            }
+
+            // names must be unique within a room, ignoring case and surrounding whitespace
+            if !superseding && room.user_named(user_name).is_some() {
+                return Err(Rejection::new(
+                    RejectionKind::NameTaken,
+                    messages::outbound::Message::NameTaken(messages::outbound::Error {
+                        object: user_name.to_string(),
+                        description: "Name is already taken in this room".to_string(),
+                        ack_id: None,
+                    }),
+                ));
+            }
+        }
+
+        // the slot is taken last, so refused joins never hold one
+        if creating {
+            self.reserve_room(room_name)?;
+        }
+
+        Ok(())
+    }
+
+    /// count a room that is about to be created, unless as many rooms as allowed exist
+    ///
+    /// The count is shared by all servers, so it's checked and increased in one step, rooms
+    /// created at the same time on different servers can't exceed the limit together. The
+    /// slot is given back when the room is reclaimed.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    fn reserve_room(&self, room_name: &str) -> Result<(), Rejection> {
+        let max_rooms = self.config.max_rooms.unwrap_or(usize::MAX);
+        self.room_count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                Some(count + 1).filter(|_| count < max_rooms)
+            })
+            .map(|_| ())
+            .map_err(|_| {
+                Rejection::new(
+                    RejectionKind::Capacity,
+                    messages::outbound::Message::Capacity(messages::outbound::Error {
+                        object: room_name.to_string(),
+                        description: format!(
+                            "The server can't host more rooms ({} rooms)",
+                            max_rooms
+                        ),
+                        ack_id: None,
+                    }),
+                )
+            })
+    }
+}
+
+impl WebSocketServer {
+    /// features clients may use, some of them depend on the configuration
+    fn capabilities(&self) -> Vec<&'static str> {
+        let mut capabilities = vec![
+            "raise",
+            "handqueue",
+            "polls",
+            "reactions",
+            "direct",
+            "typing",
+            "resume",
+            "switchroom",
+            "servertime",
+        ];
+        // messages can only be edited while they're in the history
+        if self.config.history_size > 0 {
+            capabilities.extend(["history", "edit"]);
+        }
+        if self.config.poll_archive_dir.is_some() {
+            capabilities.push("pollarchive");
+        }
+        capabilities
+    }
+}
+
 impl Handler<Join> for WebSocketServer {
-    type Result = ();
+    type Result = Result<usize, Rejection>;
+
+    fn handle(&mut self, msg: Join, ctx: &mut Context<Self>) -> Self::Result {
+        // a retried join of a session that is already in the room only updates its recipients
+        if self
+            .rooms
+            .get(&msg.room_name)
+            .is_some_and(|room| room.connected.contains_key(&msg.user_id))
+        {
+            if let Some(session) = self.sessions.get_mut(&msg.user_id) {
+                session.addr = msg.addr;
+                session.close = msg.close;
+            }
+            debug!(
+                room = msg.room_name.as_str(),
+                user_id = msg.user_id,
+                event = "duplicate_join";
+                "Session is already in the room, ignoring repeated join"
+            );
+            return Ok(msg.user_id);
+        }
 
-    fn handle(&mut self, msg: Join, _: &mut Context<Self>) {
+        self.admit(
+            &msg.room_name,
+            &msg.user_name,
+            msg.password.as_deref(),
+            msg.ip,
+        )?;
+        Ok(self.join_admitted(msg, ctx))
+    }
+}
+
+impl WebSocketServer {
+    /// add a session to a room `admit` has let it into
+    ///
+    /// Returns the user id of the session, which differs from `user_id` if a previous session
+    /// is resumed.
+    ///
+    /// # Arguments
+    ///
+    /// * `msg` - the join of the session
+    /// * `ctx` - context of the server
+    fn join_admitted(&mut self, msg: Join, ctx: &mut Context<Self>) -> usize {
         let Join {
             addr,
+            close,
             user_id,
             user_name,
             room_name,
+            password,
+            token,
+            ip,
+            spectator,
+            color,
+            avatar,
         } = msg;
 
-        self.sessions.insert(user_id, addr);
+        let room = self.rooms.entry(room_name.clone()).or_default();
 
-        let room = self
+        // first user (re)creates the room, which admit counted already, and sets its password
+        if room.connected.is_empty() {
+            room.password_hash = password.map(|password| hash_password(&room_name, &password));
+            if let Some(ip) = ip.filter(|_| room.creator_ip.is_none()) {
+                room.creator_ip = Some(ip);
+                *self.rooms_per_ip.lock().unwrap().entry(ip).or_default() += 1;
+            }
+        }
+
+        // a session with the same name is replaced and its state taken over, otherwise resume
+        // a recently disconnected session of this room if its token is presented
+        let superseded = self
             .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+            .get(&room_name)
+            .and_then(|room| room.user_named(&user_name))
+            .filter(|_| self.config.supersede_duplicates)
+            .and_then(|old_id| self.supersede(&room_name, old_id, user_id));
+        let grace = self.config.resume_grace.as_millis() as u64;
+        let resumed = superseded.or_else(|| {
+            token
+                .and_then(|token| self.suspended.remove(&token))
+                .filter(|suspended| {
+                    suspended.room_name == room_name
+                        && now_millis().saturating_sub(suspended.disconnected_at) <= grace
+                })
+        });
+        let user_id = resumed
+            .as_ref()
+            .map_or(user_id, |suspended| suspended.user_id);
 
-        let elevated = if room.connected.len() > 0 {
-            false
-        } else {
-            true
-        };
+        let token: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .collect();
 
-        room.connected.insert(
+        self.sessions.insert(
             user_id,
-            User {
-                name: user_name.clone(),
-                elevated,
+            Session {
+                id: user_id,
+                addr,
+                close,
+                server: ctx.address().recipient(),
+                token: token.clone(),
+                ip,
+                spectator,
+                color: color.clone(),
+                avatar: avatar.clone(),
             },
         );
 
-        let msg = json!(messages::outbound::User {
-            r#type: messages::outbound::Types::User,
+        let room = self.rooms.entry(room_name.clone()).or_default();
+
+        // first user in the room becomes its host, or the first one who isn't a spectator
+        let created = room.host_id == 0 && !spectator;
+        if created {
+            room.host_id = user_id;
+        }
+        let mut elevated = created;
+        let is_host = room.is_host(&user_id);
+
+        let mut raised_restored = false;
+        let mut votes_restored: HashMap<String, Vec<String>> = HashMap::new();
+        let mut muted = false;
+        let mut weight = 1;
+        if let Some(suspended) = resumed {
+            // reconnecting must not lift a mute or reset the weight
+            muted = suspended.muted;
+            weight = suspended.weight;
+            elevated |= suspended.elevated && !spectator;
+            raised_restored = !suspended.raised.is_empty();
+            room.raised.extend(suspended.raised);
+            room.raised.sort_by_key(|raised| raised.raised_at);
+
+            // votes count again unless their poll was closed or lost the option meanwhile
+            for (poll_title, (option_titles, vote_weight)) in suspended.votes {
+                if let Some(poll) = room
+                    .polls
+                    .iter_mut()
+                    .find(|poll| poll.title == poll_title && !poll.closed)
+                {
+                    let option_titles: Vec<String> = option_titles
+                        .into_iter()
+                        .filter(|title| poll.options.iter().any(|option| &option.title == title))
+                        .collect();
+                    if !option_titles.is_empty() {
+                        poll.votes.insert(user_id, option_titles.clone());
+                        poll.weights.insert(user_id, vote_weight);
+                        votes_restored.insert(poll_title, option_titles);
+                    }
+                }
+            }
+        }
+
+        let user = User {
+            name: user_name.clone(),
+            elevated,
+            spectator,
+            muted,
+            color: color
+                .unwrap_or_else(|| DEFAULT_COLORS[user_id % DEFAULT_COLORS.len()].to_string()),
+            avatar,
+            weight,
+        };
+        let roster_entry = user.roster_entry(user_id);
+        room.connected.insert(user_id, user);
+        self.record(
+            &room_name,
+            "join",
+            Some((user_id, &user_name)),
+            json!({ "spectator": spectator }),
+        );
+
+        let msg = messages::outbound::Message::Hello(messages::outbound::Hello {
+            version: env!("CARGO_PKG_VERSION"),
+            capabilities: self.capabilities(),
+            is_host,
+        })
+        .to_string();
+        self.send_message_user(&room_name, msg.as_str(), user_id);
+
+        let msg = messages::outbound::Message::User(messages::outbound::User {
             object: messages::outbound::UserFormat {
                 id: user_id,
-                name: user_name,
-                elevated
-            }
+                name: user_name.clone(),
+                elevated,
+            },
         })
         .to_string();
         self.send_message_skip_user(&room_name, msg.as_str(), user_id);
 
-        let room = self
-            .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+        let msg = messages::outbound::Message::UserJoined(messages::outbound::RosterChange {
+            object: roster_entry,
+        })
+        .to_string();
+        self.send_message_skip_user(&room_name, msg.as_str(), user_id);
+
+        // roster is built after the insert above, so it includes the joining user
+        let room = self.rooms.entry(room_name.clone()).or_default();
+        let mut roster: Vec<messages::outbound::RosterEntry> = room
+            .connected
+            .iter()
+            .map(|(id, user)| user.roster_entry(*id))
+            .collect();
+        roster.sort_by_key(|entry| entry.user_id);
+
+        let msg = messages::outbound::Message::Roster(messages::outbound::Roster {
+            object: roster,
+            host_id: room.host_id,
+        })
+        .to_string();
+        self.send_message_user(&room_name, msg.as_str(), user_id);
+
+        if created {
+            self.send_host_changed(&room_name, user_id);
+        }
 
-        let msg = json!(messages::outbound::All {
-            r#type: messages::outbound::Types::All,
+        self.send_count(&room_name);
+
+        let room = self.rooms.entry(room_name.clone()).or_default();
+        if !room.history.is_empty() {
+            let msg = messages::outbound::Message::History(messages::outbound::History {
+                object: room.history.iter().cloned().collect(),
+            })
+            .to_string();
+            self.send_message_user(&room_name, msg.as_str(), user_id);
+        }
+
+        let room = self.rooms.entry(room_name.clone()).or_default();
+
+        let msg = messages::outbound::Message::All(messages::outbound::All {
             raised: room.raised.clone(),
-            joined: room.connected.clone()
+            joined: room.connected.clone(),
         })
         .to_string();
 
         self.send_message_user(&room_name, msg.as_str(), user_id);
 
-        let msg = json!(messages::outbound::PermissionChange {
-            r#type: messages::outbound::Types::SelfStatus,
+        let msg = messages::outbound::Message::SelfStatus(messages::outbound::PermissionChange {
             object: user_id,
         })
         .to_string();
 
         self.send_message_user(&room_name, msg.as_str(), user_id);
 
-        let room = self
-            .rooms
-            .entry(room_name.clone())
-            .or_insert(Room::default());
+        let msg = messages::outbound::Message::ResumeToken(messages::outbound::ResumeToken {
+            object: token,
+        })
+        .to_string();
 
-        // send polls
-        for poll in room.polls.clone() {
-            if !poll.closed {
-                let poll_txt = json!(messages::outbound::Poll {
-                    r#type: messages::outbound::Types::Poll,
-                    object: poll.title.clone(),
-                })
-                .to_string();
-                self.send_message_user(&room_name, &poll_txt, user_id);
+        self.send_message_user(&room_name, msg.as_str(), user_id);
+
+        if raised_restored {
+            self.send_hand_queue(&room_name);
+        }
+
+        if !votes_restored.is_empty() {
+            // the votes were deleted for everyone else when the session was suspended
+            for (poll_title, option_titles) in &votes_restored {
+                let anonymous = self.rooms.get(&room_name).is_some_and(|room| {
+                    room.polls
+                        .iter()
+                        .any(|poll| &poll.title == poll_title && poll.anonymous)
+                });
+                for option_title in option_titles {
+                    self.send_vote(
+                        &room_name,
+                        poll_title,
+                        option_title,
+                        &user_name,
+                        user_id,
+                        anonymous,
+                    );
+                }
+                self.send_poll_results(&room_name, poll_title);
+            }
+
+            let msg = messages::outbound::Message::OwnVotes(messages::outbound::OwnVotes {
+                object: votes_restored,
+            })
+            .to_string();
+            self.send_message_user(&room_name, msg.as_str(), user_id);
+        }
+
+        let room = self.rooms.entry(room_name.clone()).or_default();
+
+        // send polls
+        for poll in room.polls.clone() {
+            if !poll.closed {
+                let poll_txt = messages::outbound::Message::Poll(messages::outbound::Poll {
+                    object: poll.title.clone(),
+                })
+                .to_string();
+                self.send_message_user(&room_name, &poll_txt, user_id);
 
                 // send options for poll
                 for option in poll.options.clone() {
-                    let option_txt = json!(messages::outbound::PollOption {
-                        r#type: messages::outbound::Types::PollOption,
-                        pollobject: poll.title.clone(),
-                        polloptionobject: option.title.clone(),
-                    })
-                    .to_string();
+                    let option_txt =
+                        messages::outbound::Message::PollOption(messages::outbound::PollOption {
+                            pollobject: poll.title.clone(),
+                            polloptionobject: option.title.clone(),
+                        })
+                        .to_string();
                     self.send_message_user(&room_name, &option_txt, user_id);
                 }
 
                 // send votes for poll
-                for (_, option_title) in poll.votes.clone() {
-                    let vote_txt = json!(messages::outbound::Vote {
-                        r#type: messages::outbound::Types::Vote,
+                for option_title in poll.votes.values().flatten() {
+                    let vote_txt = messages::outbound::Message::Vote(messages::outbound::Vote {
                         pollobject: poll.title.clone(),
                         polloptionobject: option_title.clone(),
                         username: "".to_string(),
@@ -552,6 +1914,464 @@ impl Handler<Join> for WebSocketServer {
                 }
             }
         }
+
+        // send a snapshot of all open polls, voter identities are left out
+        let room = self.rooms.entry(room_name.clone()).or_default();
+        let snapshot_txt =
+            messages::outbound::Message::PollSnapshot(messages::outbound::PollSnapshot {
+                object: room
+                    .polls
+                    .iter()
+                    .filter(|poll| !poll.closed)
+                    .map(Poll::snapshot)
+                    .collect(),
+            })
+            .to_string();
+        self.send_message_user(&room_name, &snapshot_txt, user_id);
+
+        if let Some(txt) = self.room_options_message(&room_name) {
+            self.send_message_user(&room_name, &txt, user_id);
+        }
+
+        self.update_metrics();
+
+        user_id
+    }
+
+    /// close a session because a newer one with the same name joins its room
+    ///
+    /// The state of the old session is returned, like that of a suspended one, so the new
+    /// session is restored from it. Unlike a leaving session, the room is never reclaimed, even
+    /// if the old session was its only member. Returns `None` if the old session isn't in the
+    /// room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    /// * `old_id` - id of the session that is replaced
+    /// * `new_id` - id of the session taking over
+    fn supersede(&mut self, room_name: &str, old_id: usize, new_id: usize) -> Option<Suspended> {
+        let room = self.rooms.get_mut(room_name)?;
+        let user = room.connected.remove(&old_id)?;
+
+        // raised objects keep their place in the queue
+        let mut raised = Vec::new();
+        room.raised.retain(|elem| {
+            if elem.owner_id == old_id {
+                raised.push(Raised {
+                    owner_id: new_id,
+                    ..elem.clone()
+                });
+                false
+            } else {
+                true
+            }
+        });
+        let mut votes = HashMap::new();
+        for poll in room.polls.iter_mut() {
+            // the new session may close, delete and rename the polls it created
+            if poll.owner_id == old_id {
+                poll.owner_id = new_id;
+            }
+            if poll.closed {
+                continue;
+            }
+            if let Some(option_titles) = poll.votes.remove(&old_id) {
+                // restored votes keep counting with the weight they were cast with
+                let weight = poll.weights.remove(&old_id).unwrap_or(1);
+                votes.insert(poll.title.clone(), (option_titles, weight));
+            }
+        }
+        for users in room
+            .reactions
+            .values_mut()
+            .flat_map(|emojis| emojis.values_mut())
+        {
+            if users.remove(&old_id) {
+                users.insert(new_id);
+            }
+        }
+        room.remove_user(&old_id);
+        // the new session becomes host once it's in the room
+        if room.is_host(&old_id) {
+            room.host_id = 0;
+        }
+
+        // the old session's own disconnect finds nothing to remove
+        if let Some(session) = self.sessions.remove(&old_id) {
+            let superseded = messages::outbound::Message::Superseded(messages::outbound::Error {
+                object: room_name.to_string(),
+                description: "You joined this room from another connection".to_string(),
+                ack_id: None,
+            })
+            .to_string();
+            let _ = session.close.do_send(Close(superseded));
+        }
+
+        info!(
+            room = room_name,
+            user_id = old_id,
+            new_user_id = new_id,
+            event = "session_superseded";
+            "Session replaced by a newer one with the same name"
+        );
+
+        self.record(
+            room_name,
+            "leave",
+            Some((old_id, &user.name)),
+            json!({ "reason": LeaveReason::Superseded }),
+        );
+        let txt = messages::outbound::Message::UserLeft(messages::outbound::UserLeft {
+            object: user.roster_entry(old_id),
+            reason: LeaveReason::Superseded,
+        })
+        .to_string();
+        self.send_message_all(room_name, &txt);
+
+        Some(Suspended {
+            user_id: new_id,
+            room_name: room_name.to_string(),
+            elevated: user.elevated,
+            muted: user.muted,
+            weight: user.weight,
+            raised,
+            votes,
+            disconnected_at: now_millis(),
+        })
+    }
+}
+
+impl Handler<SwitchRoom> for WebSocketServer {
+    type Result = Result<usize, Rejection>;
+
+    fn handle(&mut self, msg: SwitchRoom, ctx: &mut Context<Self>) -> Self::Result {
+        let (addr, close, ip, spectator, color, avatar) = match self.sessions.get(&msg.id) {
+            Some(session) => (
+                session.addr.clone(),
+                session.close.clone(),
+                session.ip,
+                session.spectator,
+                session.color.clone(),
+                session.avatar.clone(),
+            ),
+            None => {
+                return Err(Rejection::new(
+                    RejectionKind::NotInRoom,
+                    messages::outbound::Message::Error(messages::outbound::Error {
+                        object: "not_in_room".to_string(),
+                        description: "Join a room before switching rooms".to_string(),
+                        ack_id: None,
+                    }),
+                ))
+            }
+        };
+
+        let switching = self
+            .rooms
+            .get(&msg.room_name)
+            .is_none_or(|room| !room.connected.contains_key(&msg.id));
+        if !switching {
+            return Err(Rejection::new(
+                RejectionKind::AlreadyInRoom,
+                messages::outbound::Message::Error(messages::outbound::Error {
+                    object: "already_in_room".to_string(),
+                    description: "You are already in this room".to_string(),
+                    ack_id: None,
+                }),
+            ));
+        }
+
+        // check the new room before leaving the old one, so a refused switch changes nothing
+        self.admit(&msg.room_name, &msg.user_name, msg.password.as_deref(), ip)?;
+
+        self.remove_session(msg.id, false, LeaveReason::Left);
+        Ok(self.join_admitted(
+            Join {
+                addr,
+                close,
+                user_id: msg.id,
+                user_name: msg.user_name,
+                room_name: msg.room_name,
+                password: msg.password,
+                token: None,
+                ip,
+                spectator,
+                color,
+                avatar,
+            },
+            ctx,
+        ))
+    }
+}
+
+impl Handler<Kick> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Kick, ctx: &mut Context<Self>) {
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to kick users (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        // kicking a user who isn't in the room does nothing
+        if !room.connected.contains_key(&msg.user_id) {
+            return;
+        }
+
+        if let Some(Session { close, .. }) = self.sessions.get(&msg.user_id) {
+            let kicked = messages::outbound::Message::Kicked(messages::outbound::Error {
+                object: msg.room_name.clone(),
+                description: "You have been removed from the room by the host".to_string(),
+                ack_id: None,
+            })
+            .to_string();
+            let _ = close.do_send(Close(kicked));
+        }
+
+        // remove the user right away instead of waiting for the session to disconnect
+        self.handle(
+            Disconnect {
+                id: msg.user_id,
+                reason: LeaveReason::Kicked,
+            },
+            ctx,
+        );
+        // kicked users must not come back by resuming their session
+        self.suspended
+            .retain(|_, suspended| suspended.user_id != msg.user_id);
+    }
+}
+
+impl Handler<Mute> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Mute, _: &mut Context<Self>) {
+        self.set_muted(
+            &msg.room_name,
+            msg.sender_id,
+            msg.user_id,
+            true,
+            &msg.ack_id,
+        );
+    }
+}
+
+impl Handler<Unmute> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unmute, _: &mut Context<Self>) {
+        self.set_muted(
+            &msg.room_name,
+            msg.sender_id,
+            msg.user_id,
+            false,
+            &msg.ack_id,
+        );
+    }
+}
+
+impl WebSocketServer {
+    /// mute or unmute a user on request of the host and tell the user
+    ///
+    /// # Arguments
+    /// * `room_name` - The room the user is muted in
+    /// * `sender_id` - The user who requests the change. Needs to be the room's host.
+    /// * `user_id` - The user to mute or unmute
+    /// * `muted` - Whether the user should be muted
+    /// * `ack_id` - The ack id of the request, echoed in errors
+    fn set_muted(
+        &mut self,
+        room_name: &str,
+        sender_id: usize,
+        user_id: usize,
+        muted: bool,
+        ack_id: &Option<Arbitrary>,
+    ) {
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&sender_id) {
+            self.send_error_user(
+                room_name,
+                "no_permission",
+                "You do not have permission to mute users (because you're not the host)",
+                sender_id,
+                ack_id,
+            );
+            return;
+        }
+
+        let user = match room.connected.get_mut(&user_id) {
+            Some(user) => user,
+            None => {
+                self.send_error_user(
+                    room_name,
+                    "user_does_not_exist",
+                    "Refusing to mute, user is not in the room",
+                    sender_id,
+                    ack_id,
+                );
+                return;
+            }
+        };
+
+        // muting a muted user again changes nothing and tells nobody
+        if user.muted == muted {
+            return;
+        }
+        user.muted = muted;
+
+        info!(
+            room = room_name,
+            user_id = user_id,
+            event = if muted { "muted" } else { "unmuted" };
+            "Host changed whether user is muted"
+        );
+        self.send_muted(room_name, user_id, muted, &None);
+    }
+}
+
+impl Handler<TransferHost> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TransferHost, _: &mut Context<Self>) {
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to transfer the host role (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        if !room.connected.contains_key(&msg.user_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "user_does_not_exist",
+                "Refusing to transfer the host role, user is not in the room",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        if room.is_spectator(&msg.user_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "forbidden",
+                "Refusing to transfer the host role, spectators can only watch the room",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        room.host_id = msg.user_id;
+        self.send_host_changed(&msg.room_name, msg.user_id);
+    }
+}
+
+impl Handler<Ping> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, _: Ping, _: &mut Context<Self>) {}
+}
+
+impl Handler<ListRooms> for WebSocketServer {
+    type Result = MessageResult<ListRooms>;
+
+    fn handle(&mut self, _: ListRooms, _: &mut Context<Self>) -> Self::Result {
+        // rooms are reclaimed as their last member leaves, an empty one is never listed
+        MessageResult(
+            self.rooms
+                .iter()
+                .filter(|(_, room)| !room.connected.is_empty())
+                .map(|(room_name, room)| (room_name.clone(), room.connected.len()))
+                .collect(),
+        )
+    }
+}
+
+impl Handler<RoomTranscript> for WebSocketServer {
+    type Result = MessageResult<RoomTranscript>;
+
+    fn handle(&mut self, msg: RoomTranscript, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.rooms
+                .get(&msg.room_name)
+                .map(|room| room.transcript.iter().cloned().collect()),
+        )
+    }
+}
+
+impl WebSocketServer {
+    /// add an event to the transcript of a room, dropping the oldest one once it's full
+    ///
+    /// Nothing is kept if transcripts are disabled or the room doesn't exist.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    /// * `event` - what happened, e.g. `join` or `vote`
+    /// * `user` - id and name of the user causing the event, `None` for the server
+    /// * `object` - details of the event
+    fn record(
+        &mut self,
+        room_name: &str,
+        event: &'static str,
+        user: Option<(usize, &str)>,
+        object: Arbitrary,
+    ) {
+        let transcript_max = self.config.transcript_max;
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) if transcript_max > 0 => room,
+            _ => return,
+        };
+        room.transcript.push_back(TranscriptEntry {
+            at: now_millis(),
+            event,
+            user_id: user.map(|(user_id, _)| user_id),
+            user_name: user.map(|(_, user_name)| user_name.to_string()),
+            object,
+        });
+        while room.transcript.len() > transcript_max {
+            room.transcript.pop_front();
+        }
+    }
+}
+
+impl Handler<RoomPolls> for WebSocketServer {
+    type Result = MessageResult<RoomPolls>;
+
+    fn handle(&mut self, msg: RoomPolls, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.rooms
+                .get(&msg.room_name)
+                .map_or_else(Vec::new, |room| {
+                    room.polls.iter().map(Poll::snapshot).collect()
+                }),
+        )
     }
 }
 
@@ -559,47 +2379,63 @@ impl Handler<Raise> for WebSocketServer {
     type Result = ();
 
     fn handle(&mut self, msg: Raise, _: &mut Context<Self>) {
-        let mut check_raised = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .raised
-            .clone();
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        // the room may be gone if the session left it after sending the message
+        let room = match self.rooms.get(msg.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // toggling faster than the cooldown is dropped without an answer
+        let now = now_millis();
+        let cooldown = self.config.hand_cooldown.as_millis() as u64;
+        if !room.hand_cooled_down(&msg.owner_id, cooldown, now) {
+            return;
+        }
+
+        let elevated = room.is_elevated(&msg.owner_id).unwrap_or(false);
+        let mut check_raised = room.raised.clone();
         check_raised.retain(|elem| elem.object == msg.object && elem.owner_id == msg.owner_id);
 
-        if check_raised.len() > 0 {
+        if !check_raised.is_empty() {
             self.send_error_user(
                 &msg.room_name,
                 "already_raised",
                 "Refusing to raise, already raised",
                 msg.owner_id,
+                &msg.ack_id,
+            );
+            info!(
+                room = msg.room_name.as_str(),
+                user_id = msg.owner_id,
+                event = "already_raised";
+                "Refusing to raise, already raised"
             );
-            println!("Refusing to raise, already raised");
             return;
         }
 
-        let elevated = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .is_elevated(&msg.owner_id)
-            .unwrap_or(false);
-
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Raised,
+        let txt = messages::outbound::Message::Raised(messages::outbound::OwnedObject {
             owner_id: msg.owner_id,
             owner_name: msg.owner_name.clone(),
             object: msg.object.clone(),
-            elevated: elevated,
+            elevated,
         });
         self.send_message_all(msg.room_name.as_str(), &txt.to_string());
 
-        let room = self.rooms.entry(msg.room_name).or_insert(Room::default());
+        let room = self.rooms.entry(msg.room_name.clone()).or_default();
+        room.hand_toggled.insert(msg.owner_id, now);
         room.raised.push(Raised {
             object: msg.object,
             owner_id: msg.owner_id,
             owner_name: msg.owner_name,
+            raised_at: now,
+            reason: msg.reason,
         });
+
+        self.send_hand_queue(&msg.room_name);
     }
 }
 
@@ -607,33 +2443,41 @@ impl Handler<Lower> for WebSocketServer {
     type Result = ();
 
     fn handle(&mut self, msg: Lower, _: &mut Context<Self>) {
-        let equiv_clone = msg.clone();
-        let room = self
-            .rooms
-            .entry(msg.room_name.clone())
-            .or_insert(Room::default());
+        let now = now_millis();
+        let cooldown = self.config.hand_cooldown.as_millis() as u64;
+        let room = match self.rooms.get_mut(msg.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // toggling faster than the cooldown is dropped without an answer
+        if !room.hand_cooled_down(&msg.owner_id, cooldown, now) {
+            return;
+        }
 
         let mut check_raised = room.raised.clone();
-        check_raised.retain(|elem| &elem.object == &msg.object && &elem.owner_id == &msg.owner_id);
+        check_raised.retain(|elem| elem.object == msg.object && elem.owner_id == msg.owner_id);
 
-        if check_raised.len() == 0 {
+        if check_raised.is_empty() {
             self.send_error_user(
                 &msg.room_name,
                 "not_raised",
                 "Refusing to lower, is not raised",
                 msg.owner_id,
+                &msg.ack_id,
+            );
+            info!(
+                room = msg.room_name.as_str(),
+                user_id = msg.owner_id,
+                event = "not_raised";
+                "Refusing to lower, is not raised"
             );
-            println!("Refusing to lower, is not raised");
             return;
         }
 
-        let raised_equivalent = Raised {
-            object: json!(equiv_clone.object),
-            owner_id: equiv_clone.owner_id,
-            owner_name: equiv_clone.owner_name,
-        };
-
-        room.raised.retain(|elem| elem != &raised_equivalent);
+        room.hand_toggled.insert(msg.owner_id, now);
+        room.raised
+            .retain(|elem| !(elem.object == msg.object && elem.owner_id == msg.owner_id));
 
         let elevated = self
             .rooms
@@ -642,98 +2486,582 @@ impl Handler<Lower> for WebSocketServer {
             .is_elevated(&msg.owner_id)
             .unwrap_or(false);
 
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Lower,
+        let txt = messages::outbound::Message::Lower(messages::outbound::OwnedObject {
             owner_id: msg.owner_id,
             owner_name: msg.owner_name,
             object: msg.object,
-            elevated: elevated,
+            elevated,
         })
         .to_string();
         self.send_message_all(&msg.room_name, &txt);
+
+        self.send_hand_queue(&msg.room_name);
     }
 }
 
-impl Handler<Instant> for WebSocketServer {
+/// Lowering all raised objects is restricted to the host
+impl Handler<LowerAll> for WebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Instant, _: &mut Context<Self>) {
-        let elevated = self
-            .rooms
-            .get(msg.room_name.as_str())
-            .unwrap()
-            .is_elevated(&msg.owner_id)
-            .unwrap_or(false);
+    fn handle(&mut self, msg: LowerAll, _: &mut Context<Self>) {
+        let room = match self.rooms.get_mut(msg.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
 
-        let txt = json!(messages::outbound::OwnedObject {
-            r#type: messages::outbound::Types::Instant,
-            owner_id: msg.owner_id,
-            owner_name: msg.owner_name,
-            object: msg.object,
-            elevated: elevated,
-        })
-        .to_string();
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to lower all raised objects (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            info!(
+                room = msg.room_name.as_str(),
+                user_id = msg.sender_id,
+                event = "no_permission";
+                "User does not have permission to lower all raised objects (not host)"
+            );
+            return;
+        }
 
-        self.send_message_all(&msg.room_name, &txt);
+        room.raised.clear();
+
+        self.send_hand_queue(&msg.room_name);
     }
 }
 
-impl Handler<Poll> for WebSocketServer {
+impl Handler<HandQueueQuery> for WebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, poll: Poll, _: &mut Context<Self>) {
-        // get room
-        let room = self
-            .rooms
-            .entry(poll.room_name.clone())
-            .or_insert(Room::default());
+    fn handle(&mut self, msg: HandQueueQuery, _: &mut Context<Self>) {
+        if let Some(txt) = self.hand_queue_message(&msg.room_name) {
+            self.send_message_user(&msg.room_name, &txt, msg.sender_id);
+        }
+    }
+}
 
-        // check if user is elevated
-        let mut user_is_elevated = room.connected.clone();
-        user_is_elevated.retain(|id, user| {
-            id == &poll.owner_id && &user.name == &poll.owner_name && user.elevated
-        });
+impl Handler<Instant> for WebSocketServer {
+    type Result = ();
 
-        if user_is_elevated.len() == 0 {
-            self.send_error_user(
-                &poll.room_name,
-                "no_permission",
-                "You do not have permission to create polls (because you're not elevated)",
-                poll.owner_id,
-            );
-            println!("User does not have permission to create polls (not elevated)");
+    fn handle(&mut self, msg: Instant, ctx: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id)
+            || self.refuse_too_long(&msg.room_name, &msg.object, msg.owner_id, &msg.ack_id)
+        {
             return;
         }
 
-        // check if poll already exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &poll.title);
-
-        if poll_exists.len() > 0 {
+        let history_size = self.config.history_size;
+        // the room may be gone if the session left it after sending the message
+        let room = match self.rooms.get_mut(msg.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+        let elevated = room.is_elevated(&msg.owner_id).unwrap_or(false);
+        // replies to messages that were never sent in this room don't form a thread
+        let reply_to = msg
+            .reply_to
+            .filter(|reply_to| (1..=room.last_message_id).contains(reply_to));
+        room.last_message_id += 1;
+        let id = room.last_message_id;
+
+        if history_size > 0 {
+            room.history.push_back(HistoryEntry {
+                id,
+                object: msg.object.clone(),
+                owner_id: msg.owner_id,
+                owner_name: msg.owner_name.clone(),
+                elevated,
+                sent_at: now_millis(),
+                reply_to,
+            });
+            // drop messages older than the buffer
+            while room.history.len() > history_size {
+                room.history.pop_front();
+            }
+        }
+
+        self.record(
+            &msg.room_name,
+            "instant",
+            Some((msg.owner_id, &msg.owner_name)),
+            json!({ "id": id, "object": msg.object, "reply_to": reply_to }),
+        );
+
+        let txt = messages::outbound::Message::Instant(messages::outbound::Instant {
+            id,
+            owner_id: msg.owner_id,
+            owner_name: msg.owner_name,
+            object: msg.object,
+            elevated,
+            reply_to,
+        })
+        .to_string();
+
+        self.send_message_all(&msg.room_name, &txt);
+
+        // expire message automatically after its TTL
+        if let Some(ttl_secs) = msg.ttl_secs {
+            let room_name = msg.room_name;
+            ctx.run_later(Duration::from_secs(ttl_secs), move |act, _| {
+                act.expire_instant(&room_name, id);
+            });
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// refuse an instant message longer than the configured limit
+    ///
+    /// The length is counted in characters, so non-Latin text isn't cut shorter than Latin
+    /// text. Messages that aren't strings count with their JSON form. Returns whether the
+    /// message is refused.
+    ///
+    /// # Arguments
+    ///
+    /// * `room` - a string slice with the name of the room the message is sent to
+    /// * `object` - the content of the message
+    /// * `user_id` - id of the author, who gets the error
+    /// * `ack_id` - the `ack_id` of the message, echoed in the error
+    fn refuse_too_long(
+        &self,
+        room: &str,
+        object: &Arbitrary,
+        user_id: usize,
+        ack_id: &Option<Arbitrary>,
+    ) -> bool {
+        let len = match object {
+            Arbitrary::String(text) => text.chars().count(),
+            object => object.to_string().chars().count(),
+        };
+        let too_long = len > self.config.max_chat_len;
+        if too_long {
+            self.send_error_user(
+                room,
+                "message_too_long",
+                &format!(
+                    "Refusing to send message, it's longer than {} characters",
+                    self.config.max_chat_len
+                ),
+                user_id,
+                ack_id,
+            );
+        }
+        too_long
+    }
+
+    /// drop an instant message from the history and tell the room it expired
+    ///
+    /// Nothing is sent if the room has been removed in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room the message was sent to
+    /// * `id` - the id of the expired message
+    fn expire_instant(&mut self, room_name: &str, id: usize) {
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) => room,
+            None => return,
+        };
+        room.history.retain(|entry| entry.id != id);
+
+        let txt =
+            messages::outbound::Message::InstantExpired(messages::outbound::InstantExpired { id })
+                .to_string();
+        self.send_message_all(room_name, &txt);
+    }
+}
+
+impl Handler<Edit> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Edit, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id)
+            || self.refuse_too_long(&msg.room_name, &msg.object, msg.owner_id, &msg.ack_id)
+        {
+            return;
+        }
+
+        // only messages still in the history can be edited
+        let entry = self.rooms.get_mut(&msg.room_name).and_then(|room| {
+            room.history
+                .iter_mut()
+                .find(|entry| entry.id == msg.message_id)
+        });
+
+        let entry = match entry {
+            None => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "message_does_not_exist",
+                    "Refusing to edit message, message does not exist (anymore)",
+                    msg.owner_id,
+                    &msg.ack_id,
+                );
+                return;
+            }
+            Some(entry) if entry.owner_id != msg.owner_id => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "no_permission",
+                    "You do not have permission to edit this message (because you're not its author)",
+                    msg.owner_id,
+                    &msg.ack_id,
+                );
+                return;
+            }
+            Some(entry) => entry,
+        };
+
+        entry.object = msg.object;
+        let edited = json!({ "id": entry.id, "object": entry.object });
+        let owner_name = entry.owner_name.clone();
+
+        let txt = messages::outbound::Message::Edited(messages::outbound::Instant {
+            id: entry.id,
+            owner_id: entry.owner_id,
+            owner_name: entry.owner_name.clone(),
+            object: entry.object.clone(),
+            elevated: entry.elevated,
+            reply_to: entry.reply_to,
+        })
+        .to_string();
+
+        self.send_message_all(&msg.room_name, &txt);
+        self.record(
+            &msg.room_name,
+            "edit",
+            Some((msg.owner_id, &owner_name)),
+            edited,
+        );
+    }
+}
+
+/// Maximum length of a reaction in bytes, enough for emoji built of several code points
+const MAX_REACTION_LEN: usize = 32;
+
+impl Handler<React> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: React, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        if msg.emoji.is_empty() || msg.emoji.len() > MAX_REACTION_LEN {
+            self.send_error_user(
+                &msg.room_name,
+                "invalid_reaction",
+                "Refusing to react, reaction is empty or too long",
+                msg.owner_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // reacting twice with the same emoji takes the reaction back
+        let reactions = room.reactions.entry(msg.target.clone()).or_default();
+        let users = reactions.entry(msg.emoji.clone()).or_default();
+        if !users.remove(&msg.owner_id) {
+            users.insert(msg.owner_id);
+        }
+
+        self.send_reactions(&msg.room_name, &msg.target);
+    }
+}
+
+impl Handler<Unreact> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Unreact, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        // taking back a reaction that doesn't exist changes nothing and tells nobody
+        let removed = self
+            .rooms
+            .get_mut(&msg.room_name)
+            .and_then(|room| room.reactions.get_mut(&msg.target))
+            .and_then(|reactions| reactions.get_mut(&msg.emoji))
+            .is_some_and(|users| users.remove(&msg.owner_id));
+        if removed {
+            self.send_reactions(&msg.room_name, &msg.target);
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// send the reaction counts of a target to all users in a room
+    ///
+    /// Emoji nobody reacts with anymore are dropped, as is the target once it has none left.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - name of the room
+    /// * `target` - the poll or message reacted to
+    fn send_reactions(&mut self, room_name: &str, target: &str) {
+        let room = match self.rooms.get_mut(room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        let reactions = room.reactions.entry(target.to_string()).or_default();
+        reactions.retain(|_, users| !users.is_empty());
+
+        let counts: HashMap<String, usize> = reactions
+            .iter()
+            .map(|(emoji, users)| (emoji.clone(), users.len()))
+            .collect();
+        if reactions.is_empty() {
+            room.reactions.remove(target);
+        }
+
+        let txt = messages::outbound::Message::Reactions(messages::outbound::Reactions {
+            target: target.to_string(),
+            object: counts,
+        })
+        .to_string();
+
+        self.send_message_all(room_name, &txt);
+    }
+}
+
+/// Time in which a repeated, unchanged typing state is not broadcast again
+const TYPING_DEBOUNCE_MILLIS: u64 = 1000;
+
+impl Handler<Typing> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Typing, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        let now = now_millis();
+        if let Some((active, since)) = room.typing.get(&msg.owner_id) {
+            if *active == msg.active && now.saturating_sub(*since) < TYPING_DEBOUNCE_MILLIS {
+                return;
+            }
+        }
+        room.typing.insert(msg.owner_id, (msg.active, now));
+
+        let elevated = room.is_elevated(&msg.owner_id).unwrap_or(false);
+
+        let txt = messages::outbound::Message::Typing(messages::outbound::OwnedObject {
+            owner_id: msg.owner_id,
+            owner_name: msg.owner_name,
+            object: Arbitrary::Bool(msg.active),
+            elevated,
+        })
+        .to_string();
+
+        self.send_message_skip_user(&msg.room_name, &txt, msg.owner_id);
+    }
+}
+
+impl Handler<Direct> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Direct, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        let target_connected = self
+            .rooms
+            .get(&msg.room_name)
+            .is_some_and(|room| room.connected.contains_key(&msg.target_id));
+
+        if !target_connected {
+            self.send_error_user(
+                &msg.room_name,
+                "user_does_not_exist",
+                "Refusing to send direct message, user is not in the room",
+                msg.owner_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        let txt = messages::outbound::Message::Direct(messages::outbound::Direct {
+            owner_id: msg.owner_id,
+            owner_name: msg.owner_name,
+            target_id: msg.target_id,
+            object: msg.object,
+        })
+        .to_string();
+
+        self.send_message_user(&msg.room_name, &txt, msg.target_id);
+        // echo to the sender for their own history
+        if msg.owner_id != msg.target_id {
+            self.send_message_user(&msg.room_name, &txt, msg.owner_id);
+        }
+    }
+}
+
+impl Handler<Poll> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, mut poll: Poll, ctx: &mut Context<Self>) {
+        if self.refuse_action(&poll.room_name, poll.owner_id, &poll.ack_id) {
+            return;
+        }
+
+        if self
+            .rooms
+            .get(&poll.room_name)
+            .is_some_and(|room| room.options.host_only_polls && !room.is_host(&poll.owner_id))
+        {
+            self.send_error_user(
+                &poll.room_name,
+                "forbidden",
+                "Only the host may create polls in this room",
+                poll.owner_id,
+                &poll.ack_id,
+            );
+            return;
+        }
+
+        // polls are looked up by their title, so it can't be blank
+        if poll.title.trim().is_empty() {
+            self.send_error_user(
+                &poll.room_name,
+                "empty_title",
+                "The title of a poll must not be empty",
+                poll.owner_id,
+                &poll.ack_id,
+            );
+            info!(
+                room = poll.room_name.as_str(),
+                user_id = poll.owner_id,
+                event = "empty_title";
+                "Poll title is empty"
+            );
+            return;
+        }
+
+        // get room
+        let room = match self.rooms.get_mut(poll.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // check if user is elevated
+        let mut user_is_elevated = room.connected.clone();
+        user_is_elevated.retain(|id, user| {
+            id == &poll.owner_id && user.name == poll.owner_name && user.elevated
+        });
+
+        if user_is_elevated.is_empty() {
+            self.send_error_user(
+                &poll.room_name,
+                "no_permission",
+                "You do not have permission to create polls (because you're not elevated)",
+                poll.owner_id,
+                &poll.ack_id,
+            );
+            info!(
+                room = poll.room_name.as_str(),
+                user_id = poll.owner_id,
+                event = "no_permission";
+                "User does not have permission to create polls (not elevated)"
+            );
+            return;
+        }
+
+        // check if poll already exists (ignoring surrounding whitespace)
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|elem| elem.title.trim() == poll.title.trim());
+
+        if !poll_exists.is_empty() {
             self.send_error_user(
                 &poll.room_name,
                 "poll_already_exists",
                 "A poll with that title already exists",
                 poll.owner_id,
+                &poll.ack_id,
+            );
+            info!(
+                room = poll.room_name.as_str(),
+                user_id = poll.owner_id,
+                event = "poll_already_exists";
+                "A poll with that title already exists"
+            );
+            return;
+        }
+
+        // check if room has reached the maximum number of open polls
+        let open_polls = room.polls.iter().filter(|elem| !elem.closed).count();
+        if open_polls >= self.config.max_polls_per_room {
+            self.send_error_user(
+                &poll.room_name,
+                "polls_limit_reached",
+                "The room already has the maximum number of open polls",
+                poll.owner_id,
+                &poll.ack_id,
+            );
+            info!(
+                room = poll.room_name.as_str(),
+                user_id = poll.owner_id,
+                event = "polls_limit_reached";
+                "Room has reached the maximum number of open polls"
             );
-            println!("A poll with that title already exists");
             return;
         }
 
         // clone later needed values
         let poll_title = poll.title.clone();
         let room_name = poll.room_name.clone();
+        let (owner_id, owner_name) = (poll.owner_id, poll.owner_name.clone());
+        let duration_secs = poll.duration_secs;
 
         // add poll to room
+        self.last_poll_id += 1;
+        poll.id = self.last_poll_id;
+        let poll_id = poll.id;
         room.polls.push(poll);
+        metrics::poll_created();
+
+        // close poll automatically after its duration, looked up by id as it may be renamed
+        if let Some(duration_secs) = duration_secs {
+            let room_name = room_name.clone();
+            ctx.run_later(Duration::from_secs(duration_secs), move |act, _| {
+                let poll_title = act.rooms.get(&room_name).and_then(|room| {
+                    room.polls
+                        .iter()
+                        .find(|poll| poll.id == poll_id)
+                        .map(|poll| poll.title.clone())
+                });
+                if let Some(poll_title) = poll_title {
+                    act.close_poll(&room_name, &poll_title);
+                }
+            });
+        }
 
         // send poll message to clients
-        let poll_txt = json!(messages::outbound::Poll {
-            r#type: messages::outbound::Types::Poll,
+        let poll_txt = messages::outbound::Message::Poll(messages::outbound::Poll {
             object: poll_title.clone(),
         })
         .to_string();
         self.send_message_all(&room_name, &poll_txt);
+        self.record(
+            &room_name,
+            "poll",
+            Some((owner_id, &owner_name)),
+            json!({ "title": poll_title }),
+        );
     }
 }
 
@@ -741,26 +3069,58 @@ impl Handler<PollOption> for WebSocketServer {
     type Result = ();
 
     fn handle(&mut self, poll_option: PollOption, _: &mut Context<Self>) {
+        if self.refuse_action(
+            &poll_option.room_name,
+            poll_option.owner_id,
+            &poll_option.ack_id,
+        ) {
+            return;
+        }
+
+        // poll-options are looked up by their title, so it can't be blank
+        if poll_option.title.trim().is_empty() {
+            self.send_error_user(
+                &poll_option.room_name,
+                "empty_title",
+                "The title of a poll-option must not be empty",
+                poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "empty_title";
+                "Poll-option title is empty"
+            );
+            return;
+        }
+
         // get room
-        let room = self
-            .rooms
-            .entry(poll_option.room_name.clone())
-            .or_insert(Room::default());
+        let room = match self.rooms.get_mut(poll_option.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
 
         // check if user is elevated
         let mut user_is_elevated = room.connected.clone();
         user_is_elevated.retain(|id, user| {
-            id == &poll_option.owner_id && &user.name == &poll_option.owner_name && user.elevated
+            id == &poll_option.owner_id && user.name == poll_option.owner_name && user.elevated
         });
 
-        if user_is_elevated.len() == 0 {
+        if user_is_elevated.is_empty() {
             self.send_error_user(
                 &poll_option.room_name,
                 "no_permission",
                 "You do not have permission to add poll options (because you're not elevated)",
                 poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "no_permission";
+                "User does not have permission to add poll options (not elevated)"
             );
-            println!("User does not have permission to add poll options (not elevated)");
             return;
         }
 
@@ -768,14 +3128,20 @@ impl Handler<PollOption> for WebSocketServer {
         let mut poll_exists = room.polls.clone();
         poll_exists.retain(|poll| poll.title == poll_option.poll_title);
 
-        if poll_exists.len() == 0 {
+        if poll_exists.is_empty() {
             self.send_error_user(
                 &poll_option.room_name,
                 "poll_does_not_exist",
                 "A poll with that title doesn't exist",
                 poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "poll_does_not_exist";
+                "A poll with that title doesn't exist"
             );
-            println!("A poll with that title doesn't exist");
             return;
         }
 
@@ -794,8 +3160,32 @@ impl Handler<PollOption> for WebSocketServer {
                 "poll_closed",
                 "Sorry, the poll is already closed",
                 poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "poll_closed";
+                "Poll is already closed"
+            );
+            return;
+        }
+
+        // check if poll has reached the maximum number of options
+        if poll.options.len() >= self.config.max_poll_options {
+            self.send_error_user(
+                &poll_option.room_name,
+                "poll_options_limit_reached",
+                "The poll already has the maximum number of poll-options",
+                poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "poll_options_limit_reached";
+                "Poll has reached the maximum number of poll-options"
             );
-            println!("Poll is already closed");
             return;
         }
 
@@ -804,76 +3194,252 @@ impl Handler<PollOption> for WebSocketServer {
         poll_option_exists
             .retain(|existing_poll_option| existing_poll_option.title == poll_option.title);
 
-        if poll_option_exists.len() > 0 {
+        if !poll_option_exists.is_empty() {
             self.send_error_user(
                 &poll_option.room_name,
                 "poll_option_already_exists",
                 "A poll-option with that title in this poll does already exist",
                 poll_option.owner_id,
+                &poll_option.ack_id,
+            );
+            info!(
+                room = poll_option.room_name.as_str(),
+                user_id = poll_option.owner_id,
+                event = "poll_option_already_exists";
+                "A poll-option with that title in this poll does already exist"
             );
-            println!("A poll-option with that title in this poll does already exist");
             return;
         }
 
         // clone later needed values
         let poll_option_title = poll_option.title.clone();
         let room_name = poll_option.room_name.clone();
+        let (owner_id, owner_name) = (poll_option.owner_id, poll_option.owner_name.clone());
 
         // add poll_option to poll
         poll.options.push(poll_option);
 
         // send poll option message to clients
-        let txt = json!(messages::outbound::PollOption {
-            r#type: messages::outbound::Types::PollOption,
-            pollobject: poll.title.clone(),
+        let poll_title = poll.title.clone();
+        let txt = messages::outbound::Message::PollOption(messages::outbound::PollOption {
+            pollobject: poll_title.clone(),
             polloptionobject: poll_option_title.clone(),
         })
         .to_string();
         self.send_message_all(&room_name, &txt);
+        self.record(
+            &room_name,
+            "polloption",
+            Some((owner_id, &owner_name)),
+            json!({ "poll": poll_title, "option": poll_option_title }),
+        );
     }
 }
 
-impl Handler<PollVoteHelper> for WebSocketServer {
+impl Handler<PollOptionRemove> for WebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, vote: PollVoteHelper, _: &mut Context<Self>) {
-        let room = self
-            .rooms
-            .entry(vote.room_name.clone())
-            .or_insert(Room::default());
-
-        // check if poll exists
-        let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &vote.poll_title);
-
-        if poll_exists.len() == 0 {
-            self.send_error_user(
-                &vote.room_name,
-                "poll_does_not_exist",
-                "A poll with that title doesn't exist",
-                vote.owner_id,
-            );
-            println!("A poll with that title doesn't exist");
+    fn handle(&mut self, remove: PollOptionRemove, _: &mut Context<Self>) {
+        if self.refuse_action(&remove.room_name, remove.sender_id, &remove.ack_id) {
             return;
         }
 
+        // get room
+        let room = match self.rooms.get_mut(remove.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
         // get poll
-        let poll_index = room
+        let poll = match room
+            .polls
+            .iter_mut()
+            .find(|poll| poll.title == remove.poll_title)
+        {
+            Some(poll) => poll,
+            None => {
+                self.send_error_user(
+                    &remove.room_name,
+                    "poll_does_not_exist",
+                    "A poll with that title doesn't exist",
+                    remove.sender_id,
+                    &remove.ack_id,
+                );
+                info!(
+                    room = remove.room_name.as_str(),
+                    user_id = remove.sender_id,
+                    event = "poll_does_not_exist";
+                    "A poll with that title doesn't exist"
+                );
+                return;
+            }
+        };
+
+        // check if user owns the poll
+        if poll.owner_id != remove.sender_id {
+            self.send_error_user(
+                &remove.room_name,
+                "no_permission",
+                "You do not have permission to remove poll options (because you're not the poll's owner)",
+                remove.sender_id,
+                &remove.ack_id,
+            );
+            info!(
+                room = remove.room_name.as_str(),
+                user_id = remove.sender_id,
+                event = "no_permission";
+                "User does not have permission to remove poll options (not owner)"
+            );
+            return;
+        }
+
+        // check if poll is closed
+        if poll.closed {
+            self.send_error_user(
+                &remove.room_name,
+                "poll_closed",
+                "Sorry, the poll is already closed",
+                remove.sender_id,
+                &remove.ack_id,
+            );
+            info!(
+                room = remove.room_name.as_str(),
+                user_id = remove.sender_id,
+                event = "poll_closed";
+                "Poll is already closed"
+            );
+            return;
+        }
+
+        // check if poll_option exists
+        if !poll
+            .options
+            .iter()
+            .any(|option| option.title == remove.title)
+        {
+            self.send_error_user(
+                &remove.room_name,
+                "poll_option_does_not_exist",
+                "A poll-option with that title in this poll doesn't exist",
+                remove.sender_id,
+                &remove.ack_id,
+            );
+            info!(
+                room = remove.room_name.as_str(),
+                user_id = remove.sender_id,
+                event = "poll_option_does_not_exist";
+                "Poll-Option with that title in this poll doesn't exist"
+            );
+            return;
+        }
+
+        // check if poll_option has votes
+        if poll
+            .votes
+            .values()
+            .flatten()
+            .any(|option_title| option_title == &remove.title)
+        {
+            self.send_error_user(
+                &remove.room_name,
+                "poll_option_has_votes",
+                "A poll-option which has already been voted for can't be removed",
+                remove.sender_id,
+                &remove.ack_id,
+            );
+            info!(
+                room = remove.room_name.as_str(),
+                user_id = remove.sender_id,
+                event = "poll_option_has_votes";
+                "Poll-Option has already been voted for"
+            );
+            return;
+        }
+
+        // remove poll_option from poll
+        poll.options.retain(|option| option.title != remove.title);
+
+        // send updated poll options to clients
+        let txt = messages::outbound::Message::PollOptions(messages::outbound::PollOptions {
+            pollobject: poll.title.clone(),
+            object: poll
+                .options
+                .iter()
+                .map(|option| option.title.clone())
+                .collect(),
+            ack_id: remove.ack_id.clone(),
+        })
+        .to_string();
+        self.send_message_all(&remove.room_name, &txt);
+    }
+}
+
+impl Handler<PollVoteHelper> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, vote: PollVoteHelper, ctx: &mut Context<Self>) {
+        if self.refuse_action(&vote.room_name, vote.owner_id, &vote.ack_id) {
+            return;
+        }
+
+        if vote.poll_title.trim().is_empty() || vote.option_title.trim().is_empty() {
+            self.send_vote_nack(
+                &vote,
+                "empty_title",
+                "The titles of the poll and the poll-option must not be empty",
+            );
+            info!(
+                room = vote.room_name.as_str(),
+                user_id = vote.owner_id,
+                event = "empty_title";
+                "Vote with an empty poll or poll-option title"
+            );
+            return;
+        }
+
+        let room = match self.rooms.get_mut(vote.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // check if poll exists
+        let mut poll_exists = room.polls.clone();
+        poll_exists.retain(|elem| elem.title == vote.poll_title);
+
+        if poll_exists.is_empty() {
+            self.send_vote_nack(
+                &vote,
+                "poll_does_not_exist",
+                "A poll with that title doesn't exist",
+            );
+            info!(
+                room = vote.room_name.as_str(),
+                user_id = vote.owner_id,
+                event = "poll_does_not_exist";
+                "A poll with that title doesn't exist"
+            );
+            return;
+        }
+
+        // get poll
+        let poll_index = room
             .polls
             .iter()
             .position(|poll| poll.title == vote.poll_title)
             .unwrap();
+        let voters = room.voters();
+        let weight = room.weight(&vote.owner_id);
         let poll = room.polls.get_mut(poll_index).unwrap();
 
         // check if poll is closed
         if poll.closed {
-            self.send_error_user(
-                &vote.room_name,
-                "poll_closed",
-                "Sorry, the poll is already closed",
-                vote.owner_id,
+            self.send_vote_nack(&vote, "poll_closed", "Sorry, the poll is already closed");
+            info!(
+                room = vote.room_name.as_str(),
+                user_id = vote.owner_id,
+                event = "poll_closed";
+                "Poll is already closed!"
             );
-            println!("Poll is already closed!");
             return;
         }
 
@@ -882,87 +3448,221 @@ impl Handler<PollVoteHelper> for WebSocketServer {
         poll_option_exists
             .retain(|existing_poll_option| existing_poll_option.title == vote.option_title);
 
-        if poll_option_exists.len() == 0 {
-            self.send_error_user(
-                &vote.room_name,
+        if poll_option_exists.is_empty() {
+            self.send_vote_nack(
+                &vote,
                 "poll_option_does_not_exist",
                 "A poll-option with that title in this poll doesn't exist",
-                vote.owner_id,
             );
-            println!("Poll-Option with that title in this poll doesn't exist");
+            info!(
+                room = vote.room_name.as_str(),
+                user_id = vote.owner_id,
+                event = "poll_option_does_not_exist";
+                "Poll-Option with that title in this poll doesn't exist"
+            );
             return;
         }
 
-        // check if user has already voted
-        let mut remove_vote = false;
-        let mut remove_vote_option_title = "".to_string();
-
-        if poll.votes.contains_key(&vote.owner_id) {
-            println!(
-                "User has already votes in this poll, removing existing vote and adding new vote."
-            );
+        // clone later needed values
+        let poll_title = poll.title.clone();
+        let multi = poll.multi;
+        let anonymous = poll.anonymous;
+        let user_votes = poll.votes.entry(vote.owner_id).or_default();
+
+        let (removed_option_titles, added) = if user_votes.contains(&vote.option_title) {
+            // check if user has already voted for this option
+            if !multi {
+                debug!(
+                    room = vote.room_name.as_str(),
+                    user_id = vote.owner_id,
+                    event = "duplicate_vote";
+                    "User has already voted for this option, ignoring vote."
+                );
+                self.send_vote_ack(&vote);
+                return;
+            }
 
-            // send delete vote message to clients
-            for (userid, poll_option_title) in poll.votes.clone() {
-                if userid == vote.owner_id {
-                    remove_vote = true;
-                    remove_vote_option_title = poll_option_title.to_string().clone();
-                    break;
-                }
+            // toggle vote off in multi-select polls
+            user_votes.retain(|option_title| option_title != &vote.option_title);
+            (vec![vote.option_title.clone()], false)
+        } else if multi {
+            // add vote in multi-select polls
+            user_votes.push(vote.option_title.clone());
+            (Vec::new(), true)
+        } else {
+            // add vote to poll, moving an existing vote of the user to the new option
+            let previous_option_titles =
+                std::mem::replace(user_votes, vec![vote.option_title.clone()]);
+            if !previous_option_titles.is_empty() {
+                info!(
+                    room = vote.room_name.as_str(),
+                    user_id = vote.owner_id,
+                    event = "vote_moved";
+                    "User has already voted in this poll, moved existing vote to new option."
+                );
             }
+            (previous_option_titles, true)
+        };
 
-            // remove existing vote
+        if user_votes.is_empty() {
             poll.votes.remove(&vote.owner_id);
+            poll.weights.remove(&vote.owner_id);
+        } else {
+            poll.weights.insert(vote.owner_id, weight);
+        }
+        let poll_results = poll.results();
+        // votes of users who were muted after voting don't stand in for missing ones
+        let everyone_voted = poll.auto_close_on_full
+            && !voters.is_empty()
+            && voters.iter().all(|id| poll.votes.contains_key(id));
+
+        // anonymous votes must not be traced back to their voter through the transcript
+        let voter = Some((vote.owner_id, vote.owner_name.as_str())).filter(|_| !anonymous);
+        for option_title in &removed_option_titles {
+            self.record(
+                &vote.room_name,
+                "unvote",
+                voter,
+                json!({ "poll": poll_title, "option": option_title }),
+            );
+        }
+        if added {
+            self.record(
+                &vote.room_name,
+                "vote",
+                voter,
+                json!({ "poll": poll_title, "option": vote.option_title }),
+            );
         }
 
-        // clone later needed values
-        let poll_option_title = vote.option_title.clone();
-        let poll_title = poll.title.clone();
+        // inform other users about removed votes
+        for option_title in removed_option_titles {
+            self.send_vote_delete(
+                &vote.room_name,
+                &poll_title,
+                &option_title,
+                vote.owner_id,
+                anonymous,
+            );
+        }
 
-        // add vote to poll
-        poll.votes.insert(vote.owner_id, vote.option_title);
+        // inform other users about the added vote
+        if added {
+            self.send_vote_ack(&vote);
 
-        // inform other users if one vote has to be removed
-        if remove_vote {
-            let elevated_txt = json!(messages::outbound::VoteDelete {
-                r#type: messages::outbound::Types::VoteDelete,
-                pollobject: poll_title.clone(),
-                polloptionobject: remove_vote_option_title.clone(),
-                userid: vote.owner_id,
-            })
-            .to_string();
-            let not_elevated_txt = json!(messages::outbound::VoteDelete {
-                r#type: messages::outbound::Types::VoteDelete,
-                pollobject: poll_title.clone(),
-                polloptionobject: remove_vote_option_title.clone(),
-                userid: 0,
-            })
-            .to_string();
+            self.send_vote(
+                &vote.room_name,
+                &poll_title,
+                &vote.option_title,
+                &vote.owner_name,
+                vote.owner_id,
+                anonymous,
+            );
+        }
 
-            self.send_message_all_elevated(&vote.room_name, &elevated_txt);
-            self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+        // send updated results to clients, right away or once at the end of the window
+        let window = self.config.results_coalesce_window;
+        if window.is_zero() {
+            let results_txt =
+                messages::outbound::Message::PollResults(messages::outbound::PollResults {
+                    pollobject: poll_title,
+                    object: poll_results,
+                    ack_id: vote.ack_id.clone(),
+                })
+                .to_string();
+            self.send_message_all(&vote.room_name, &results_txt);
+        } else if self
+            .pending_results
+            .insert((vote.room_name.clone(), poll_title.clone()))
+        {
+            let room_name = vote.room_name.clone();
+            ctx.run_later(window, move |act, _| {
+                act.send_pending_results(&room_name, &poll_title);
+            });
         }
 
-        // send poll option message to clients
-        let elevated_txt = json!(messages::outbound::Vote {
-            r#type: messages::outbound::Types::Vote,
-            pollobject: poll_title.clone(),
-            polloptionobject: poll_option_title.clone(),
-            username: vote.owner_name.clone(),
-            userid: vote.owner_id,
+        if everyone_voted {
+            self.close_poll(&vote.room_name, &vote.poll_title);
+        }
+    }
+}
+
+impl WebSocketServer {
+    /// send the current results of a poll whose results are waiting to be sent
+    ///
+    /// Does nothing if the results were sent already, e.g. because the poll was closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room the poll belongs to
+    /// * `poll_title` - a string slice with the title of the poll
+    fn send_pending_results(&mut self, room_name: &str, poll_title: &str) {
+        if self
+            .pending_results
+            .remove(&(room_name.to_string(), poll_title.to_string()))
+        {
+            self.send_poll_results(room_name, poll_title);
+        }
+    }
+
+    /// send the current results of a poll to all users in a room
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room the poll belongs to
+    /// * `poll_title` - a string slice with the title of the poll
+    fn send_poll_results(&mut self, room_name: &str, poll_title: &str) {
+        let poll_results = match self
+            .rooms
+            .get(room_name)
+            .and_then(|room| room.polls.iter().find(|poll| poll.title == poll_title))
+        {
+            Some(poll) => poll.results(),
+            None => return,
+        };
+
+        let results_txt =
+            messages::outbound::Message::PollResults(messages::outbound::PollResults {
+                pollobject: poll_title.to_string(),
+                object: poll_results,
+                ack_id: None,
+            })
+            .to_string();
+        self.send_message_all(room_name, &results_txt);
+    }
+
+    /// acknowledge a recorded vote to the voting user
+    ///
+    /// # Arguments
+    ///
+    /// * `vote` - the vote that has been recorded
+    fn send_vote_ack(&self, vote: &PollVoteHelper) {
+        let txt = messages::outbound::Message::VoteAck(messages::outbound::VoteAck {
+            pollobject: vote.poll_title.clone(),
+            polloptionobject: vote.option_title.clone(),
+            ack_id: vote.ack_id.clone(),
         })
         .to_string();
-        let not_elevated_txt = json!(messages::outbound::Vote {
-            r#type: messages::outbound::Types::Vote,
-            pollobject: poll_title.clone(),
-            polloptionobject: poll_option_title.clone(),
-            username: "".to_string(),
-            userid: 0,
+        self.send_message_user(&vote.room_name, &txt, vote.owner_id);
+    }
+
+    /// inform the voting user that a vote has been rejected
+    ///
+    /// # Arguments
+    ///
+    /// * `vote` - the vote that has been rejected
+    /// * `error_code` - a string slice with a short reason
+    /// * `error_description` - a string slice with a longer description why the vote was rejected
+    fn send_vote_nack(&self, vote: &PollVoteHelper, error_code: &str, error_description: &str) {
+        let txt = messages::outbound::Message::VoteNack(messages::outbound::VoteNack {
+            pollobject: vote.poll_title.clone(),
+            polloptionobject: vote.option_title.clone(),
+            object: error_code.to_string(),
+            description: error_description.to_string(),
+            ack_id: vote.ack_id.clone(),
         })
         .to_string();
-
-        self.send_message_all_elevated(&vote.room_name, &elevated_txt);
-        self.send_message_all_not_elevated(&vote.room_name, &not_elevated_txt);
+        self.send_message_user(&vote.room_name, &txt, vote.owner_id);
     }
 }
 
@@ -970,24 +3670,34 @@ impl Handler<PollCloseHelper> for WebSocketServer {
     type Result = ();
 
     fn handle(&mut self, close: PollCloseHelper, _: &mut Context<Self>) {
+        if self.refuse_action(&close.room_name, close.sender_id, &close.ack_id) {
+            return;
+        }
+
         // get room
-        let room = self
-            .rooms
-            .entry(close.room_name.clone())
-            .or_insert(Room::default());
+        let room = match self.rooms.get_mut(close.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
 
         // check if poll exists
         let mut poll_exists = room.polls.clone();
-        poll_exists.retain(|elem| &elem.title == &close.poll_title);
+        poll_exists.retain(|elem| elem.title == close.poll_title);
 
-        if poll_exists.len() == 0 {
+        if poll_exists.is_empty() {
             self.send_error_user(
                 &close.room_name,
                 "poll_does_not_exist",
                 "A poll with that title doesn't exist",
                 close.sender_id,
+                &close.ack_id,
+            );
+            info!(
+                room = close.room_name.as_str(),
+                user_id = close.sender_id,
+                event = "poll_does_not_exist";
+                "Poll with that title doesn't exist!"
             );
-            println!("Poll with that title doesn't exist!");
             return;
         }
 
@@ -997,7 +3707,25 @@ impl Handler<PollCloseHelper> for WebSocketServer {
             .iter()
             .position(|poll| poll.title == close.poll_title)
             .unwrap();
-        let poll = room.polls.get_mut(poll_index).unwrap();
+        let poll = room.polls.get(poll_index).unwrap();
+
+        // check if user owns the poll
+        if poll.owner_id != close.sender_id {
+            self.send_error_user(
+                &close.room_name,
+                "no_permission",
+                "You do not have permission to close this poll (because you're not its owner)",
+                close.sender_id,
+                &close.ack_id,
+            );
+            info!(
+                room = close.room_name.as_str(),
+                user_id = close.sender_id,
+                event = "no_permission";
+                "User does not have permission to close poll (not owner)"
+            );
+            return;
+        }
 
         // check if poll is closed
         if poll.closed {
@@ -1006,133 +3734,3489 @@ impl Handler<PollCloseHelper> for WebSocketServer {
                 "poll_closed",
                 "Sorry, the poll is already closed",
                 close.sender_id,
+                &close.ack_id,
+            );
+            info!(
+                room = close.room_name.as_str(),
+                user_id = close.sender_id,
+                event = "poll_closed";
+                "Poll is already closed!"
             );
-            println!("Poll is already closed!");
             return;
         }
 
-        // close poll
-        poll.closed = true;
-
-        // send poll option message to clients
-        let txt = json!(messages::outbound::PollClose {
-            r#type: messages::outbound::Types::PollClose,
-            object: poll.title.clone(),
-        })
-        .to_string();
-        self.send_message_all(&close.room_name, &txt);
+        self.close_poll(&close.room_name, &close.poll_title);
     }
 }
 
-impl WebSocketServer {
-    /// Handles managing priligiges on request
-    ///
-    /// # Arguments
-    /// * `room_name` - The room in which the user's priviliges should be changed
-    /// * `requested_id` - The user who requests the change. Elevated priviliges needed.
-    /// * `user_id` - The user whose priviliges should be changed.
-    /// * `elevated` - If the user should have elevated priviliges or not.
-    fn process_priviliges(
-        &mut self,
-        room_name: &String,
-        requester_id: usize,
-        user_id: usize,
-        elevated: bool,
-    ) -> Result<(), &'static str> {
-        if let Some(room) = self.rooms.get_mut(room_name) {
-            if room.is_elevated(&requester_id)? && room.is_elevated(&user_id)? != elevated {
-                room.set_elevated(&user_id, elevated);
-
-                // resend votes (with user_id and user_name) for open polls
-                let room_imut = room.clone();
-                for poll in room_imut.polls.clone() {
-                    if !poll.closed {
-                        // send votes for poll
-                        for (userid, option_title) in poll.votes.clone() {
-                            let user = room_imut.connected.get(&userid).unwrap();
+impl Handler<PollOptionsQuery> for WebSocketServer {
+    type Result = ();
 
-                            if elevated {
-                                let del_vote_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    userid: 0,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &del_vote_txt, user_id);
-
-                                let vote_txt = json!(messages::outbound::Vote {
-                                    r#type: messages::outbound::Types::Vote,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    username: user.name.clone(),
-                                    userid: userid,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &vote_txt, user_id);
-                            } else {
-                                let del_vote_txt = json!(messages::outbound::VoteDelete {
-                                    r#type: messages::outbound::Types::VoteDelete,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    userid: userid,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &del_vote_txt, user_id);
-
-                                let vote_txt = json!(messages::outbound::Vote {
-                                    r#type: messages::outbound::Types::Vote,
-                                    pollobject: poll.title.clone(),
-                                    polloptionobject: option_title.clone(),
-                                    username: "".to_string(),
-                                    userid: 0,
-                                })
-                                .to_string();
-                                self.send_message_user(&room_name, &vote_txt, user_id);
-                            }
-                        }
-                    }
-                }
+    fn handle(&mut self, query: PollOptionsQuery, _: &mut Context<Self>) {
+        let options = self.rooms.get(&query.room_name).and_then(|room| {
+            room.polls
+                .iter()
+                .find(|poll| poll.title == query.poll_title)
+                .map(|poll| {
+                    poll.options
+                        .iter()
+                        .map(|option| option.title.clone())
+                        .collect()
+                })
+        });
 
-                return Ok(());
+        match options {
+            Some(options) => {
+                let txt =
+                    messages::outbound::Message::PollOptions(messages::outbound::PollOptions {
+                        pollobject: query.poll_title,
+                        object: options,
+                        ack_id: query.ack_id,
+                    })
+                    .to_string();
+                self.send_message_user(&query.room_name, &txt, query.sender_id);
             }
+            None => self.send_error_user(
+                &query.room_name,
+                "poll_does_not_exist",
+                "A poll with that title doesn't exist",
+                query.sender_id,
+                &query.ack_id,
+            ),
         }
-        Err("")
     }
 }
 
-impl Handler<Elevate> for WebSocketServer {
+impl Handler<PollDelete> for WebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Elevate, _: &mut Context<Self>) {
-        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true) {
-            Err(_) => (),
-            Ok(_) => {
-                let txt = json!(messages::outbound::PermissionChange {
-                    r#type: messages::outbound::Types::Elevated,
-                    object: msg.object,
-                })
-                .to_string();
-                self.send_message_all(&msg.room_name, &txt);
+    fn handle(&mut self, delete: PollDelete, _: &mut Context<Self>) {
+        if self.refuse_action(&delete.room_name, delete.sender_id, &delete.ack_id) {
+            return;
+        }
+
+        // get room
+        let room = match self.rooms.get_mut(delete.room_name.as_str()) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // get poll
+        let poll_index = match room
+            .polls
+            .iter()
+            .position(|poll| poll.title == delete.poll_title)
+        {
+            Some(poll_index) => poll_index,
+            None => {
+                self.send_error_user(
+                    &delete.room_name,
+                    "poll_does_not_exist",
+                    "A poll with that title doesn't exist",
+                    delete.sender_id,
+                    &delete.ack_id,
+                );
+                info!(
+                    room = delete.room_name.as_str(),
+                    user_id = delete.sender_id,
+                    event = "poll_does_not_exist";
+                    "Poll with that title doesn't exist!"
+                );
+                return;
             }
+        };
+
+        // check if user owns the poll
+        if room.polls[poll_index].owner_id != delete.sender_id {
+            self.send_error_user(
+                &delete.room_name,
+                "no_permission",
+                "You do not have permission to delete this poll (because you're not its owner)",
+                delete.sender_id,
+                &delete.ack_id,
+            );
+            info!(
+                room = delete.room_name.as_str(),
+                user_id = delete.sender_id,
+                event = "no_permission";
+                "User does not have permission to delete poll (not owner)"
+            );
+            return;
         }
+
+        // delete poll
+        let poll = room.polls.remove(poll_index);
+
+        // send poll deleted message to clients
+        let txt = messages::outbound::Message::PollDeleted(messages::outbound::PollClose {
+            object: poll.title,
+        })
+        .to_string();
+        self.send_message_all(&delete.room_name, &txt);
     }
 }
 
-impl Handler<Recede> for WebSocketServer {
+impl Handler<PollRename> for WebSocketServer {
     type Result = ();
 
-    fn handle(&mut self, msg: Recede, _: &mut Context<Self>) {
-        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, false) {
-            Err(_) => (),
-            Ok(_) => {
-                let txt = json!(messages::outbound::PermissionChange {
-                    r#type: messages::outbound::Types::Receded,
-                    object: msg.object,
-                })
-                .to_string();
-                self.send_message_all(&msg.room_name, &txt);
-            }
+    fn handle(&mut self, rename: PollRename, ctx: &mut Context<Self>) {
+        if self.refuse_action(&rename.room_name, rename.sender_id, &rename.ack_id) {
+            return;
+        }
+
+        // polls are looked up by their title, so it can't be blank
+        if rename.new_title.trim().is_empty() {
+            self.send_error_user(
+                &rename.room_name,
+                "empty_title",
+                "The title of a poll must not be empty",
+                rename.sender_id,
+                &rename.ack_id,
+            );
+            return;
         }
+
+        let room = match self.rooms.get_mut(&rename.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        // get poll
+        let poll_index = match room
+            .polls
+            .iter()
+            .position(|poll| poll.title == rename.poll_title)
+        {
+            Some(poll_index) => poll_index,
+            None => {
+                self.send_error_user(
+                    &rename.room_name,
+                    "poll_does_not_exist",
+                    "A poll with that title doesn't exist",
+                    rename.sender_id,
+                    &rename.ack_id,
+                );
+                return;
+            }
+        };
+
+        // check if user owns the poll
+        if room.polls[poll_index].owner_id != rename.sender_id {
+            self.send_error_user(
+                &rename.room_name,
+                "no_permission",
+                "You do not have permission to rename this poll (because you're not its owner)",
+                rename.sender_id,
+                &rename.ack_id,
+            );
+            info!(
+                room = rename.room_name.as_str(),
+                user_id = rename.sender_id,
+                event = "no_permission";
+                "User does not have permission to rename poll (not owner)"
+            );
+            return;
+        }
+
+        // voters must not find their vote under another title
+        if room.polls[poll_index].closed || !room.polls[poll_index].votes.is_empty() {
+            self.send_error_user(
+                &rename.room_name,
+                "poll_has_votes",
+                "Polls can only be renamed before anybody votes",
+                rename.sender_id,
+                &rename.ack_id,
+            );
+            return;
+        }
+
+        // check if poll already exists (ignoring surrounding whitespace)
+        if room
+            .polls
+            .iter()
+            .any(|poll| poll.title.trim() == rename.new_title.trim())
+        {
+            self.send_error_user(
+                &rename.room_name,
+                "poll_already_exists",
+                "A poll with that title already exists",
+                rename.sender_id,
+                &rename.ack_id,
+            );
+            return;
+        }
+
+        // rename poll and its options
+        let poll = &mut room.polls[poll_index];
+        poll.title = rename.new_title.clone();
+        for option in &mut poll.options {
+            option.poll_title = rename.new_title.clone();
+        }
+        // reactions are kept by the title of the poll they belong to
+        if let Some(reactions) = room.reactions.remove(&rename.poll_title) {
+            room.reactions.insert(rename.new_title.clone(), reactions);
+        }
+
+        // results waiting to be sent follow the poll, the timer of the old title finds nothing
+        if self
+            .pending_results
+            .remove(&(rename.room_name.clone(), rename.poll_title.clone()))
+        {
+            let (room_name, poll_title) = (rename.room_name.clone(), rename.new_title.clone());
+            self.pending_results
+                .insert((room_name.clone(), poll_title.clone()));
+            ctx.run_later(self.config.results_coalesce_window, move |act, _| {
+                act.send_pending_results(&room_name, &poll_title);
+            });
+        }
+
+        info!(
+            room = rename.room_name.as_str(),
+            user_id = rename.sender_id,
+            event = "poll_renamed";
+            "Poll renamed"
+        );
+
+        // send poll renamed message to clients
+        let txt = messages::outbound::Message::PollRenamed(messages::outbound::PollRenamed {
+            pollobject: rename.poll_title,
+            object: rename.new_title,
+        })
+        .to_string();
+        self.send_message_all(&rename.room_name, &txt);
+    }
+}
+
+impl WebSocketServer {
+    /// close a poll and inform all users in the room
+    ///
+    /// Nothing happens if the poll does not exist or is already closed.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room the poll belongs to
+    /// * `poll_title` - a string slice with the title of the poll to close
+    fn close_poll(&mut self, room_name: &str, poll_title: &str) {
+        if !self.finish_poll(room_name, poll_title) {
+            return;
+        }
+
+        // send poll close message to clients
+        let txt = messages::outbound::Message::PollClose(messages::outbound::PollClose {
+            object: poll_title.to_string(),
+        })
+        .to_string();
+        self.send_message_all(room_name, &txt);
+    }
+}
+
+impl WebSocketServer {
+    /// mark a poll closed and archive it, without informing the users in the room
+    ///
+    /// Returns whether the poll was open.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room the poll belongs to
+    /// * `poll_title` - a string slice with the title of the poll to close
+    fn finish_poll(&mut self, room_name: &str, poll_title: &str) -> bool {
+        // clients get the final results before the poll is closed
+        self.send_pending_results(room_name, poll_title);
+
+        let poll = match self.rooms.get_mut(room_name).and_then(|room| {
+            room.polls
+                .iter_mut()
+                .find(|poll| poll.title == poll_title && !poll.closed)
+        }) {
+            Some(poll) => poll,
+            None => return false,
+        };
+
+        // close poll
+        poll.closed = true;
+
+        // archive poll results and hand them to the webhook
+        let archive = PollArchive::new(room_name, poll);
+        if let Some(archive_dir) = &self.config.poll_archive_dir {
+            archive_poll(archive_dir, room_name, poll.id, &archive);
+        }
+        let closed = json!({ "title": archive.title, "results": archive.results });
+        if let Some(url) = &self.config.poll_webhook_url {
+            actix::spawn(post_poll_webhook(url.clone(), archive));
+        }
+        self.record(room_name, "pollclosed", None, closed);
+        true
+    }
+}
+
+impl WebSocketServer {
+    /// message with the options of a room, `None` if the room doesn't exist
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    fn room_options_message(&self, room_name: &str) -> Option<String> {
+        let room = self.rooms.get(room_name)?;
+        Some(
+            messages::outbound::Message::RoomOptions(messages::outbound::RoomOptions {
+                object: room.options.clone(),
+            })
+            .to_string(),
+        )
+    }
+}
+
+/// Largest weight the host may give a user
+const MAX_VOTE_WEIGHT: usize = 1000;
+
+impl Handler<SetWeight> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetWeight, _: &mut Context<Self>) {
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to set voting weights (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        if msg.weight > MAX_VOTE_WEIGHT {
+            self.send_error_user(
+                &msg.room_name,
+                "invalid_weight",
+                &format!("Weights can't be larger than {}", MAX_VOTE_WEIGHT),
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        // votes already cast keep their weight, only the following ones count differently
+        let user = match room.connected.get_mut(&msg.user_id) {
+            Some(user) => user,
+            None => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "user_does_not_exist",
+                    "Refusing to set weight, user is not in the room",
+                    msg.sender_id,
+                    &msg.ack_id,
+                );
+                return;
+            }
+        };
+        user.weight = msg.weight;
+
+        info!(
+            room = msg.room_name.as_str(),
+            user_id = msg.user_id,
+            weight = msg.weight,
+            event = "weight_set";
+            "Voting weight changed"
+        );
+
+        // weights aren't broadcast, so they can't be matched with changes of anonymous results
+        let txt = messages::outbound::Message::WeightChanged(messages::outbound::WeightChanged {
+            user_id: msg.user_id,
+            object: msg.weight,
+        })
+        .to_string();
+        self.send_message_user(&msg.room_name, &txt, msg.user_id);
+        if msg.sender_id != msg.user_id {
+            self.send_message_user(&msg.room_name, &txt, msg.sender_id);
+        }
+    }
+}
+
+impl Handler<SetRoomOption> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetRoomOption, _: &mut Context<Self>) {
+        let room = match self.rooms.get_mut(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to change room options (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            return;
+        }
+
+        match msg.option.as_str() {
+            "host_only_polls" => room.options.host_only_polls = msg.value,
+            _ => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "unknown_room_option",
+                    "A room option with that name doesn't exist",
+                    msg.sender_id,
+                    &msg.ack_id,
+                );
+                return;
+            }
+        }
+
+        info!(
+            room = msg.room_name.as_str(),
+            user_id = msg.sender_id,
+            option = msg.option.as_str(),
+            value = msg.value,
+            event = "room_option_set";
+            "Room option changed"
+        );
+
+        if let Some(txt) = self.room_options_message(&msg.room_name) {
+            self.send_message_all(&msg.room_name, &txt);
+        }
+    }
+}
+
+impl Handler<CloseAllPolls> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: CloseAllPolls, _: &mut Context<Self>) {
+        let room = match self.rooms.get(&msg.room_name) {
+            Some(room) => room,
+            None => return,
+        };
+
+        if !room.is_host(&msg.sender_id) {
+            self.send_error_user(
+                &msg.room_name,
+                "no_permission",
+                "You do not have permission to close all polls (because you're not the host)",
+                msg.sender_id,
+                &msg.ack_id,
+            );
+            info!(
+                room = msg.room_name.as_str(),
+                user_id = msg.sender_id,
+                event = "no_permission";
+                "User does not have permission to close all polls (not host)"
+            );
+            return;
+        }
+
+        // already closed polls are left alone
+        let open: Vec<String> = room
+            .polls
+            .iter()
+            .filter(|poll| !poll.closed)
+            .map(|poll| poll.title.clone())
+            .collect();
+        let closed: Vec<String> = open
+            .into_iter()
+            .filter(|poll_title| self.finish_poll(&msg.room_name, poll_title))
+            .collect();
+
+        if closed.is_empty() {
+            return;
+        }
+
+        info!(
+            room = msg.room_name.as_str(),
+            user_id = msg.sender_id,
+            polls = closed.len(),
+            event = "polls_closed";
+            "All polls closed"
+        );
+
+        // one message for all polls instead of one per poll
+        let txt = messages::outbound::Message::PollsClosed(messages::outbound::PollsClosed {
+            object: closed,
+        })
+        .to_string();
+        self.send_message_all(&msg.room_name, &txt);
+    }
+}
+
+/// Archived state of a closed poll, also the body of the poll webhook
+#[derive(Serialize)]
+struct PollArchive {
+    room: String,
+    title: String,
+    options: Vec<String>,
+    results: HashMap<String, messages::outbound::PollOptionResult>,
+}
+
+impl PollArchive {
+    fn new(room_name: &str, poll: &Poll) -> PollArchive {
+        PollArchive {
+            room: room_name.to_string(),
+            title: poll.title.clone(),
+            options: poll
+                .options
+                .iter()
+                .map(|option| option.title.clone())
+                .collect(),
+            results: poll.results(),
+        }
+    }
+}
+
+/// Number of times the results of a poll are posted to the webhook before giving up
+const WEBHOOK_ATTEMPTS: u32 = 3;
+
+/// post the results of a closed poll to the webhook, retrying failed attempts
+///
+/// Runs on the arbiter of the server without blocking it. Attempts are 2 and 4 seconds apart,
+/// responses with a status other than 2xx count as failed. The URL isn't logged, as webhook
+/// URLs often contain a secret.
+///
+/// # Arguments
+///
+/// * `url` - the URL of the webhook
+/// * `archive` - the results of the poll, sent as JSON
+async fn post_poll_webhook(url: String, archive: PollArchive) {
+    let client = Client::default();
+    for attempt in 1..=WEBHOOK_ATTEMPTS {
+        let failure = match client
+            .post(&url)
+            .timeout(Duration::from_secs(10))
+            .send_json(&archive)
+            .await
+        {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => response.status().to_string(),
+            Err(err) => err.to_string(),
+        };
+        warn!(
+            room = archive.room.as_str(),
+            attempt = attempt,
+            error = failure.as_str(),
+            event = "poll_webhook_failed";
+            "Failed to post poll results to webhook"
+        );
+        if attempt < WEBHOOK_ATTEMPTS {
+            delay_for(Duration::from_secs(1 << attempt)).await;
+        }
+    }
+    error!(
+        room = archive.room.as_str(),
+        event = "poll_webhook_abandoned";
+        "Giving up posting poll results to webhook"
+    );
+}
+
+/// hash a room password, salted with the room name
+fn hash_password(room_name: &str, password: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(room_name.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(password.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// compare two byte slices in time independent of their content
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// current time in milliseconds since the unix epoch
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// write the results of a poll to a JSON file in the background
+///
+/// The file is named after the room, the current time and the id of the poll, so polls with
+/// the same title, or several polls closed at once, don't overwrite each other.
+///
+/// # Arguments
+///
+/// * `archive_dir` - the directory the file is written to
+/// * `room_name` - a string slice with the name of the room the poll belongs to
+/// * `poll_id` - the id of the poll, unique for the server
+/// * `archive` - the state of the closed poll
+fn archive_poll(archive_dir: &Path, room_name: &str, poll_id: usize, archive: &PollArchive) {
+    let contents = json!(archive).to_string();
+    let timestamp = now_millis();
+    let safe_room_name: String = room_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = archive_dir.join(format!("{}-{}-{}.json", safe_room_name, timestamp, poll_id));
+
+    thread::spawn(move || {
+        if let Err(err) = fs::write(&path, contents) {
+            error!(path:% = path.display(), error:% = err; "Failed to archive poll");
+        }
+    });
+}
+
+impl WebSocketServer {
+    /// Handles managing priligiges on request
+    ///
+    /// # Arguments
+    /// * `room_name` - The room in which the user's priviliges should be changed
+    /// * `requested_id` - The user who requests the change. Needs to be the room's host.
+    /// * `user_id` - The user whose priviliges should be changed.
+    /// * `elevated` - If the user should have elevated priviliges or not.
+    fn process_priviliges(
+        &mut self,
+        room_name: &String,
+        requester_id: usize,
+        user_id: usize,
+        elevated: bool,
+    ) -> Result<(), &'static str> {
+        if let Some(room) = self.rooms.get_mut(room_name) {
+            if !room.is_host(&requester_id) {
+                return Err("no_permission");
+            }
+            // the user already has the requested priviliges, so nothing changes and nothing
+            // is broadcast, e.g. when receding a user who is not elevated
+            if room.is_elevated(&user_id)? == elevated {
+                return Err("unchanged");
+            }
+
+            room.set_elevated(&user_id, elevated);
+
+            // resend votes (with user_id and user_name) for open, not anonymous polls
+            let room_imut = room.clone();
+            for poll in room_imut.polls.clone() {
+                if !poll.closed && !poll.anonymous {
+                    // send votes for poll
+                    for (userid, option_titles) in poll.votes.clone() {
+                        let user = room_imut.connected.get(&userid).unwrap();
+
+                        for option_title in option_titles {
+                            if elevated {
+                                let del_vote_txt = messages::outbound::Message::VoteDelete(
+                                    messages::outbound::VoteDelete {
+                                        pollobject: poll.title.clone(),
+                                        polloptionobject: option_title.clone(),
+                                        userid: 0,
+                                    },
+                                )
+                                .to_string();
+                                self.send_message_user(room_name, &del_vote_txt, user_id);
+
+                                let vote_txt =
+                                    messages::outbound::Message::Vote(messages::outbound::Vote {
+                                        pollobject: poll.title.clone(),
+                                        polloptionobject: option_title.clone(),
+                                        username: user.name.clone(),
+                                        userid,
+                                    })
+                                    .to_string();
+                                self.send_message_user(room_name, &vote_txt, user_id);
+                            } else {
+                                let del_vote_txt = messages::outbound::Message::VoteDelete(
+                                    messages::outbound::VoteDelete {
+                                        pollobject: poll.title.clone(),
+                                        polloptionobject: option_title.clone(),
+                                        userid,
+                                    },
+                                )
+                                .to_string();
+                                self.send_message_user(room_name, &del_vote_txt, user_id);
+
+                                let vote_txt =
+                                    messages::outbound::Message::Vote(messages::outbound::Vote {
+                                        pollobject: poll.title.clone(),
+                                        polloptionobject: option_title.clone(),
+                                        username: "".to_string(),
+                                        userid: 0,
+                                    })
+                                    .to_string();
+                                self.send_message_user(room_name, &vote_txt, user_id);
+                            }
+                        }
+                    }
+                }
+            }
+
+            return Ok(());
+        }
+        Err("")
+    }
+}
+
+impl Handler<Elevate> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Elevate, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, true) {
+            Err("no_permission") => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "no_permission",
+                    "You do not have permission to elevate users (because you're not the host)",
+                    msg.owner_id,
+                    &msg.ack_id,
+                );
+                info!(
+                    room = msg.room_name.as_str(),
+                    user_id = msg.owner_id,
+                    event = "no_permission";
+                    "User does not have permission to elevate users (not host)"
+                );
+            }
+            Err(_) => (),
+            Ok(_) => {
+                let txt =
+                    messages::outbound::Message::Elevated(messages::outbound::PermissionChange {
+                        object: msg.object,
+                    })
+                    .to_string();
+                self.send_message_all(&msg.room_name, &txt);
+
+                // elevated users are speaking, so their raised objects are lowered
+                let lowered = match self.rooms.get_mut(&msg.room_name) {
+                    Some(room) => room.lower_user(&msg.object),
+                    None => false,
+                };
+                if lowered {
+                    self.send_hand_queue(&msg.room_name);
+                }
+            }
+        }
+    }
+}
+
+impl Handler<Recede> for WebSocketServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Recede, _: &mut Context<Self>) {
+        if self.refuse_action(&msg.room_name, msg.owner_id, &msg.ack_id) {
+            return;
+        }
+
+        match self.process_priviliges(&msg.room_name, msg.owner_id, msg.object, false) {
+            Err("no_permission") => {
+                self.send_error_user(
+                    &msg.room_name,
+                    "no_permission",
+                    "You do not have permission to recede users (because you're not the host)",
+                    msg.owner_id,
+                    &msg.ack_id,
+                );
+                info!(
+                    room = msg.room_name.as_str(),
+                    user_id = msg.owner_id,
+                    event = "no_permission";
+                    "User does not have permission to recede users (not host)"
+                );
+            }
+            Err(_) => (),
+            Ok(_) => {
+                let txt =
+                    messages::outbound::Message::Receded(messages::outbound::PermissionChange {
+                        object: msg.object,
+                    })
+                    .to_string();
+                self.send_message_all(&msg.room_name, &txt);
+
+                // users keep their raised objects when receded, but lose their head start
+                let waiting = self.rooms.get(&msg.room_name).is_some_and(|room| {
+                    room.raised
+                        .iter()
+                        .any(|raised| raised.owner_id == msg.object)
+                });
+                if waiting {
+                    self.send_speaking_order(&msg.room_name);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for the session of a client, keeping the messages the server sends it
+    ///
+    /// The last message of a closed session is kept like any other.
+    #[derive(Default)]
+    struct Probe {
+        received: Vec<Arbitrary>,
+    }
+
+    impl Actor for Probe {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for Probe {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Context<Self>) {
+            self.received.push(serde_json::from_str(&msg.0).unwrap());
+        }
+    }
+
+    impl Handler<Close> for Probe {
+        type Result = ();
+
+        fn handle(&mut self, msg: Close, _: &mut Context<Self>) {
+            self.received.push(serde_json::from_str(&msg.0).unwrap());
+        }
+    }
+
+    /// Take the messages a probe received since it was last asked
+    #[derive(Message)]
+    #[rtype(result = "Vec<Arbitrary>")]
+    struct Take;
+
+    impl Handler<Take> for Probe {
+        type Result = MessageResult<Take>;
+
+        fn handle(&mut self, _: Take, _: &mut Context<Self>) -> Self::Result {
+            MessageResult(std::mem::take(&mut self.received))
+        }
+    }
+
+    /// A user in a room, connected through a probe
+    struct Member {
+        id: usize,
+        name: String,
+        room: String,
+        probe: Addr<Probe>,
+    }
+
+    impl Member {
+        /// messages received since the last call
+        async fn received(&self) -> Vec<Arbitrary> {
+            self.probe.send(Take).await.unwrap()
+        }
+
+        /// messages of a type received since the last call, messages of other types are dropped
+        async fn received_of(&self, kind: &str) -> Vec<Arbitrary> {
+            let mut received = self.received().await;
+            received.retain(|message| message["type"] == kind);
+            received
+        }
+    }
+
+    /// settings with every variable unset
+    fn config() -> Config {
+        config_with(&[])
+    }
+
+    /// settings with some variables set
+    fn config_with(vars: &[(&str, &str)]) -> Config {
+        Config::from_vars(|key| {
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.into())
+        })
+        .unwrap()
+    }
+
+    fn start(config: Config) -> Addr<WebSocketServer> {
+        WebSocketServer::new(config, 0, RoomsPerIp::default(), RoomCount::default()).start()
+    }
+
+    /// a probe with enough room for all messages of a test, it's only read when asked
+    fn probe() -> Addr<Probe> {
+        Probe::create(|ctx| {
+            ctx.set_mailbox_capacity(1024);
+            Probe::default()
+        })
+    }
+
+    fn join_of(probe: &Addr<Probe>, user_id: usize, user_name: &str, room_name: &str) -> Join {
+        Join {
+            addr: probe.clone().recipient(),
+            close: probe.clone().recipient(),
+            user_id,
+            user_name: user_name.to_string(),
+            room_name: room_name.to_string(),
+            password: None,
+            token: None,
+            ip: None,
+            spectator: false,
+            color: None,
+            avatar: None,
+        }
+    }
+
+    /// join a room, panicking if the server refuses
+    async fn join(
+        server: &Addr<WebSocketServer>,
+        user_id: usize,
+        user_name: &str,
+        room_name: &str,
+    ) -> Member {
+        let probe = probe();
+        let id = server
+            .send(join_of(&probe, user_id, user_name, room_name))
+            .await
+            .unwrap()
+            .unwrap_or_else(|rejection| panic!("join refused: {}", rejection.message));
+        Member {
+            id,
+            name: user_name.to_string(),
+            room: room_name.to_string(),
+            probe,
+        }
+    }
+
+    /// the resume token a member got when joining
+    async fn token_of(member: &Member) -> String {
+        let tokens = member.received_of("resumetoken").await;
+        tokens[0]["object"].as_str().unwrap().to_string()
+    }
+
+    fn poll_of(member: &Member, title: &str) -> Poll {
+        Poll {
+            title: title.to_string(),
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            id: 0,
+            options: Vec::new(),
+            votes: HashMap::new(),
+            weights: HashMap::new(),
+            closed: false,
+            multi: false,
+            anonymous: false,
+            duration_secs: None,
+            auto_close_on_full: false,
+            ack_id: None,
+        }
+    }
+
+    fn option_of(member: &Member, poll_title: &str, title: &str) -> PollOption {
+        PollOption {
+            title: title.to_string(),
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            ack_id: None,
+        }
+    }
+
+    fn remove_option_of(member: &Member, poll_title: &str, title: &str) -> PollOptionRemove {
+        PollOptionRemove {
+            title: title.to_string(),
+            sender_id: member.id,
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            ack_id: None,
+        }
+    }
+
+    fn vote_of(member: &Member, poll_title: &str, option_title: &str) -> PollVoteHelper {
+        PollVoteHelper {
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            option_title: option_title.to_string(),
+            ack_id: None,
+        }
+    }
+
+    fn close_of(member: &Member, poll_title: &str) -> PollCloseHelper {
+        PollCloseHelper {
+            sender_id: member.id,
+            sender_name: member.name.clone(),
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            ack_id: None,
+        }
+    }
+
+    fn delete_of(member: &Member, poll_title: &str) -> PollDelete {
+        PollDelete {
+            sender_id: member.id,
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            ack_id: None,
+        }
+    }
+
+    fn instant_of(member: &Member, text: &str) -> Instant {
+        Instant {
+            object: json!(text),
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            ttl_secs: None,
+            reply_to: None,
+            ack_id: None,
+        }
+    }
+
+    fn edit_of(member: &Member, message_id: usize, text: &str) -> Edit {
+        Edit {
+            message_id,
+            object: json!(text),
+            owner_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn typing_of(member: &Member, active: bool) -> Typing {
+        Typing {
+            active,
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn react_of(member: &Member, target: &str, emoji: &str) -> React {
+        React {
+            target: target.to_string(),
+            emoji: emoji.to_string(),
+            owner_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    /// reaction counts of the last `reactions` message
+    fn reactions(received: &[Arbitrary]) -> Arbitrary {
+        received
+            .iter()
+            .rfind(|message| message["type"] == "reactions")
+            .expect("no reactions received")["object"]
+            .clone()
+    }
+
+    fn raise_of(member: &Member, object: &str) -> Raise {
+        Raise {
+            object: json!(object),
+            reason: None,
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn lower_of(member: &Member, object: &str) -> Lower {
+        Lower {
+            object: json!(object),
+            owner_id: member.id,
+            owner_name: member.name.clone(),
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn elevate_of(member: &Member, user_id: usize) -> Elevate {
+        Elevate {
+            object: user_id,
+            owner_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn recede_of(member: &Member, user_id: usize) -> Recede {
+        Recede {
+            object: user_id,
+            owner_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    fn kick_of(member: &Member, user_id: usize) -> Kick {
+        Kick {
+            user_id,
+            sender_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    /// names of the owners of the raised objects in the last `handqueue` message
+    fn queued(received: &[Arbitrary]) -> Vec<String> {
+        let queue = received
+            .iter()
+            .rfind(|message| message["type"] == "handqueue")
+            .expect("no hand queue received");
+        queue["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|raised| raised["owner_name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    /// create a poll with some options
+    async fn create_poll(
+        server: &Addr<WebSocketServer>,
+        member: &Member,
+        poll: Poll,
+        options: &[&str],
+    ) {
+        let title = poll.title.clone();
+        server.send(poll).await.unwrap();
+        for option in options {
+            server
+                .send(option_of(member, &title, option))
+                .await
+                .unwrap();
+        }
+    }
+
+    #[actix_rt::test]
+    async fn repeated_vote_is_counted_once() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "pasta"],
+        )
+        .await;
+        alice.received().await;
+
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let results = alice.received_of("pollresults").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["object"]["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn second_vote_moves_to_the_new_option() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "pasta"],
+        )
+        .await;
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        alice.received().await;
+
+        server
+            .send(vote_of(&alice, "lunch", "pasta"))
+            .await
+            .unwrap();
+
+        let received = alice.received().await;
+        let deleted: Vec<_> = received
+            .iter()
+            .filter(|message| message["type"] == "votedelete")
+            .collect();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["polloptionobject"], "pizza");
+        let results = received
+            .iter()
+            .rfind(|message| message["type"] == "pollresults")
+            .unwrap();
+        assert_eq!(results["object"]["pizza"]["count"], 0);
+        assert_eq!(results["object"]["pasta"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn vote_sends_results_once_to_every_member() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+
+        for member in &[&alice, &bob] {
+            let results = member.received_of("pollresults").await;
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0]["pollobject"], "lunch");
+            assert_eq!(results[0]["object"]["pizza"]["count"], 1);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn vote_in_unknown_or_closed_poll_sends_no_results() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+        alice.received().await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "dinner", "pizza")).await.unwrap();
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+
+        assert!(alice.received().await.is_empty());
+        let nacks = bob.received_of("votenack").await;
+        assert_eq!(nacks.len(), 2);
+        assert_eq!(nacks[0]["object"], "poll_does_not_exist");
+        assert_eq!(nacks[1]["object"], "poll_closed");
+    }
+
+    #[actix_rt::test]
+    async fn poll_closes_after_its_duration() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let mut poll = poll_of(&alice, "lunch");
+        poll.duration_secs = Some(1);
+        create_poll(&server, &alice, poll, &["pizza"]).await;
+        alice.received().await;
+
+        delay_for(Duration::from_millis(500)).await;
+        assert!(alice.received_of("pollclose").await.is_empty());
+
+        delay_for(Duration::from_millis(700)).await;
+        let closed = alice.received_of("pollclose").await;
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0]["object"], "lunch");
+    }
+
+    #[actix_rt::test]
+    async fn only_the_owner_closes_a_poll() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(close_of(&bob, "lunch")).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(alice.received().await.is_empty());
+
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+        for member in &[&alice, &bob] {
+            let closed = member.received_of("pollclose").await;
+            assert_eq!(closed.len(), 1);
+            assert_eq!(closed[0]["object"], "lunch");
+        }
+    }
+
+    #[actix_rt::test]
+    async fn multi_select_votes_toggle() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let mut poll = poll_of(&alice, "lunch");
+        poll.multi = true;
+        create_poll(&server, &alice, poll, &["pizza", "pasta"]).await;
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(vote_of(&alice, "lunch", "pasta"))
+            .await
+            .unwrap();
+
+        let results = alice.received_of("pollresults").await;
+        let last = &results.last().unwrap()["object"];
+        assert_eq!(last["pizza"]["count"], 1);
+        assert_eq!(last["pasta"]["count"], 1);
+
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+
+        let results = alice.received_of("pollresults").await;
+        let last = &results.last().unwrap()["object"];
+        assert_eq!(last["pizza"]["count"], 0);
+        assert_eq!(last["pasta"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn anonymous_votes_are_not_traced_to_their_voter() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let mut poll = poll_of(&alice, "lunch");
+        poll.anonymous = true;
+        create_poll(&server, &alice, poll, &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+
+        // alice is elevated and would see voters of other polls
+        let received = alice.received().await;
+        assert!(received
+            .iter()
+            .any(|message| message["type"] == "pollresults"));
+        for message in &received {
+            assert!(!message.to_string().contains("bob"));
+            assert!(message.get("user_id").is_none());
+            assert!(message.get("owner_name").is_none());
+            if message["type"] == "vote" {
+                assert_eq!(message["userid"], 0);
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn poll_titles_are_unique_per_room() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let carol = join(&server, 3, "carol", "other").await;
+        server.send(poll_of(&alice, "lunch")).await.unwrap();
+        alice.received().await;
+        carol.received().await;
+
+        server.send(poll_of(&alice, " lunch ")).await.unwrap();
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "poll_already_exists");
+
+        server.send(poll_of(&carol, "lunch")).await.unwrap();
+        assert!(carol.received_of("error").await.is_empty());
+        let polls = server
+            .send(RoomPolls {
+                room_name: "other".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(polls.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn only_the_owner_removes_options_without_votes() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "pasta"],
+        )
+        .await;
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        alice.received().await;
+        bob.received().await;
+
+        server
+            .send(remove_option_of(&bob, "lunch", "pasta"))
+            .await
+            .unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+
+        server
+            .send(remove_option_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "poll_option_has_votes");
+
+        server
+            .send(remove_option_of(&alice, "lunch", "pasta"))
+            .await
+            .unwrap();
+        let options = bob.received_of("polloptions").await;
+        assert_eq!(options.len(), 1);
+        assert!(!options[0].to_string().contains("pasta"));
+    }
+
+    #[actix_rt::test]
+    async fn only_the_owner_deletes_a_poll() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(delete_of(&bob, "lunch")).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(alice.received().await.is_empty());
+
+        server.send(delete_of(&alice, "lunch")).await.unwrap();
+        for member in &[&alice, &bob] {
+            let deleted = member.received_of("polldeleted").await;
+            assert_eq!(deleted.len(), 1);
+            assert_eq!(deleted[0]["object"], "lunch");
+        }
+        let polls = server
+            .send(RoomPolls {
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(polls.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn poll_takes_up_to_the_maximum_of_options() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let titles: Vec<String> = (1..=20).map(|n| format!("option {}", n)).collect();
+        let titles: Vec<&str> = titles.iter().map(String::as_str).collect();
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &titles).await;
+        assert!(alice.received_of("error").await.is_empty());
+
+        server
+            .send(option_of(&alice, "lunch", "option 21"))
+            .await
+            .unwrap();
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "poll_options_limit_reached");
+    }
+
+    #[test]
+    fn percentage_of_no_votes_is_zero() {
+        assert_eq!(percentage(0, 0), 0.0);
+        assert_eq!(percentage(0, 3), 0.0);
+        assert_eq!(percentage(1, 3), 33.3);
+        assert_eq!(percentage(2, 3), 66.7);
+        assert_eq!(percentage(3, 3), 100.0);
+    }
+
+    #[test]
+    fn results_of_a_poll_without_votes_are_zero() {
+        let mut poll = Poll {
+            title: "lunch".to_string(),
+            owner_id: 1,
+            owner_name: "alice".to_string(),
+            room_name: "room".to_string(),
+            id: 1,
+            options: Vec::new(),
+            votes: HashMap::new(),
+            weights: HashMap::new(),
+            closed: false,
+            multi: false,
+            anonymous: false,
+            duration_secs: None,
+            auto_close_on_full: false,
+            ack_id: None,
+        };
+        for title in &["pizza", "pasta"] {
+            poll.options.push(PollOption {
+                title: title.to_string(),
+                owner_id: 1,
+                owner_name: "alice".to_string(),
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                ack_id: None,
+            });
+        }
+
+        let results = poll.results();
+        assert_eq!(results.len(), 2);
+        for result in results.values() {
+            assert_eq!(result.count, 0);
+            assert_eq!(result.percent, 0.0);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn closed_poll_is_archived() {
+        let dir = std::env::temp_dir().join(format!("vimeet-archive-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let server = start(config_with(&[(
+            "VIMEET_POLL_ARCHIVE_DIR",
+            dir.to_str().unwrap(),
+        )]));
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "pasta"],
+        )
+        .await;
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+
+        // the archive is written by another thread, it may not be complete yet
+        let mut archives: Vec<Arbitrary> = Vec::new();
+        for _ in 0..50 {
+            archives = fs::read_dir(&dir)
+                .unwrap()
+                .filter_map(|file| fs::read_to_string(file.unwrap().path()).ok())
+                .filter_map(|contents| serde_json::from_str(&contents).ok())
+                .collect();
+            if !archives.is_empty() {
+                break;
+            }
+            delay_for(Duration::from_millis(20)).await;
+        }
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(archives.len(), 1);
+        let archive = &archives[0];
+
+        assert_eq!(archive["room"], "room");
+        assert_eq!(archive["title"], "lunch");
+        assert_eq!(archive["options"], json!(["pizza", "pasta"]));
+        assert_eq!(archive["results"]["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn voter_gets_an_ack_or_a_nack() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        let received = bob.received().await;
+        let acks: Vec<_> = received
+            .iter()
+            .filter(|message| message["type"] == "voteack")
+            .collect();
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0]["pollobject"], "lunch");
+        assert_eq!(acks[0]["polloptionobject"], "pizza");
+        assert!(!received.iter().any(|message| message["type"] == "votenack"));
+        assert!(alice.received_of("voteack").await.is_empty());
+
+        server.send(vote_of(&bob, "lunch", "sushi")).await.unwrap();
+        let received = bob.received().await;
+        assert!(!received.iter().any(|message| message["type"] == "voteack"));
+        let nacks: Vec<_> = received
+            .iter()
+            .filter(|message| message["type"] == "votenack")
+            .collect();
+        assert_eq!(nacks.len(), 1);
+        assert_eq!(nacks[0]["object"], "poll_option_does_not_exist");
+        assert_eq!(nacks[0]["polloptionobject"], "sushi");
+    }
+
+    #[actix_rt::test]
+    async fn hand_queue_is_first_in_first_out() {
+        let server = start(config_with(&[("VIMEET_HAND_COOLDOWN_MS", "0")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        let dave = join(&server, 4, "dave", "room").await;
+
+        for member in &[&carol, &bob, &dave] {
+            server.send(raise_of(member, "hand")).await.unwrap();
+        }
+        assert_eq!(queued(&alice.received().await), ["carol", "bob", "dave"]);
+
+        server.send(lower_of(&bob, "hand")).await.unwrap();
+        assert_eq!(queued(&alice.received().await), ["carol", "dave"]);
+    }
+
+    #[actix_rt::test]
+    async fn lower_all_empties_the_queue() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        server.send(elevate_of(&alice, bob.id)).await.unwrap();
+        server.send(raise_of(&bob, "hand")).await.unwrap();
+        server.send(raise_of(&carol, "hand")).await.unwrap();
+        bob.received().await;
+        carol.received().await;
+
+        // being elevated isn't enough, only the host may lower everyone
+        server
+            .send(LowerAll {
+                sender_id: bob.id,
+                room_name: bob.room.clone(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(carol.received_of("handqueue").await.is_empty());
+
+        server
+            .send(LowerAll {
+                sender_id: alice.id,
+                room_name: alice.room.clone(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(queued(&bob.received().await).is_empty());
+        server
+            .send(HandQueueQuery {
+                sender_id: carol.id,
+                room_name: carol.room.clone(),
+            })
+            .await
+            .unwrap();
+        let queues = carol.received_of("handqueue").await;
+        assert_eq!(queues.len(), 2);
+        assert_eq!(queues[1]["object"], json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn raise_carries_its_reason() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        let mut raise = raise_of(&bob, "hand");
+        raise.reason = Some("question".to_string());
+        server.send(raise).await.unwrap();
+        server.send(raise_of(&carol, "hand")).await.unwrap();
+
+        let queues = alice.received_of("handqueue").await;
+        let queue = &queues.last().unwrap()["object"];
+        assert_eq!(queue[0]["owner_name"], "bob");
+        assert_eq!(queue[0]["reason"], "question");
+        assert_eq!(queue[1]["owner_name"], "carol");
+        assert_eq!(queue[1]["reason"], Arbitrary::Null);
+    }
+
+    #[actix_rt::test]
+    async fn elevating_a_raised_user_lowers_their_hand() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(raise_of(&bob, "hand")).await.unwrap();
+        bob.received().await;
+
+        server.send(elevate_of(&alice, bob.id)).await.unwrap();
+
+        let received = bob.received().await;
+        let elevated: Vec<_> = received
+            .iter()
+            .filter(|message| message["type"] == "elevated")
+            .collect();
+        assert_eq!(elevated.len(), 1);
+        assert_eq!(elevated[0]["object"], bob.id);
+        assert!(queued(&received).is_empty());
+
+        // only elevated users may create polls
+        server.send(poll_of(&bob, "lunch")).await.unwrap();
+        assert!(bob.received_of("error").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn only_the_host_elevates() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        alice.received().await;
+        carol.received().await;
+
+        server.send(elevate_of(&bob, carol.id)).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(carol.received().await.is_empty());
+
+        server.send(elevate_of(&alice, carol.id)).await.unwrap();
+        let elevated = carol.received_of("elevated").await;
+        assert_eq!(elevated.len(), 1);
+        assert_eq!(elevated[0]["object"], carol.id);
+    }
+
+    #[actix_rt::test]
+    async fn receding_a_user_who_is_not_elevated_changes_nothing() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+        bob.received().await;
+
+        server.send(recede_of(&alice, bob.id)).await.unwrap();
+
+        assert!(alice.received().await.is_empty());
+        assert!(bob.received().await.is_empty());
+        server.send(poll_of(&bob, "lunch")).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors[0]["object"], "no_permission");
+    }
+
+    #[actix_rt::test]
+    async fn joiner_gets_a_roster_with_everyone() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        alice.received().await;
+        let bob = join(&server, 2, "bob", "room").await;
+
+        let rosters = bob.received_of("roster").await;
+        assert_eq!(rosters.len(), 1);
+        let names: Vec<_> = rosters[0]["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["user_name"].clone())
+            .collect();
+        assert_eq!(names, [json!("alice"), json!("bob")]);
+        assert_eq!(rosters[0]["host_id"], alice.id);
+
+        let joined = alice.received_of("userjoined").await;
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["object"]["user_name"], "bob");
+    }
+
+    #[actix_rt::test]
+    async fn full_room_refuses_joins() {
+        let server = start(config_with(&[("VIMEET_MAX_ROOM_SIZE", "2")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        join(&server, 2, "bob", "room").await;
+        alice.received().await;
+
+        let refused = server
+            .send(join_of(&probe(), 3, "carol", "room"))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            refused,
+            Err(Rejection {
+                kind: RejectionKind::RoomFull,
+                ..
+            })
+        ));
+        assert!(alice.received().await.is_empty());
+        let rooms = server.send(ListRooms).await.unwrap();
+        assert_eq!(rooms["room"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn room_password_is_checked() {
+        let server = start(config());
+        let owner = probe();
+        let mut join = join_of(&owner, 1, "alice", "secret");
+        join.password = Some("hunter2".to_string());
+        assert!(server.send(join).await.unwrap().is_ok());
+
+        let mut wrong = join_of(&probe(), 2, "bob", "secret");
+        wrong.password = Some("hunter3".to_string());
+        let missing = join_of(&probe(), 3, "carol", "secret");
+        for join in [wrong, missing] {
+            assert!(matches!(
+                server.send(join).await.unwrap(),
+                Err(Rejection {
+                    kind: RejectionKind::AuthFailed,
+                    ..
+                })
+            ));
+        }
+
+        let mut right = join_of(&probe(), 4, "dave", "secret");
+        right.password = Some("hunter2".to_string());
+        assert!(server.send(right).await.unwrap().is_ok());
+
+        // rooms created without a password let anyone in
+        assert!(server
+            .send(join_of(&probe(), 5, "erin", "open"))
+            .await
+            .unwrap()
+            .is_ok());
+        let mut any = join_of(&probe(), 6, "frank", "open");
+        any.password = Some("whatever".to_string());
+        assert!(server.send(any).await.unwrap().is_ok());
+    }
+
+    #[actix_rt::test]
+    async fn only_the_host_kicks() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        alice.received().await;
+        carol.received().await;
+
+        server.send(kick_of(&bob, carol.id)).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(carol.received().await.is_empty());
+
+        server.send(kick_of(&alice, carol.id)).await.unwrap();
+        let kicked = carol.received_of("kicked").await;
+        assert_eq!(kicked.len(), 1);
+        assert_eq!(kicked[0]["object"], "room");
+        let left = bob.received_of("userleft").await;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["object"]["user_id"], carol.id);
+        assert_eq!(left[0]["reason"], "kicked");
+        let rooms = server.send(ListRooms).await.unwrap();
+        assert_eq!(rooms["room"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn host_role_is_handed_over() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        bob.received().await;
+
+        server
+            .send(TransferHost {
+                user_id: carol.id,
+                sender_id: alice.id,
+                room_name: alice.room.clone(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let changed = bob.received_of("hostchanged").await;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0]["object"], carol.id);
+
+        // the longest connected member takes over from a leaving host
+        server
+            .send(Disconnect {
+                id: carol.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+        let changed = bob.received_of("hostchanged").await;
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0]["object"], alice.id);
+    }
+
+    #[actix_rt::test]
+    async fn names_are_unique_per_room() {
+        let server = start(config());
+        join(&server, 1, "alice", "room").await;
+
+        let refused = server
+            .send(join_of(&probe(), 2, " Alice", "room"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            refused,
+            Err(Rejection {
+                kind: RejectionKind::NameTaken,
+                ..
+            })
+        ));
+
+        join(&server, 3, "alice", "other").await;
+    }
+
+    #[actix_rt::test]
+    async fn direct_message_reaches_only_its_target() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        alice.received().await;
+        bob.received().await;
+
+        server
+            .send(Direct {
+                object: json!("psst"),
+                target_id: carol.id,
+                owner_id: alice.id,
+                owner_name: alice.name.clone(),
+                room_name: alice.room.clone(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+
+        let direct = carol.received_of("direct").await;
+        assert_eq!(direct.len(), 1);
+        assert_eq!(direct[0]["object"], "psst");
+        assert_eq!(direct[0]["owner_id"], alice.id);
+        assert!(bob.received().await.is_empty());
+        // the sender gets a copy for their own history
+        assert_eq!(alice.received_of("direct").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn late_joiner_gets_the_history() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        alice.received().await;
+        server.send(instant_of(&alice, "first")).await.unwrap();
+        server.send(instant_of(&alice, "second")).await.unwrap();
+
+        let live: Vec<_> = alice
+            .received_of("instant")
+            .await
+            .iter()
+            .map(|message| message["object"].clone())
+            .collect();
+        assert_eq!(live, [json!("first"), json!("second")]);
+
+        let bob = join(&server, 2, "bob", "room").await;
+        let history = bob.received_of("history").await;
+        assert_eq!(history.len(), 1);
+        let objects: Vec<_> = history[0]["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["object"].clone())
+            .collect();
+        assert_eq!(objects, [json!("first"), json!("second")]);
+    }
+
+    #[actix_rt::test]
+    async fn repeated_typing_state_is_sent_once() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+
+        server.send(typing_of(&bob, true)).await.unwrap();
+        server.send(typing_of(&bob, true)).await.unwrap();
+        server.send(typing_of(&bob, false)).await.unwrap();
+
+        let typing: Vec<_> = alice
+            .received_of("typing")
+            .await
+            .iter()
+            .map(|message| message["object"].clone())
+            .collect();
+        assert_eq!(typing, [json!(true), json!(false)]);
+        assert!(bob.received_of("typing").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn reacting_again_takes_the_reaction_back() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+
+        server.send(react_of(&alice, "lunch", "👍")).await.unwrap();
+        server.send(react_of(&bob, "lunch", "👍")).await.unwrap();
+        server.send(react_of(&bob, "lunch", "🍕")).await.unwrap();
+        assert_eq!(
+            reactions(&alice.received().await),
+            json!({ "👍": 2, "🍕": 1 })
+        );
+
+        server.send(react_of(&bob, "lunch", "👍")).await.unwrap();
+        assert_eq!(
+            reactions(&alice.received().await),
+            json!({ "👍": 1, "🍕": 1 })
+        );
+    }
+
+    #[actix_rt::test]
+    async fn only_the_author_edits_a_message() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(instant_of(&alice, "helo")).await.unwrap();
+        let id = alice.received_of("instant").await[0]["id"]
+            .as_u64()
+            .unwrap() as usize;
+        bob.received().await;
+
+        server.send(edit_of(&bob, id, "bye")).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "no_permission");
+        assert!(alice.received().await.is_empty());
+
+        server.send(edit_of(&alice, id, "hello")).await.unwrap();
+        let edited = bob.received_of("edited").await;
+        assert_eq!(edited.len(), 1);
+        assert_eq!(edited[0]["id"], id);
+        assert_eq!(edited[0]["object"], "hello");
+    }
+
+    #[actix_rt::test]
+    async fn resumed_session_stays_elevated() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let token = token_of(&bob).await;
+        server.send(elevate_of(&alice, bob.id)).await.unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        let probe = probe();
+        let mut join = join_of(&probe, 3, "bob", "room");
+        join.token = Some(token);
+        let id = server.send(join).await.unwrap().ok().unwrap();
+        assert_eq!(id, bob.id);
+
+        let resumed = Member {
+            id,
+            name: "bob".to_string(),
+            room: "room".to_string(),
+            probe,
+        };
+        resumed.received().await;
+        server.send(poll_of(&resumed, "lunch")).await.unwrap();
+        assert!(resumed.received_of("error").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn shutdown_notice_reaches_every_room() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "first").await;
+        let bob = join(&server, 2, "bob", "second").await;
+        let carol = join(&server, 3, "carol", "second").await;
+        for member in &[&alice, &bob, &carol] {
+            member.received().await;
+        }
+
+        let notice =
+            messages::outbound::Message::ServerShutdown(messages::outbound::ServerShutdown {
+                grace_secs: 5,
+            })
+            .to_string();
+        server.send(ShutdownNotice(notice)).await.unwrap();
+        for member in &[&alice, &bob, &carol] {
+            let notices = member.received_of("servershutdown").await;
+            assert_eq!(notices.len(), 1);
+            assert_eq!(notices[0]["grace_secs"], 5);
+        }
+
+        // sessions leaving from now on leave because of the shutdown
+        server
+            .send(Disconnect {
+                id: carol.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+        let left = bob.received_of("userleft").await;
+        assert_eq!(left[0]["reason"], "servershutdown");
+    }
+
+    #[actix_rt::test]
+    async fn vote_ack_echoes_the_ack_id() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+
+        let vote = PollVoteHelper {
+            ack_id: Some(json!("vote-1")),
+            ..vote_of(&alice, "lunch", "pizza")
+        };
+        server.send(vote).await.unwrap();
+        let acks = alice.received_of("voteack").await;
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0]["ack_id"], "vote-1");
+
+        // without an ID there is nothing to echo
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        let acks = alice.received_of("voteack").await;
+        assert_eq!(acks.len(), 1);
+        assert!(acks[0].get("ack_id").is_none());
+    }
+
+    #[actix_rt::test]
+    async fn connection_gauge_goes_up_and_back_down() {
+        // a shard no other test uses, they all share the gauges
+        let shard = 41;
+        let server =
+            WebSocketServer::new(config(), shard, RoomsPerIp::default(), RoomCount::default())
+                .start();
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        assert_eq!(metrics::connections_of(shard), 2);
+
+        for member in &[alice, bob] {
+            server
+                .send(Disconnect {
+                    id: member.id,
+                    reason: LeaveReason::ClientClose,
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(metrics::connections_of(shard), 0);
+    }
+
+    #[actix_rt::test]
+    async fn room_is_removed_once_its_last_member_leaves() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let exists = || {
+            server.send(RoomTranscript {
+                room_name: "room".to_string(),
+            })
+        };
+
+        for (member, remains) in &[(alice, true), (bob, false)] {
+            server
+                .send(Disconnect {
+                    id: member.id,
+                    reason: LeaveReason::ClientClose,
+                })
+                .await
+                .unwrap();
+            assert_eq!(exists().await.unwrap().is_some(), *remains);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn closing_a_poll_frees_a_slot_for_another() {
+        let server = start(config_with(&[("VIMEET_MAX_POLLS_PER_ROOM", "2")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "other").await;
+        for title in &["lunch", "dinner"] {
+            server.send(poll_of(&alice, title)).await.unwrap();
+        }
+        alice.received().await;
+
+        server.send(poll_of(&alice, "breakfast")).await.unwrap();
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "polls_limit_reached");
+
+        // the limit is per room
+        server.send(poll_of(&bob, "breakfast")).await.unwrap();
+        assert!(bob.received_of("error").await.is_empty());
+
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+        alice.received().await;
+        server.send(poll_of(&alice, "breakfast")).await.unwrap();
+        assert!(alice.received_of("error").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn late_joiner_gets_a_snapshot_of_the_polls() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "sushi"],
+        )
+        .await;
+        server
+            .send(vote_of(&alice, "lunch", "sushi"))
+            .await
+            .unwrap();
+
+        let bob = join(&server, 2, "bob", "room").await;
+        let snapshots = bob.received_of("pollsnapshot").await;
+        assert_eq!(snapshots.len(), 1);
+        let polls = snapshots[0]["object"].as_array().unwrap();
+        assert_eq!(polls.len(), 1);
+        assert_eq!(polls[0]["title"], "lunch");
+        assert_eq!(polls[0]["options"], json!(["pizza", "sushi"]));
+        assert_eq!(polls[0]["results"]["sushi"]["count"], 1);
+        assert_eq!(polls[0]["results"]["pizza"]["count"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn switching_rooms_updates_both_rosters() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "first").await;
+        let bob = join(&server, 2, "bob", "first").await;
+        let carol = join(&server, 3, "carol", "second").await;
+        for member in &[&alice, &bob, &carol] {
+            member.received().await;
+        }
+
+        let id = server
+            .send(SwitchRoom {
+                id: alice.id,
+                user_name: alice.name.clone(),
+                room_name: "second".to_string(),
+                password: None,
+            })
+            .await
+            .unwrap()
+            .ok()
+            .unwrap();
+
+        let left = bob.received_of("userleft").await;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["object"]["user_name"], "alice");
+        let joined = carol.received_of("userjoined").await;
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0]["object"]["user_id"], id);
+        let roster = alice.received_of("roster").await;
+        let names: Vec<_> = roster[0]["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|entry| entry["user_name"].clone())
+            .collect();
+        assert_eq!(names, [json!("alice"), json!("carol")]);
+
+        let rooms = server.send(ListRooms).await.unwrap();
+        assert_eq!(rooms["first"], 1);
+        assert_eq!(rooms["second"], 2);
+    }
+
+    /// a session that never reads its mailbox
+    struct Stuck;
+
+    impl Actor for Stuck {
+        type Context = Context<Self>;
+
+        fn started(&mut self, ctx: &mut Context<Self>) {
+            ctx.wait(fut::wrap_future(futures::future::pending()));
+        }
+    }
+
+    impl Handler<Message> for Stuck {
+        type Result = ();
+
+        fn handle(&mut self, _: Message, _: &mut Context<Self>) {}
+    }
+
+    #[actix_rt::test]
+    async fn session_that_cant_keep_up_is_disconnected() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let backed_up = Stuck::create(|ctx| {
+            ctx.set_mailbox_capacity(16);
+            Stuck
+        });
+        let closed = probe();
+        let join = Join {
+            addr: backed_up.recipient(),
+            close: closed.clone().recipient(),
+            ..join_of(&closed, 2, "bob", "room")
+        };
+        let bob = server.send(join).await.unwrap().ok().unwrap();
+        alice.received().await;
+        assert!(closed.send(Take).await.unwrap().is_empty());
+
+        for n in 0..32 {
+            server
+                .send(instant_of(&alice, &format!("message {}", n)))
+                .await
+                .unwrap();
+        }
+
+        let closes = closed.send(Take).await.unwrap();
+        assert_eq!(closes.len(), 1);
+        assert_eq!(closes[0]["type"], "slowconsumer");
+        let left = alice.received_of("userleft").await;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0]["object"]["user_id"], bob);
+        assert_eq!(left[0]["reason"], "slowconsumer");
+    }
+
+    #[actix_rt::test]
+    async fn whitespace_titles_are_refused() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+
+        server.send(poll_of(&alice, "  ")).await.unwrap();
+        server.send(option_of(&alice, "lunch", "\t")).await.unwrap();
+        server.send(vote_of(&alice, "lunch", " ")).await.unwrap();
+
+        let received = alice.received().await;
+        // votes are refused with a nack, like votes for unknown options
+        let refusals: Vec<_> = received
+            .iter()
+            .filter(|message| message["type"] == "error" || message["type"] == "votenack")
+            .map(|message| (message["type"].clone(), message["object"].clone()))
+            .collect();
+        assert_eq!(
+            refusals,
+            [
+                (json!("error"), json!("empty_title")),
+                (json!("error"), json!("empty_title")),
+                (json!("votenack"), json!("empty_title")),
+            ]
+        );
+        for kind in &["pollresults", "polloptions", "voteack"] {
+            assert!(!received.iter().any(|message| message["type"] == *kind));
+        }
+    }
+
+    #[actix_rt::test]
+    async fn poll_closes_once_every_member_who_may_vote_did() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        server
+            .send(Mute {
+                user_id: carol.id,
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let poll = Poll {
+            auto_close_on_full: true,
+            ..poll_of(&alice, "lunch")
+        };
+        create_poll(&server, &alice, poll, &["pizza"]).await;
+        bob.received().await;
+
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        assert!(bob.received_of("pollclose").await.is_empty());
+
+        // the muted carol isn't waited for
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        let closes = bob.received_of("pollclose").await;
+        assert_eq!(closes.len(), 1);
+        assert_eq!(closes[0]["object"], "lunch");
+    }
+
+    #[actix_rt::test]
+    async fn hand_queue_query_matches_the_broadcast() {
+        let server = start(config_with(&[("VIMEET_HAND_COOLDOWN_MS", "0")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(raise_of(&bob, "question")).await.unwrap();
+        server.send(raise_of(&alice, "remark")).await.unwrap();
+        let broadcast = alice.received_of("handqueue").await.pop().unwrap();
+        assert_eq!(broadcast["object"].as_array().unwrap().len(), 2);
+        bob.received().await;
+
+        server
+            .send(HandQueueQuery {
+                sender_id: bob.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        let answers = bob.received_of("handqueue").await;
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0]["object"], broadcast["object"]);
+        // only the asking session is answered
+        assert!(alice.received().await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn repeated_join_keeps_a_single_roster_entry() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        bob.received().await;
+
+        let again = join(&server, alice.id, "alice", "room").await;
+        assert_eq!(again.id, alice.id);
+        assert!(bob.received_of("userjoined").await.is_empty());
+        assert_eq!(server.send(ListRooms).await.unwrap()["room"], 2);
+
+        // the repeated join only took over the recipient
+        let carol = join(&server, 3, "carol", "room").await;
+        let rosters = carol.received_of("roster").await;
+        assert_eq!(rosters[0]["object"].as_array().unwrap().len(), 3);
+        assert_eq!(again.received_of("userjoined").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn rooms_per_ip_limit_creation_but_not_joins() {
+        let server = start(config_with(&[("VIMEET_MAX_ROOMS_PER_IP", "1")]));
+        let ip: IpAddr = "192.0.2.1".parse().unwrap();
+        let join_from = |user_id, room_name: &str, ip| Join {
+            ip,
+            ..join_of(&probe(), user_id, &format!("user{}", user_id), room_name)
+        };
+
+        assert!(server
+            .send(join_from(1, "first", Some(ip)))
+            .await
+            .unwrap()
+            .is_ok());
+        let refused = server.send(join_from(2, "second", Some(ip))).await.unwrap();
+        assert!(matches!(
+            refused,
+            Err(Rejection {
+                kind: RejectionKind::RoomLimit,
+                ..
+            })
+        ));
+
+        // rooms created by others may be joined from anywhere
+        assert!(server
+            .send(join_from(3, "third", None))
+            .await
+            .unwrap()
+            .is_ok());
+        for (user_id, room_name) in &[(4, "first"), (5, "third")] {
+            let joined = server.send(join_from(*user_id, room_name, Some(ip))).await;
+            assert!(joined.unwrap().is_ok(), "{}", room_name);
+        }
+    }
+
+    #[actix_rt::test]
+    async fn added_option_is_broadcast_once_and_duplicates_never() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(poll_of(&alice, "lunch")).await.unwrap();
+        alice.received().await;
+        bob.received().await;
+
+        server
+            .send(option_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        let added = bob.received_of("polloption").await;
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0]["pollobject"], "lunch");
+        assert_eq!(added[0]["polloptionobject"], "pizza");
+        alice.received().await;
+
+        server
+            .send(option_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        assert!(bob.received().await.is_empty());
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors[0]["object"], "poll_option_already_exists");
+    }
+
+    #[actix_rt::test]
+    async fn instant_expires_after_its_lifetime() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+
+        let instant = Instant {
+            ttl_secs: Some(1),
+            ..instant_of(&alice, "starting in 2 minutes")
+        };
+        server.send(instant).await.unwrap();
+        server.send(instant_of(&alice, "welcome")).await.unwrap();
+        let instants = bob.received_of("instant").await;
+        assert_eq!(instants.len(), 2);
+        assert!(bob.received_of("instantexpired").await.is_empty());
+
+        delay_for(Duration::from_millis(1200)).await;
+        let expired = bob.received_of("instantexpired").await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0]["id"], instants[0]["id"]);
+    }
+
+    #[actix_rt::test]
+    async fn rapid_votes_are_coalesced_into_one_results_message() {
+        let server = start(config_with(&[("VIMEET_RESULTS_COALESCE_MS", "200")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "sushi"],
+        )
+        .await;
+        carol.received().await;
+
+        for (member, option) in &[(&alice, "pizza"), (&bob, "sushi"), (&carol, "pizza")] {
+            server.send(vote_of(member, "lunch", option)).await.unwrap();
+        }
+        assert!(carol.received_of("pollresults").await.is_empty());
+
+        delay_for(Duration::from_millis(300)).await;
+        let results = carol.received_of("pollresults").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["object"]["pizza"]["count"], 2);
+        assert_eq!(results[0]["object"]["sushi"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn raises_within_the_cooldown_are_dropped() {
+        let server = start(config_with(&[("VIMEET_HAND_COOLDOWN_MS", "300")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+
+        server.send(raise_of(&bob, "question")).await.unwrap();
+        server.send(raise_of(&bob, "remark")).await.unwrap();
+        server.send(lower_of(&bob, "question")).await.unwrap();
+        let queues = alice.received_of("handqueue").await;
+        assert_eq!(queues.len(), 1);
+        assert_eq!(queues[0]["object"].as_array().unwrap().len(), 1);
+        assert_eq!(queues[0]["object"][0]["object"], "question");
+        // the spammer isn't told either
+        assert!(bob.received_of("error").await.is_empty());
+
+        delay_for(Duration::from_millis(400)).await;
+        server.send(raise_of(&bob, "remark")).await.unwrap();
+        assert_eq!(alice.received_of("handqueue").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn hello_names_the_package_version() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+
+        let hellos = alice.received_of("hello").await;
+        assert_eq!(hellos.len(), 1);
+        assert_eq!(hellos[0]["version"], env!("CARGO_PKG_VERSION"));
+        assert!(hellos[0]["capabilities"]
+            .as_array()
+            .unwrap()
+            .contains(&json!("polls")));
+    }
+
+    #[actix_rt::test]
+    async fn vote_after_reconnecting_is_not_counted_twice() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let token = token_of(&bob).await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "sushi"],
+        )
+        .await;
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        let probe = probe();
+        let rejoin = Join {
+            token: Some(token),
+            ..join_of(&probe, 3, "bob", "room")
+        };
+        let id = server.send(rejoin).await.unwrap().ok().unwrap();
+        let resumed = Member {
+            id,
+            name: "bob".to_string(),
+            room: "room".to_string(),
+            probe,
+        };
+        let own = resumed.received_of("ownvotes").await;
+        assert_eq!(own[0]["object"], json!({ "lunch": ["pizza"] }));
+        alice.received().await;
+
+        server
+            .send(vote_of(&resumed, "lunch", "pizza"))
+            .await
+            .unwrap();
+        // the vote is recognized as the one cast before, nothing changes
+        assert!(alice.received_of("pollresults").await.is_empty());
+        let carol = join(&server, 4, "carol", "room").await;
+        let snapshots = carol.received_of("pollsnapshot").await;
+        assert_eq!(snapshots[0]["object"][0]["results"]["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn options_query_lists_options_in_the_order_they_were_added() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let options = ["sushi", "pizza", "ramen", "curry"];
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &options).await;
+        bob.received().await;
+
+        let query = |poll_title: &str| PollOptionsQuery {
+            sender_id: bob.id,
+            room_name: "room".to_string(),
+            poll_title: poll_title.to_string(),
+            ack_id: None,
+        };
+        server.send(query("lunch")).await.unwrap();
+        let answers = bob.received_of("polloptions").await;
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0]["pollobject"], "lunch");
+        assert_eq!(answers[0]["object"], json!(options));
+
+        server.send(query("dinner")).await.unwrap();
+        assert_eq!(bob.received_of("error").await.len(), 1);
+    }
+
+    #[actix_rt::test]
+    async fn elevation_moves_a_later_raiser_ahead() {
+        let server = start(config_with(&[("VIMEET_HAND_COOLDOWN_MS", "0")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        server.send(raise_of(&bob, "question")).await.unwrap();
+        delay_for(Duration::from_millis(10)).await;
+        server.send(raise_of(&carol, "question")).await.unwrap();
+        let speaking_order = |received: Vec<Arbitrary>| -> Vec<Arbitrary> {
+            let order = received
+                .into_iter()
+                .rfind(|message| message["type"] == "speakingorder")
+                .expect("no speaking order received");
+            order["object"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|entry| entry["user_name"].clone())
+                .collect()
+        };
+        assert_eq!(
+            speaking_order(alice.received().await),
+            [json!("bob"), json!("carol")]
+        );
+
+        // elevating lowers the raised objects, raising again puts carol ahead of bob
+        server.send(elevate_of(&alice, carol.id)).await.unwrap();
+        server.send(raise_of(&carol, "question")).await.unwrap();
+        assert_eq!(
+            speaking_order(alice.received().await),
+            [json!("carol"), json!("bob")]
+        );
+    }
+
+    #[actix_rt::test]
+    async fn first_joiner_is_host_and_later_ones_are_not() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+
+        let hello = alice.received_of("hello").await;
+        assert_eq!(hello[0]["is_host"], true);
+        let received = bob.received().await;
+        let hello = received
+            .iter()
+            .find(|message| message["type"] == "hello")
+            .unwrap();
+        assert_eq!(hello["is_host"], false);
+        let roster = received
+            .iter()
+            .find(|message| message["type"] == "roster")
+            .unwrap();
+        assert_eq!(roster["host_id"], alice.id);
+    }
+
+    #[actix_rt::test]
+    async fn spectator_cant_vote_but_sees_the_results() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let probe = probe();
+        let watch = Join {
+            spectator: true,
+            ..join_of(&probe, 2, "bob", "room")
+        };
+        let id = server.send(watch).await.unwrap().ok().unwrap();
+        let bob = Member {
+            id,
+            name: "bob".to_string(),
+            room: "room".to_string(),
+            probe,
+        };
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        let received = bob.received().await;
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0]["type"], "error");
+        assert_eq!(received[0]["object"], "forbidden");
+
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        let results = bob.received_of("pollresults").await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["object"]["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn muted_user_cant_chat_until_unmuted() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+        server
+            .send(Mute {
+                user_id: bob.id,
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.received_of("muted").await.len(), 1);
+
+        server.send(instant_of(&bob, "hello?")).await.unwrap();
+        assert!(alice.received_of("instant").await.is_empty());
+        assert_eq!(bob.received_of("muted").await.len(), 1);
+        // broadcasts still reach muted users
+        server.send(instant_of(&alice, "hi all")).await.unwrap();
+        assert_eq!(bob.received_of("instant").await.len(), 1);
+        alice.received().await;
+
+        server
+            .send(Unmute {
+                user_id: bob.id,
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(bob.received_of("unmuted").await.len(), 1);
+        server.send(instant_of(&bob, "hello!")).await.unwrap();
+        let instants = alice.received_of("instant").await;
+        assert_eq!(instants.len(), 1);
+        assert_eq!(instants[0]["object"], "hello!");
+    }
+
+    #[actix_rt::test]
+    async fn vote_for_an_unknown_option_leaves_the_tally_untouched() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        alice.received().await;
+        bob.received().await;
+
+        server.send(vote_of(&bob, "lunch", "sushi")).await.unwrap();
+        let nacks = bob.received_of("votenack").await;
+        assert_eq!(nacks.len(), 1);
+        assert_eq!(nacks[0]["object"], "poll_option_does_not_exist");
+        assert!(alice.received_of("pollresults").await.is_empty());
+
+        let carol = join(&server, 3, "carol", "room").await;
+        let snapshots = carol.received_of("pollsnapshot").await;
+        let results = &snapshots[0]["object"][0]["results"];
+        assert_eq!(results.as_object().unwrap().len(), 1);
+        assert_eq!(results["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn only_the_owner_renames_polls_without_votes() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        alice.received().await;
+        bob.received().await;
+        let rename_of = |member: &Member, poll_title: &str, new_title: &str| PollRename {
+            sender_id: member.id,
+            room_name: member.room.clone(),
+            poll_title: poll_title.to_string(),
+            new_title: new_title.to_string(),
+            ack_id: None,
+        };
+
+        server
+            .send(rename_of(&bob, "lunch", "brunch"))
+            .await
+            .unwrap();
+        assert_eq!(bob.received_of("error").await[0]["object"], "no_permission");
+        assert!(alice.received_of("pollrenamed").await.is_empty());
+
+        server
+            .send(rename_of(&alice, "lunch", "brunch"))
+            .await
+            .unwrap();
+        let renamed = bob.received_of("pollrenamed").await;
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0]["pollobject"], "lunch");
+        assert_eq!(renamed[0]["object"], "brunch");
+
+        server.send(vote_of(&bob, "brunch", "pizza")).await.unwrap();
+        server
+            .send(rename_of(&alice, "brunch", "dinner"))
+            .await
+            .unwrap();
+        assert_eq!(
+            alice.received_of("error").await[0]["object"],
+            "poll_has_votes"
+        );
+        assert!(bob.received_of("pollrenamed").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn count_goes_up_with_joins_and_down_with_leaves() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        server
+            .send(Disconnect {
+                id: carol.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        let counts: Vec<_> = alice
+            .received_of("count")
+            .await
+            .into_iter()
+            .map(|count| count["object"].clone())
+            .collect();
+        assert_eq!(counts, [json!(1), json!(2), json!(3), json!(2), json!(1)]);
+    }
+
+    #[actix_rt::test]
+    async fn unreact_lowers_the_count() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(react_of(&alice, "lunch", "👍")).await.unwrap();
+        server.send(react_of(&bob, "lunch", "👍")).await.unwrap();
+        alice.received().await;
+        let unreact_of = |member: &Member| Unreact {
+            target: "lunch".to_string(),
+            emoji: "👍".to_string(),
+            owner_id: member.id,
+            room_name: member.room.clone(),
+            ack_id: None,
+        };
+
+        server.send(unreact_of(&bob)).await.unwrap();
+        assert_eq!(reactions(&alice.received().await), json!({ "👍": 1 }));
+
+        // unlike reacting again, unreacting twice doesn't toggle the reaction back
+        server.send(unreact_of(&bob)).await.unwrap();
+        assert!(alice.received_of("reactions").await.is_empty());
+    }
+
+    #[actix_rt::test]
+    async fn new_rooms_past_the_capacity_are_refused() {
+        let server = start(config_with(&[("VIMEET_MAX_ROOMS", "1")]));
+        join(&server, 1, "alice", "first").await;
+
+        let refused = server
+            .send(join_of(&probe(), 2, "bob", "second"))
+            .await
+            .unwrap();
+        assert!(matches!(
+            refused,
+            Err(Rejection {
+                kind: RejectionKind::Capacity,
+                ..
+            })
+        ));
+        // existing rooms may still be joined
+        join(&server, 3, "carol", "first").await;
+        assert_eq!(server.send(ListRooms).await.unwrap()["first"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn closing_all_polls_closes_the_open_ones_for_good() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        for title in &["lunch", "dinner", "breakfast"] {
+            create_poll(&server, &alice, poll_of(&alice, title), &["pizza"]).await;
+        }
+        server.send(close_of(&alice, "breakfast")).await.unwrap();
+        bob.received().await;
+
+        server
+            .send(CloseAllPolls {
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let closed = bob.received_of("pollsclosed").await;
+        assert_eq!(closed.len(), 1);
+        let mut titles: Vec<_> = closed[0]["object"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|title| title.as_str().unwrap().to_string())
+            .collect();
+        titles.sort();
+        assert_eq!(titles, ["dinner", "lunch"]);
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        assert_eq!(
+            bob.received_of("votenack").await[0]["object"],
+            "poll_closed"
+        );
+        let carol = join(&server, 3, "carol", "room").await;
+        // the snapshot only holds open polls
+        let snapshots = carol.received_of("pollsnapshot").await;
+        assert_eq!(snapshots[0]["object"], json!([]));
+    }
+
+    #[actix_rt::test]
+    async fn roster_entry_has_the_color_given_at_join() {
+        let server = start(config());
+        let probe = probe();
+        let colored = Join {
+            color: Some("#ff8800".to_string()),
+            ..join_of(&probe, 1, "alice", "room")
+        };
+        server.send(colored).await.unwrap().ok().unwrap();
+        let bob = join(&server, 2, "bob", "room").await;
+
+        let rosters = bob.received_of("roster").await;
+        let entries = rosters[0]["object"].as_array().unwrap();
+        assert_eq!(entries[0]["user_name"], "alice");
+        assert_eq!(entries[0]["color"], "#ff8800");
+        // a color is picked for users who didn't give one
+        let picked = entries[1]["color"].as_str().unwrap();
+        assert!(picked.starts_with('#') && picked.len() == 7, "{}", picked);
+    }
+
+    #[actix_rt::test]
+    async fn reply_carries_the_id_of_its_parent() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(instant_of(&alice, "lunch?")).await.unwrap();
+        let parent = bob.received_of("instant").await[0]["id"].clone();
+
+        let reply = Instant {
+            reply_to: parent.as_u64().map(|id| id as usize),
+            ..instant_of(&bob, "sure")
+        };
+        server.send(reply).await.unwrap();
+        let replies = alice.received_of("instant").await;
+        assert_eq!(replies.last().unwrap()["reply_to"], parent);
+
+        // replies to unknown messages aren't threaded
+        let orphan = Instant {
+            reply_to: Some(9999),
+            ..instant_of(&bob, "what?")
+        };
+        server.send(orphan).await.unwrap();
+        let replies = alice.received_of("instant").await;
+        assert!(replies[0]["reply_to"].is_null());
+    }
+
+    #[actix_rt::test]
+    async fn newer_session_supersedes_the_older_one() {
+        let server = start(config_with(&[
+            ("VIMEET_SUPERSEDE_DUPLICATES", "true"),
+            ("VIMEET_HAND_COOLDOWN_MS", "0"),
+        ]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(elevate_of(&alice, bob.id)).await.unwrap();
+        server.send(raise_of(&bob, "question")).await.unwrap();
+        bob.received().await;
+
+        let newer = join(&server, 3, "bob", "room").await;
+        let closes: Vec<_> = bob
+            .received()
+            .await
+            .into_iter()
+            .filter(|message| message["type"] == "superseded")
+            .collect();
+        assert_eq!(closes.len(), 1);
+
+        // the raised hand and the elevation are taken over
+        server
+            .send(HandQueueQuery {
+                sender_id: newer.id,
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(queued(&newer.received().await), ["bob"]);
+        server.send(poll_of(&newer, "lunch")).await.unwrap();
+        assert!(newer.received_of("error").await.is_empty());
+        assert_eq!(server.send(ListRooms).await.unwrap()["room"], 2);
+    }
+
+    #[actix_rt::test]
+    async fn messages_up_to_the_limit_are_sent_and_longer_ones_refused() {
+        let server = start(config_with(&[("VIMEET_MAX_CHAT_LEN", "5")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        alice.received().await;
+
+        // characters are counted, not bytes
+        server.send(instant_of(&alice, "ääääà")).await.unwrap();
+        let instants = bob.received_of("instant").await;
+        assert_eq!(instants.len(), 1);
+        assert_eq!(instants[0]["object"], "ääääà");
+        assert!(alice.received_of("error").await.is_empty());
+
+        server.send(instant_of(&alice, "hello!")).await.unwrap();
+        assert!(bob.received_of("instant").await.is_empty());
+        let errors = alice.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "message_too_long");
+    }
+
+    #[actix_rt::test]
+    async fn closed_poll_is_posted_to_the_webhook_once() {
+        let posted = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let posted = posted.clone();
+            actix_web::test::start(move || {
+                let posted = posted.clone();
+                actix_web::App::new().route(
+                    "/hook",
+                    actix_web::web::post().to(move |body: actix_web::web::Json<Arbitrary>| {
+                        posted.lock().unwrap().push(body.into_inner());
+                        actix_web::HttpResponse::Ok().finish()
+                    }),
+                )
+            })
+        };
+        let url = hook.url("/hook");
+        let server = start(config_with(&[("VIMEET_POLL_WEBHOOK_URL", url.as_str())]));
+        let alice = join(&server, 1, "alice", "room").await;
+        create_poll(
+            &server,
+            &alice,
+            poll_of(&alice, "lunch"),
+            &["pizza", "sushi"],
+        )
+        .await;
+        server
+            .send(vote_of(&alice, "lunch", "sushi"))
+            .await
+            .unwrap();
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+
+        for _ in 0..50 {
+            if !posted.lock().unwrap().is_empty() {
+                break;
+            }
+            delay_for(Duration::from_millis(20)).await;
+        }
+        // no further attempts follow a successful one
+        delay_for(Duration::from_millis(200)).await;
+        let posted = posted.lock().unwrap();
+        assert_eq!(posted.len(), 1);
+        assert_eq!(posted[0]["room"], "room");
+        assert_eq!(posted[0]["title"], "lunch");
+        assert_eq!(posted[0]["options"], json!(["pizza", "sushi"]));
+        assert_eq!(posted[0]["results"]["sushi"]["count"], 1);
+        assert_eq!(posted[0]["results"]["pizza"]["count"], 0);
+    }
+
+    #[actix_rt::test]
+    async fn host_may_restrict_poll_creation_to_themselves() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(elevate_of(&alice, bob.id)).await.unwrap();
+        bob.received().await;
+
+        // by default every elevated user creates polls
+        server.send(poll_of(&bob, "lunch")).await.unwrap();
+        assert!(bob.received_of("error").await.is_empty());
+
+        server
+            .send(SetRoomOption {
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                option: "host_only_polls".to_string(),
+                value: true,
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let options = bob.received_of("roomoptions").await;
+        assert_eq!(options[0]["object"]["host_only_polls"], true);
+
+        server.send(poll_of(&bob, "dinner")).await.unwrap();
+        let errors = bob.received_of("error").await;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0]["object"], "forbidden");
+        alice.received().await;
+        server.send(poll_of(&alice, "dinner")).await.unwrap();
+        assert!(alice.received_of("error").await.is_empty());
+
+        // joiners learn about the restriction
+        let carol = join(&server, 3, "carol", "room").await;
+        let options = carol.received_of("roomoptions").await;
+        assert_eq!(options[0]["object"]["host_only_polls"], true);
+    }
+
+    #[actix_rt::test]
+    async fn transcript_lists_room_events_in_order() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        server.send(instant_of(&alice, "lunch?")).await.unwrap();
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        server
+            .send(Direct {
+                object: json!("psst"),
+                target_id: alice.id,
+                owner_id: bob.id,
+                owner_name: bob.name.clone(),
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        let transcript = server
+            .send(RoomTranscript {
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let events: Vec<_> = transcript
+            .iter()
+            .map(|entry| (entry.event, entry.user_name.as_deref()))
+            .collect();
+        // direct messages are private, they're left out
+        assert_eq!(
+            events,
+            [
+                ("join", Some("alice")),
+                ("join", Some("bob")),
+                ("instant", Some("alice")),
+                ("poll", Some("alice")),
+                ("polloption", Some("alice")),
+                ("vote", Some("bob")),
+                ("pollclosed", None),
+                ("leave", Some("bob")),
+            ]
+        );
+        assert!(transcript.windows(2).all(|pair| pair[0].at <= pair[1].at));
+    }
+
+    #[actix_rt::test]
+    async fn transcript_drops_the_oldest_events_once_full() {
+        let server = start(config_with(&[("VIMEET_TRANSCRIPT_MAX", "2")]));
+        let alice = join(&server, 1, "alice", "room").await;
+        for text in &["one", "two", "three"] {
+            server.send(instant_of(&alice, text)).await.unwrap();
+        }
+
+        let transcript = server
+            .send(RoomTranscript {
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let objects: Vec<_> = transcript
+            .iter()
+            .map(|entry| entry.object.clone())
+            .collect();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(transcript[1].event, "instant");
+        assert!(objects[1].to_string().contains("three"));
+    }
+
+    #[actix_rt::test]
+    async fn weighted_votes_count_with_their_weight() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let carol = join(&server, 3, "carol", "room").await;
+        let mut poll = poll_of(&alice, "lunch");
+        poll.anonymous = true;
+        create_poll(&server, &alice, poll, &["pizza", "pasta"]).await;
+        server
+            .send(SetWeight {
+                user_id: bob.id,
+                weight: 3,
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        let changes = bob.received_of("weightchanged").await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0]["object"], 3);
+        assert_eq!(alice.received_of("weightchanged").await.len(), 1);
+        // only bob and the host are told about the weight
+        assert!(carol.received_of("weightchanged").await.is_empty());
+
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        server
+            .send(vote_of(&carol, "lunch", "pasta"))
+            .await
+            .unwrap();
+
+        let results = carol.received_of("pollresults").await;
+        let last = &results.last().unwrap()["object"];
+        assert_eq!(last["pizza"]["count"], 3);
+        assert_eq!(last["pasta"]["count"], 1);
+        for message in carol.received().await.iter().chain(&results) {
+            assert!(!message.to_string().contains("bob"));
+            assert!(message.get("weight").is_none());
+        }
+    }
+
+    #[actix_rt::test]
+    async fn messages_for_a_reclaimed_room_do_not_bring_it_back() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        server
+            .send(Disconnect {
+                id: alice.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        // messages alice queued before leaving arrive after the room is gone
+        server.send(lower_of(&alice, "hand")).await.unwrap();
+        server
+            .send(LowerAll {
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        server.send(poll_of(&alice, "lunch")).await.unwrap();
+        server
+            .send(option_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        server
+            .send(PollOptionRemove {
+                title: "pizza".to_string(),
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+        server
+            .send(vote_of(&alice, "lunch", "pizza"))
+            .await
+            .unwrap();
+        server.send(close_of(&alice, "lunch")).await.unwrap();
+        server
+            .send(PollDelete {
+                sender_id: alice.id,
+                room_name: "room".to_string(),
+                poll_title: "lunch".to_string(),
+                ack_id: None,
+            })
+            .await
+            .unwrap();
+
+        let transcript = server
+            .send(RoomTranscript {
+                room_name: "room".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(transcript.is_none());
+        assert!(server.send(ListRooms).await.unwrap().is_empty());
+    }
+
+    /// run with `cargo +nightly bench --features bench`
+    #[cfg(feature = "bench")]
+    mod benches {
+        extern crate test;
+
+        use super::*;
+        use test::Bencher;
+
+        const MEMBERS: usize = 100;
+
+        /// a session that drops everything it's sent
+        struct Sink;
+
+        impl Actor for Sink {
+            type Context = Context<Self>;
+        }
+
+        impl Handler<Message> for Sink {
+            type Result = ();
+
+            fn handle(&mut self, _: Message, _: &mut Context<Self>) {}
+        }
+
+        impl Handler<Close> for Sink {
+            type Result = ();
+
+            fn handle(&mut self, _: Close, _: &mut Context<Self>) {}
+        }
+
+        /// a room of `MEMBERS` sinks with an open poll, the first one is its host
+        async fn room_with_poll(config: Config) -> (Addr<WebSocketServer>, Member) {
+            let server = start(config);
+            for user_id in 1..=MEMBERS {
+                let sink = Sink::create(|ctx| {
+                    ctx.set_mailbox_capacity(1024);
+                    Sink
+                });
+                let probe = probe();
+                let join = Join {
+                    addr: sink.clone().recipient(),
+                    close: sink.recipient(),
+                    ..join_of(&probe, user_id, &format!("user{}", user_id), "room")
+                };
+                server.send(join).await.unwrap().ok().unwrap();
+            }
+            // only used to address messages, the sink of user 1 receives them
+            let host = Member {
+                id: 1,
+                name: "user1".to_string(),
+                room: "room".to_string(),
+                probe: probe(),
+            };
+            create_poll(&server, &host, poll_of(&host, "lunch"), &["pizza", "pasta"]).await;
+            (server, host)
+        }
+
+        fn bench_votes(b: &mut Bencher, config: Config) {
+            let mut system = actix_rt::System::new("bench");
+            let (server, host) = system.block_on(room_with_poll(config));
+            // switching the vote every time, so each one changes the results
+            let mut options = ["pizza", "pasta"].iter().cycle();
+            b.iter(|| {
+                let vote = vote_of(&host, "lunch", options.next().unwrap());
+                system.block_on(server.send(vote)).unwrap();
+            });
+        }
+
+        #[bench]
+        fn instant_to_a_room(b: &mut Bencher) {
+            let mut system = actix_rt::System::new("bench");
+            let (server, host) = system.block_on(room_with_poll(config()));
+            b.iter(|| {
+                system
+                    .block_on(server.send(instant_of(&host, "hi")))
+                    .unwrap();
+            });
+        }
+
+        #[bench]
+        fn vote_in_a_room(b: &mut Bencher) {
+            bench_votes(b, config());
+        }
+
+        #[bench]
+        fn vote_in_a_room_with_coalesced_results(b: &mut Bencher) {
+            bench_votes(b, config_with(&[("VIMEET_RESULTS_COALESCE_MS", "50")]));
+        }
+    }
+
+    fn weight_of(host: &Member, user_id: usize, weight: usize) -> SetWeight {
+        SetWeight {
+            user_id,
+            weight,
+            sender_id: host.id,
+            room_name: host.room.clone(),
+            ack_id: None,
+        }
+    }
+
+    #[actix_rt::test]
+    async fn weights_of_leaving_voters_are_dropped() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        server.send(weight_of(&alice, bob.id, 3)).await.unwrap();
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+        alice.received().await;
+
+        // a later session with the same id votes with the default weight
+        let rejoined = join(&server, 2, "bob", "room").await;
+        server
+            .send(vote_of(&rejoined, "lunch", "pizza"))
+            .await
+            .unwrap();
+        let results = alice.received_of("pollresults").await;
+        assert_eq!(results.last().unwrap()["object"]["pizza"]["count"], 1);
+    }
+
+    #[actix_rt::test]
+    async fn resumed_votes_keep_their_weight() {
+        let server = start(config());
+        let alice = join(&server, 1, "alice", "room").await;
+        let bob = join(&server, 2, "bob", "room").await;
+        let token = token_of(&bob).await;
+        create_poll(&server, &alice, poll_of(&alice, "lunch"), &["pizza"]).await;
+        server.send(weight_of(&alice, bob.id, 3)).await.unwrap();
+        server.send(vote_of(&bob, "lunch", "pizza")).await.unwrap();
+        server
+            .send(Disconnect {
+                id: bob.id,
+                reason: LeaveReason::ClientClose,
+            })
+            .await
+            .unwrap();
+
+        let probe = probe();
+        let mut rejoin = join_of(&probe, 3, "bob", "room");
+        rejoin.token = Some(token);
+        server.send(rejoin).await.unwrap().ok().unwrap();
+        alice.received().await;
+
+        // carol's snapshot shows the tally as it is now
+        let carol = join(&server, 4, "carol", "room").await;
+        let snapshots = carol.received_of("pollsnapshot").await;
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0]["object"][0]["results"]["pizza"]["count"], 3);
     }
 }
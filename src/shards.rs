@@ -0,0 +1,156 @@
+//! Rooms spread across several web socket server actors, so independent rooms are handled in
+//! parallel.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use actix::*;
+
+use crate::config::Config;
+use crate::server::{RoomCount, RoomsPerIp, WebSocketServer};
+
+/// Web socket server actors, every room belongs to exactly one of them
+#[derive(Clone)]
+pub struct Shards {
+    servers: Vec<Addr<WebSocketServer>>,
+}
+
+impl Shards {
+    /// Start `config.shards` web socket server actors
+    ///
+    /// The first one runs on the current arbiter, like a single server always did, every further
+    /// one gets an arbiter (and thread) of its own.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - the settings the servers enforce
+    pub fn start(config: &Config) -> Shards {
+        // rooms created by a client count against its limit whichever server hosts them
+        let rooms_per_ip = RoomsPerIp::default();
+        // as does every room against the global limit
+        let room_count = RoomCount::default();
+        let servers = (0..config.shards)
+            .map(|shard| {
+                let config = config.clone();
+                let rooms_per_ip = rooms_per_ip.clone();
+                let room_count = room_count.clone();
+                if shard == 0 {
+                    WebSocketServer::new(config, shard, rooms_per_ip, room_count).start()
+                } else {
+                    WebSocketServer::start_in_arbiter(&Arbiter::new(), move |_| {
+                        WebSocketServer::new(config, shard, rooms_per_ip, room_count)
+                    })
+                }
+            })
+            .collect();
+        Shards { servers }
+    }
+
+    /// The server responsible for a room
+    ///
+    /// # Arguments
+    ///
+    /// * `room_name` - a string slice with the name of the room
+    pub fn for_room(&self, room_name: &str) -> &Addr<WebSocketServer> {
+        let mut hasher = DefaultHasher::new();
+        room_name.hash(&mut hasher);
+        &self.servers[hasher.finish() as usize % self.servers.len()]
+    }
+
+    /// All servers, for requests spanning every room
+    pub fn all(&self) -> &[Addr<WebSocketServer>] {
+        &self.servers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::{Close, Join, ListRooms, Message};
+
+    /// a session that drops everything it's sent
+    struct Session;
+
+    impl Actor for Session {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for Session {
+        type Result = ();
+
+        fn handle(&mut self, _: Message, _: &mut Context<Self>) {}
+    }
+
+    impl Handler<Close> for Session {
+        type Result = ();
+
+        fn handle(&mut self, _: Close, _: &mut Context<Self>) {}
+    }
+
+    fn shards_of(shards: &str) -> Shards {
+        let config = Config::from_vars(|key| match key {
+            "VIMEET_SHARDS" => Some(shards.into()),
+            _ => None,
+        })
+        .unwrap();
+        Shards::start(&config)
+    }
+
+    async fn join(shards: &Shards, user_id: usize, room_name: &str) {
+        let session = Session.start();
+        shards
+            .for_room(room_name)
+            .send(Join {
+                addr: session.clone().recipient(),
+                close: session.recipient(),
+                user_id,
+                user_name: format!("user{}", user_id),
+                room_name: room_name.to_string(),
+                password: None,
+                token: None,
+                ip: None,
+                spectator: false,
+                color: None,
+                avatar: None,
+            })
+            .await
+            .unwrap()
+            .ok()
+            .unwrap();
+    }
+
+    #[actix_rt::test]
+    async fn rooms_on_different_shards_are_independent() {
+        let shards = shards_of("2");
+        assert_eq!(shards.all().len(), 2);
+        let first = "room0";
+        let second = (1..)
+            .map(|n| format!("room{}", n))
+            .find(|room| shards.for_room(room) != shards.for_room(first))
+            .unwrap();
+        join(&shards, 1, first).await;
+        join(&shards, 2, &second).await;
+        join(&shards, 3, &second).await;
+
+        let rooms = shards.for_room(first).send(ListRooms).await.unwrap();
+        assert_eq!(
+            rooms.into_iter().collect::<Vec<_>>(),
+            [(first.to_string(), 1)]
+        );
+        let rooms = shards.for_room(&second).send(ListRooms).await.unwrap();
+        assert_eq!(rooms.into_iter().collect::<Vec<_>>(), [(second, 2)]);
+    }
+
+    #[actix_rt::test]
+    async fn single_shard_hosts_every_room() {
+        let shards = shards_of("1");
+        assert_eq!(shards.all().len(), 1);
+        join(&shards, 1, "first").await;
+        join(&shards, 2, "second").await;
+
+        let rooms = shards.all()[0].send(ListRooms).await.unwrap();
+        assert_eq!(rooms.len(), 2);
+        assert_eq!(rooms["first"], 1);
+        assert_eq!(rooms["second"], 1);
+    }
+}